@@ -0,0 +1,173 @@
+//! Tool arguments injected by the bridge
+//!
+//! `tools.defaults` supplies arguments a call leaves out, and `tools.pinned` sets arguments
+//! regardless of what the caller passed. Both are keyed by the original tool name,
+//! `<upstream>__<tool>` when there are several upstreams. The schemas served in `tools/list`
+//! are rewritten to match: defaulted arguments become optional, and pinned arguments are
+//! removed so the model does not try to set them.
+
+use crate::settings::ToolSettings;
+use rmcp::model::{JsonObject, Tool};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolArguments {
+    defaults: HashMap<String, JsonObject>,
+    pinned: HashMap<String, JsonObject>,
+}
+
+impl ToolArguments {
+    pub fn new(settings: &ToolSettings) -> Self {
+        Self {
+            defaults: settings.defaults.clone(),
+            pinned: settings.pinned.clone(),
+        }
+    }
+
+    /// Fill in missing defaults and overwrite pinned arguments
+    pub fn apply(&self, tool: &str, arguments: &mut Option<JsonObject>) {
+        let defaults = self.defaults.get(tool);
+        let pinned = self.pinned.get(tool);
+        if defaults.is_none() && pinned.is_none() {
+            return;
+        }
+
+        let arguments = arguments.get_or_insert_with(JsonObject::new);
+        for (name, value) in defaults.into_iter().flatten() {
+            if arguments.get(name).is_none_or(Value::is_null) {
+                arguments.insert(name.clone(), value.clone());
+            }
+        }
+        for (name, value) in pinned.into_iter().flatten() {
+            arguments.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// Rewrite the schema of the tool exposed as `name` (before any renaming)
+    pub fn rewrite_schema(&self, name: &str, tool: &mut Tool) {
+        let defaults = self.defaults.get(name);
+        let pinned = self.pinned.get(name);
+        if defaults.is_none() && pinned.is_none() {
+            return;
+        }
+
+        let mut schema = tool.input_schema.as_ref().clone();
+        for (name, value) in defaults.into_iter().flatten() {
+            if let Some(property) = property_mut(&mut schema, name) {
+                property.insert("default".to_string(), value.clone());
+            }
+            make_optional(&mut schema, name, &format!("defaults to {value}"));
+        }
+        for name in pinned.into_iter().flatten().map(|(name, _)| name) {
+            remove_argument(&mut schema, name);
+        }
+        tool.input_schema = Arc::new(schema);
+    }
+}
+
+/// The schema of one of the tool's arguments
+pub fn property_mut<'a>(schema: &'a mut JsonObject, argument: &str) -> Option<&'a mut JsonObject> {
+    schema
+        .get_mut("properties")
+        .and_then(|properties| properties.get_mut(argument))
+        .and_then(Value::as_object_mut)
+}
+
+/// Drop `argument` from `required` and note where its value comes from in its description
+pub fn make_optional(schema: &mut JsonObject, argument: &str, note: &str) {
+    if let Some(Value::Array(required)) = schema.get_mut("required") {
+        required.retain(|name| name != argument);
+    }
+    if let Some(property) = property_mut(schema, argument) {
+        let description = property
+            .get("description")
+            .and_then(Value::as_str)
+            .map_or_else(String::new, |d| format!("{d} "));
+        property.insert(
+            "description".to_string(),
+            Value::String(format!("{description}({note})")),
+        );
+    }
+}
+
+/// Remove `argument` from the schema entirely
+pub fn remove_argument(schema: &mut JsonObject, argument: &str) {
+    if let Some(Value::Array(required)) = schema.get_mut("required") {
+        required.retain(|name| name != argument);
+    }
+    if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+        properties.remove(argument);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn arguments() -> ToolArguments {
+        let settings: ToolSettings = serde_json::from_value(json!({
+            "defaults": {"ask_question": {"repoName": "our-org/platform"}},
+            "pinned": {"read_wiki_contents": {"repoName": "our-org/docs"}}
+        }))
+        .unwrap();
+        ToolArguments::new(&settings)
+    }
+
+    #[test]
+    fn test_defaults_fill_gaps_and_pins_override() {
+        let arguments = arguments();
+
+        let mut call = json!({"question": "How are deploys done?"})
+            .as_object()
+            .cloned();
+        arguments.apply("ask_question", &mut call);
+        assert_eq!(call.unwrap()["repoName"], "our-org/platform");
+
+        let mut call = json!({"repoName": "a/b"}).as_object().cloned();
+        arguments.apply("ask_question", &mut call);
+        assert_eq!(call.unwrap()["repoName"], "a/b");
+
+        let mut call = json!({"repoName": "a/b"}).as_object().cloned();
+        arguments.apply("read_wiki_contents", &mut call);
+        assert_eq!(call.unwrap()["repoName"], "our-org/docs");
+
+        let mut call = None;
+        arguments.apply("read_wiki_structure", &mut call);
+        assert!(call.is_none());
+    }
+
+    #[test]
+    fn test_schemas_are_rewritten() {
+        let arguments = arguments();
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "repoName": {"type": "string", "description": "owner/repo"},
+                "question": {"type": "string"}
+            },
+            "required": ["repoName", "question"]
+        });
+
+        let mut tool = Tool::new("ask_question", "Ask", schema.as_object().cloned().unwrap());
+        arguments.rewrite_schema("ask_question", &mut tool);
+        assert_eq!(tool.input_schema["required"], json!(["question"]));
+        let repo_name = &tool.input_schema["properties"]["repoName"];
+        assert_eq!(repo_name["default"], "our-org/platform");
+        assert_eq!(
+            repo_name["description"],
+            "owner/repo (defaults to \"our-org/platform\")"
+        );
+
+        let mut tool = Tool::new(
+            "read_wiki_contents",
+            "Read",
+            schema.as_object().cloned().unwrap(),
+        );
+        arguments.rewrite_schema("read_wiki_contents", &mut tool);
+        assert_eq!(tool.input_schema["required"], json!(["question"]));
+        assert!(tool.input_schema["properties"].get("repoName").is_none());
+    }
+}
@@ -0,0 +1,277 @@
+//! Per-upstream circuit breakers
+//!
+//! After `failure_threshold` consecutive failures to reach an upstream, its circuit opens and
+//! requests to it fail at once with [`Unavailable`] instead of each waiting out the outage
+//! (tool calls are still answered from the response cache when possible). Once `open_secs`
+//! have passed the circuit half-opens: the next request is let through as a probe, closing the
+//! circuit when it succeeds and opening it again when it fails.
+//!
+//! Only failures to reach the server count; errors the server returns mean it is up.
+
+use crate::coalesce::send_error;
+use crate::metrics::Metrics;
+use crate::settings::CircuitBreakerSettings;
+use rmcp::service::ServiceError;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Circuit state, as reported in metrics and by `doctor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    HalfOpen,
+    Open,
+}
+
+impl CircuitState {
+    /// Value of the `deepwiki_bridge_circuit_state` gauge
+    pub const fn gauge(self) -> u8 {
+        match self {
+            Self::Closed => 0,
+            Self::HalfOpen => 1,
+            Self::Open => 2,
+        }
+    }
+
+    pub const fn from_gauge(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Closed),
+            1 => Some(Self::HalfOpen),
+            2 => Some(Self::Open),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::HalfOpen => "half-open",
+            Self::Open => "open",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe is in flight; another is let through at `expires`
+    HalfOpen {
+        expires: Instant,
+    },
+}
+
+/// Error for requests refused while a circuit is open
+#[derive(Debug)]
+pub struct Unavailable {
+    pub upstream: String,
+    pub retry_in: Duration,
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Upstream {} is unavailable after repeated failures; the bridge will try it again \
+             in {}s",
+            self.upstream,
+            self.retry_in.as_secs().max(1)
+        )
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// The [`Unavailable`] error behind a failed request, if the circuit refused it
+pub fn unavailable(error: &ServiceError) -> Option<&Unavailable> {
+    send_error(error)?.downcast_ref()
+}
+
+pub struct CircuitBreaker {
+    upstream: String,
+    /// 0 disables the breaker
+    threshold: u32,
+    open_for: Duration,
+    state: Mutex<State>,
+    metrics: Arc<Metrics>,
+}
+
+impl CircuitBreaker {
+    pub fn new(upstream: &str, settings: &CircuitBreakerSettings, metrics: Arc<Metrics>) -> Self {
+        metrics.record_circuit_state(upstream, CircuitState::Closed);
+        Self {
+            upstream: upstream.to_string(),
+            threshold: settings.failure_threshold,
+            open_for: Duration::from_secs(settings.open_secs),
+            state: Mutex::new(State::Closed { failures: 0 }),
+            metrics,
+        }
+    }
+
+    #[cfg(test)]
+    fn state(&self) -> CircuitState {
+        match self.state.lock().map(|state| *state) {
+            Ok(State::Open { .. }) => CircuitState::Open,
+            Ok(State::HalfOpen { .. }) => CircuitState::HalfOpen,
+            _ => CircuitState::Closed,
+        }
+    }
+
+    /// Let a request through, or refuse it while the circuit is open
+    ///
+    /// A probe that never reports back (for example because it was cancelled) is replaced by
+    /// another after `open_secs`.
+    pub fn admit(&self) -> Result<(), Unavailable> {
+        let Ok(mut state) = self.state.lock() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let refused_until = match *state {
+            State::Closed { .. } => return Ok(()),
+            State::Open { until } | State::HalfOpen { expires: until } if now >= until => {
+                let probing = matches!(*state, State::Open { .. });
+                *state = State::HalfOpen {
+                    expires: now + self.open_for,
+                };
+                drop(state);
+                if probing {
+                    info!("Probing whether upstream {} has recovered", self.upstream);
+                    self.metrics
+                        .record_circuit_state(&self.upstream, CircuitState::HalfOpen);
+                }
+                return Ok(());
+            }
+            State::Open { until } | State::HalfOpen { expires: until } => until,
+        };
+        Err(Unavailable {
+            upstream: self.upstream.clone(),
+            retry_in: refused_until - now,
+        })
+    }
+
+    /// Note a request that reached the server
+    pub fn succeed(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let was_closed = matches!(*state, State::Closed { .. });
+        *state = State::Closed { failures: 0 };
+        drop(state);
+        if !was_closed {
+            info!("Upstream {} has recovered", self.upstream);
+            self.metrics
+                .record_circuit_state(&self.upstream, CircuitState::Closed);
+        }
+    }
+
+    /// Note a failure to reach the server
+    pub fn fail(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::HalfOpen { .. } => self.threshold,
+            State::Open { .. } => return,
+        };
+        if failures < self.threshold {
+            *state = State::Closed { failures };
+            return;
+        }
+        *state = State::Open {
+            until: Instant::now() + self.open_for,
+        };
+        drop(state);
+        warn!(
+            "Upstream {} failed {} times in a row; failing fast for {}s",
+            self.upstream,
+            failures,
+            self.open_for.as_secs()
+        );
+        self.metrics
+            .record_circuit_state(&self.upstream, CircuitState::Open);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_secs: u64) -> CircuitBreaker {
+        let settings = CircuitBreakerSettings {
+            failure_threshold: 2,
+            open_secs,
+        };
+        CircuitBreaker::new("deepwiki", &settings, Arc::default())
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker(30);
+        breaker.fail();
+        breaker.succeed();
+        breaker.fail();
+        assert!(breaker.admit().is_ok());
+        breaker.fail();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let error = breaker.admit().unwrap_err();
+        assert!(error.retry_in <= Duration::from_secs(30));
+        assert!(error
+            .to_string()
+            .contains("Upstream deepwiki is unavailable"));
+        let error = ServiceError::TransportSend(Box::new(error));
+        assert!(unavailable(&error).is_some());
+        assert!(unavailable(&ServiceError::TransportClosed).is_none());
+    }
+
+    #[test]
+    fn test_half_opens_for_a_single_probe() {
+        let breaker = breaker(0);
+        breaker.fail();
+        breaker.fail();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        assert!(breaker.admit().is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // A failed probe opens the circuit again
+        breaker.fail();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        assert!(breaker.admit().is_ok());
+        breaker.succeed();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_refuses_while_a_probe_is_pending() {
+        let breaker = breaker(30);
+        *breaker.state.lock().unwrap() = State::Open {
+            until: Instant::now(),
+        };
+        assert!(breaker.admit().is_ok());
+        assert!(breaker.admit().is_err());
+    }
+
+    #[test]
+    fn test_zero_threshold_disables_the_breaker() {
+        let settings = CircuitBreakerSettings {
+            failure_threshold: 0,
+            open_secs: 30,
+        };
+        let breaker = CircuitBreaker::new("deepwiki", &settings, Arc::default());
+        for _ in 0..10 {
+            breaker.fail();
+        }
+        assert!(breaker.admit().is_ok());
+    }
+}
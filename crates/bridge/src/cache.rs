@@ -0,0 +1,516 @@
+//! On-disk response cache for idempotent tool calls
+//!
+//! Entries are content-addressed by a SHA-256 of the endpoint, tool name and canonicalized
+//! arguments, and stored as one JSON file each under the cache directory. Freshness is
+//! evaluated at read time against the per-tool lifetime from [`CacheSettings`], so changing a
+//! lifetime applies to existing entries too.
+//!
+//! In offline mode every stored entry is served regardless of age, marked as stale with the
+//! time it was fetched. The tool list is kept alongside so `tools/list` works offline too.
+
+use crate::settings::{CacheMode, CacheSettings};
+use anyhow::{anyhow, Result};
+use rmcp::model::{CallToolResult, Content, Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A cached tool result together with the request that produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub endpoint: String,
+    pub tool: String,
+    pub arguments: Value,
+    /// Seconds since the Unix epoch when the result was fetched upstream
+    pub fetched_at: u64,
+    pub result: CallToolResult,
+}
+
+impl CacheEntry {
+    fn age(&self, now: u64) -> Duration {
+        Duration::from_secs(now.saturating_sub(self.fetched_at))
+    }
+
+    /// The cached result prefixed with a notice saying when it was fetched
+    pub fn into_stale_result(self) -> CallToolResult {
+        let notice = format!(
+            "[Offline] Served from the local cache; fetched {} ({} ago) and may be out of date.",
+            format_timestamp(self.fetched_at),
+            format_age(self.age(unix_now()))
+        );
+        let mut result = self.result;
+        result.content.insert(0, Content::text(notice));
+        result
+    }
+}
+
+/// The last tool list received from an endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolListEntry {
+    pub endpoint: String,
+    pub fetched_at: u64,
+    pub tools: Vec<Tool>,
+}
+
+/// Summary of the cache directory contents
+#[derive(Debug, Default, Serialize)]
+pub struct CacheStats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub expired: usize,
+    pub total_bytes: u64,
+    pub by_tool: BTreeMap<String, usize>,
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    mode: CacheMode,
+    ttl: HashMap<String, u64>,
+}
+
+impl ResponseCache {
+    /// Create the cache described by `settings`, or `None` when caching is disabled
+    pub fn from_settings(settings: &CacheSettings) -> Option<Self> {
+        if settings.mode == CacheMode::NoStore {
+            return None;
+        }
+        Self::open(settings)
+    }
+
+    /// Open the cache directory regardless of mode, for management subcommands
+    pub fn open(settings: &CacheSettings) -> Option<Self> {
+        let dir = settings.dir.clone().or_else(default_cache_dir)?;
+        Some(Self {
+            dir,
+            mode: settings.mode,
+            ttl: settings.ttl.clone(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Configured lifetime for a tool, if its results may be cached
+    fn ttl_for(&self, tool: &str) -> Option<Duration> {
+        self.ttl
+            .get(tool)
+            .filter(|secs| **secs > 0)
+            .map(|secs| Duration::from_secs(*secs))
+    }
+
+    /// Whether results of `tool` are stored in the cache
+    pub fn is_cacheable(&self, tool: &str) -> bool {
+        self.mode != CacheMode::NoStore && self.ttl_for(tool).is_some()
+    }
+
+    /// Look up a fresh entry for the call, honoring the cache mode
+    pub fn get(&self, endpoint: &str, tool: &str, arguments: &Value) -> Option<CacheEntry> {
+        if self.mode != CacheMode::Default {
+            return None;
+        }
+        let ttl = self.ttl_for(tool)?;
+        let entry = self.read_entry(&cache_key(endpoint, tool, arguments))?;
+        (entry.age(unix_now()) <= ttl).then_some(entry)
+    }
+
+    /// Look up any stored entry for the call, however old, for serving while offline
+    pub fn get_stale(&self, endpoint: &str, tool: &str, arguments: &Value) -> Option<CacheEntry> {
+        self.read_entry(&cache_key(endpoint, tool, arguments))
+    }
+
+    /// Remember the tool list so it can be served while offline
+    pub fn put_tools(&self, endpoint: &str, tools: &[Tool]) -> Result<()> {
+        let entry = ToolListEntry {
+            endpoint: normalize_endpoint(endpoint).to_string(),
+            fetched_at: unix_now(),
+            tools: tools.to_vec(),
+        };
+        let path = self.entry_path(&tool_list_key(endpoint));
+        write_atomically(&path, &serde_json::to_vec(&entry)?)
+    }
+
+    pub fn get_tools(&self, endpoint: &str) -> Option<ToolListEntry> {
+        let bytes = fs::read(self.entry_path(&tool_list_key(endpoint))).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Store a successful result for the call if the tool is cacheable
+    pub fn put(
+        &self,
+        endpoint: &str,
+        tool: &str,
+        arguments: &Value,
+        result: &CallToolResult,
+    ) -> Result<()> {
+        if !self.is_cacheable(tool) || result.is_error == Some(true) {
+            return Ok(());
+        }
+
+        let entry = CacheEntry {
+            endpoint: normalize_endpoint(endpoint).to_string(),
+            tool: tool.to_string(),
+            arguments: arguments.clone(),
+            fetched_at: unix_now(),
+            result: result.clone(),
+        };
+        let path = self.entry_path(&cache_key(endpoint, tool, arguments));
+        write_atomically(&path, &serde_json::to_vec(&entry)?)
+    }
+
+    /// Remove every cached entry, returning how many were deleted
+    pub fn clear(&self) -> Result<usize> {
+        let paths = self.entry_paths();
+        for path in &paths {
+            fs::remove_file(path)
+                .map_err(|e| anyhow!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        Ok(paths.len())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let now = unix_now();
+        let mut stats = CacheStats {
+            dir: self.dir.clone(),
+            ..CacheStats::default()
+        };
+
+        for path in self.entry_paths() {
+            stats.total_bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            let Some(entry) = read_entry_file(&path) else {
+                continue;
+            };
+            stats.entries += 1;
+            if self
+                .ttl_for(&entry.tool)
+                .is_none_or(|ttl| entry.age(now) > ttl)
+            {
+                stats.expired += 1;
+            }
+            *stats.by_tool.entry(entry.tool).or_default() += 1;
+        }
+        stats
+    }
+
+    fn read_entry(&self, key: &str) -> Option<CacheEntry> {
+        read_entry_file(&self.entry_path(key))
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(format!("{key}.json"))
+    }
+
+    fn entry_paths(&self) -> Vec<PathBuf> {
+        let Ok(shards) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        shards
+            .flatten()
+            .filter(|shard| shard.path().is_dir())
+            .filter_map(|shard| fs::read_dir(shard.path()).ok())
+            .flat_map(|files| files.flatten().map(|file| file.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect()
+    }
+}
+
+/// Platform cache directory used when no `cache.dir` setting is given
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("deepwiki-mcp-bridge").join("responses"))
+}
+
+/// Content address for a tool call
+pub fn cache_key(endpoint: &str, tool: &str, arguments: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(normalize_endpoint(endpoint).as_bytes());
+    hasher.update(b"\n");
+    hasher.update(tool.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(canonical_json(arguments).as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn tool_list_key(endpoint: &str) -> String {
+    cache_key(endpoint, "tools/list", &Value::Null)
+}
+
+fn normalize_endpoint(endpoint: &str) -> &str {
+    endpoint.trim_end_matches('/')
+}
+
+/// Serialize JSON with object keys sorted at every level
+pub fn canonical_json(value: &Value) -> String {
+    fn sorted(value: &Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let ordered: BTreeMap<&String, Value> =
+                    map.iter().map(|(k, v)| (k, sorted(v))).collect();
+                Value::Object(ordered.into_iter().map(|(k, v)| (k.clone(), v)).collect())
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+            other => other.clone(),
+        }
+    }
+    sorted(value).to_string()
+}
+
+fn read_entry_file(path: &Path) -> Option<CacheEntry> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Write via a temporary file so concurrent readers never see a partial entry
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid cache path: {}", path.display()))?;
+    fs::create_dir_all(parent).map_err(|e| {
+        anyhow!(
+            "Failed to create cache directory {}: {}",
+            parent.display(),
+            e
+        )
+    })?;
+
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, bytes).map_err(|e| anyhow!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| anyhow!("Failed to move {}: {}", tmp.display(), e))
+}
+
+/// Format Unix seconds as an RFC 3339 UTC timestamp
+pub fn format_timestamp(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map_or_else(
+            || secs.to_string(),
+            |time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        )
+}
+
+/// Coarse human-readable age, e.g. "3 hours"
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (count, unit) = match secs {
+        0..60 => return "less than a minute".to_string(),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::Content;
+    use serde_json::json;
+
+    fn cache_in(dir: &Path, mode: CacheMode) -> ResponseCache {
+        ResponseCache::open(&CacheSettings {
+            mode,
+            dir: Some(dir.to_path_buf()),
+            ..CacheSettings::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cache_key_is_canonical() {
+        let a = json!({"repoName": "a/b", "options": {"x": 1, "y": [2, {"q": 1, "p": 0}]}});
+        let b = json!({"options": {"y": [2, {"p": 0, "q": 1}], "x": 1}, "repoName": "a/b"});
+
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+        assert_eq!(
+            cache_key("https://mcp.deepwiki.com/", "read_wiki_structure", &a),
+            cache_key("https://mcp.deepwiki.com", "read_wiki_structure", &b)
+        );
+        assert_ne!(
+            cache_key("https://mcp.deepwiki.com", "read_wiki_structure", &a),
+            cache_key("https://mcp.deepwiki.com", "read_wiki_contents", &a)
+        );
+    }
+
+    #[test]
+    fn test_put_and_get_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::Default);
+        let args = json!({"repoName": "tokio-rs/tokio"});
+        let result = CallToolResult::success(vec![Content::text("# Overview")]);
+
+        assert!(cache
+            .get("https://mcp.deepwiki.com", "read_wiki_structure", &args)
+            .is_none());
+        cache
+            .put(
+                "https://mcp.deepwiki.com",
+                "read_wiki_structure",
+                &args,
+                &result,
+            )
+            .unwrap();
+
+        let entry = cache
+            .get("https://mcp.deepwiki.com", "read_wiki_structure", &args)
+            .unwrap();
+        assert_eq!(entry.result, result);
+        assert_eq!(entry.tool, "read_wiki_structure");
+    }
+
+    #[test]
+    fn test_non_idempotent_tools_and_errors_are_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::Default);
+        let args = json!({"repoName": "a/b", "question": "why?"});
+
+        cache
+            .put(
+                "https://mcp.deepwiki.com",
+                "ask_question",
+                &args,
+                &CallToolResult::success(vec![Content::text("because")]),
+            )
+            .unwrap();
+        cache
+            .put(
+                "https://mcp.deepwiki.com",
+                "read_wiki_contents",
+                &args,
+                &CallToolResult::error(vec![Content::text("not indexed")]),
+            )
+            .unwrap();
+
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_no_cache_mode_skips_reads_but_refreshes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::NoCache);
+        let args = json!({"repoName": "a/b"});
+
+        cache
+            .put(
+                "https://mcp.deepwiki.com",
+                "read_wiki_contents",
+                &args,
+                &CallToolResult::success(vec![Content::text("fresh")]),
+            )
+            .unwrap();
+
+        assert!(cache
+            .get("https://mcp.deepwiki.com", "read_wiki_contents", &args)
+            .is_none());
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_no_store_mode_disables_cache() {
+        let settings = CacheSettings {
+            mode: CacheMode::NoStore,
+            ..CacheSettings::default()
+        };
+        assert!(ResponseCache::from_settings(&settings).is_none());
+    }
+
+    #[test]
+    fn test_stale_entries_are_served_and_marked() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::NoCache);
+        let args = json!({"repoName": "a/b"});
+        let entry = CacheEntry {
+            endpoint: "https://mcp.deepwiki.com".to_string(),
+            tool: "read_wiki_contents".to_string(),
+            arguments: args.clone(),
+            fetched_at: unix_now() - 3 * 86400,
+            result: CallToolResult::success(vec![Content::text("page")]),
+        };
+        let path = cache.entry_path(&cache_key(
+            "https://mcp.deepwiki.com",
+            "read_wiki_contents",
+            &args,
+        ));
+        write_atomically(&path, &serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        let stale = cache
+            .get_stale("https://mcp.deepwiki.com", "read_wiki_contents", &args)
+            .unwrap();
+        let result = stale.into_stale_result();
+        assert_eq!(result.content.len(), 2);
+        let notice = &result.content[0].as_text().unwrap().text;
+        assert!(notice.starts_with("[Offline]"));
+        assert!(notice.contains("3 days ago"));
+        assert_eq!(result.content[1].as_text().unwrap().text, "page");
+
+        assert!(cache
+            .get_stale("https://mcp.deepwiki.com", "read_wiki_contents", &json!({}))
+            .is_none());
+    }
+
+    #[test]
+    fn test_tool_list_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::Default);
+        let tool = Tool::new(
+            "read_wiki_structure",
+            "List documentation topics",
+            serde_json::Map::new(),
+        );
+
+        assert!(cache.get_tools("https://mcp.deepwiki.com").is_none());
+        cache
+            .put_tools("https://mcp.deepwiki.com/", std::slice::from_ref(&tool))
+            .unwrap();
+        let entry = cache.get_tools("https://mcp.deepwiki.com").unwrap();
+        assert_eq!(entry.tools, vec![tool]);
+    }
+
+    #[test]
+    fn test_format_timestamp_and_age() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_age(Duration::from_secs(5)), "less than a minute");
+        assert_eq!(format_age(Duration::from_secs(3600)), "1 hour");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2 hours");
+    }
+
+    #[test]
+    fn test_stats_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache_in(dir.path(), CacheMode::Default);
+        let result = CallToolResult::success(vec![Content::text("page")]);
+
+        for repo in ["a/b", "c/d"] {
+            cache
+                .put(
+                    "https://mcp.deepwiki.com",
+                    "read_wiki_contents",
+                    &json!({"repoName": repo}),
+                    &result,
+                )
+                .unwrap();
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.expired, 0);
+        assert_eq!(stats.by_tool.get("read_wiki_contents"), Some(&2));
+        assert!(stats.total_bytes > 0);
+
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -0,0 +1,663 @@
+//! Command-line argument parsing for the bridge binary
+//!
+//! The bridge runs as a STDIO proxy by default (`deepwiki-mcp-bridge <ENDPOINT_URL>`),
+//! and additionally exposes standalone subcommands for scripting `DeepWiki` from a shell.
+
+use crate::logging::LogOptions;
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Default endpoint used by subcommands that do not take an explicit URL
+pub const DEFAULT_ENDPOINT: &str = "https://mcp.deepwiki.com";
+
+/// Default output directory for `mirror`
+const DEFAULT_MIRROR_DIR: &str = "deepwiki-mirror";
+
+/// Output format for the standalone subcommands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!(
+                "Invalid output format: {other}. Expected 'text' or 'json'"
+            )),
+        }
+    }
+}
+
+/// Operation performed by the `cache` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheAction {
+    Clear,
+    Stats,
+}
+
+/// A fully parsed bridge invocation
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Proxy Zed's STDIO connection to a remote MCP server
+    Proxy {
+        endpoint_url: String,
+        /// Serve only from the response cache without contacting the server
+        offline: bool,
+        /// Repository used when a DeepWiki tool call omits `repoName`
+        repo: Option<String>,
+        /// JSONL file to record all JSON-RPC traffic to
+        record: Option<PathBuf>,
+        /// Address to serve Prometheus metrics on
+        metrics_addr: Option<SocketAddr>,
+        /// Log format and file overriding the settings
+        log: LogOptions,
+    },
+    /// List the tools exposed by an MCP server
+    Tools {
+        endpoint_url: String,
+        output: OutputFormat,
+    },
+    /// Invoke a single tool and print its result
+    Call {
+        endpoint_url: String,
+        tool: String,
+        arguments: Map<String, Value>,
+        output: OutputFormat,
+    },
+    /// Shortcut for calling `ask_question` on a repository
+    Ask {
+        endpoint_url: String,
+        repo: String,
+        question: String,
+        output: OutputFormat,
+    },
+    /// Interactive session for exploring an MCP server
+    Repl { endpoint_url: String },
+    /// Save a repository's wiki as a Markdown tree and seed the response cache
+    Mirror {
+        endpoint_url: String,
+        repo: String,
+        out: PathBuf,
+    },
+    /// Serve a recorded session on STDIO, as the bridge or as one upstream
+    Replay {
+        file: PathBuf,
+        upstream: Option<String>,
+    },
+    /// Check the settings and every upstream, with circuit states from a running bridge
+    Doctor {
+        endpoint_url: String,
+        /// Metrics address of a running bridge to read circuit states from
+        metrics_addr: Option<SocketAddr>,
+        output: OutputFormat,
+    },
+    /// Manage the on-disk response cache
+    Cache {
+        action: CacheAction,
+        output: OutputFormat,
+    },
+    /// Print usage information
+    Help,
+}
+
+impl Command {
+    /// Default log filter when `RUST_LOG` is not set
+    ///
+    /// Subcommands write their results to stdout, so they stay quiet unless something goes wrong.
+    pub const fn default_log_level(&self) -> &'static str {
+        match self {
+            Self::Proxy { .. } => "info",
+            _ => "warn",
+        }
+    }
+
+    /// Logging options given on the command line
+    pub fn log_options(&self) -> LogOptions {
+        match self {
+            Self::Proxy { log, .. } => log.clone(),
+            _ => LogOptions::default(),
+        }
+    }
+
+    /// The endpoint URL this command connects to, if any
+    pub fn endpoint_url(&self) -> Option<&str> {
+        match self {
+            Self::Proxy { endpoint_url, .. }
+            | Self::Tools { endpoint_url, .. }
+            | Self::Call { endpoint_url, .. }
+            | Self::Ask { endpoint_url, .. }
+            | Self::Mirror { endpoint_url, .. }
+            | Self::Doctor { endpoint_url, .. }
+            | Self::Repl { endpoint_url } => Some(endpoint_url),
+            Self::Replay { .. } | Self::Cache { .. } | Self::Help => None,
+        }
+    }
+}
+
+/// Parse the process arguments (including the program name) into a [`Command`]
+pub fn parse_args(args: &[String]) -> Result<Command> {
+    let rest = args.get(1..).unwrap_or_default();
+
+    match rest.first().map(String::as_str) {
+        None => Err(anyhow!("Missing <ENDPOINT_URL> argument")),
+        Some("help" | "-h" | "--help") => Ok(Command::Help),
+        Some("tools") => parse_tools(&rest[1..]),
+        Some("call") => parse_call(&rest[1..]),
+        Some("ask") => parse_ask(&rest[1..]),
+        Some("repl") => parse_repl(&rest[1..]),
+        Some("mirror") => parse_mirror(&rest[1..]),
+        Some("replay") => parse_replay(&rest[1..]),
+        Some("cache") => parse_cache(&rest[1..]),
+        Some("doctor") => parse_doctor(&rest[1..]),
+        Some(_) => parse_proxy(rest),
+    }
+}
+
+fn parse_proxy(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(
+        args,
+        &[
+            "--repo",
+            "--record",
+            "--metrics-addr",
+            "--log-format",
+            "--log-file",
+            "--log-rotation",
+        ],
+        &["--offline"],
+    )?;
+    let [endpoint_url] = parsed.expect_positionals(["<ENDPOINT_URL>"])?;
+    let repo = parsed.value("--repo").map(str::to_string);
+    if let Some(repo) = &repo {
+        validate_repo(repo)?;
+    }
+    Ok(Command::Proxy {
+        endpoint_url,
+        offline: parsed.flag("--offline"),
+        repo,
+        record: parsed.value("--record").map(PathBuf::from),
+        metrics_addr: parsed.metrics_addr()?,
+        log: LogOptions {
+            format: parsed.value("--log-format").map(str::parse).transpose()?,
+            file: parsed.value("--log-file").map(PathBuf::from),
+            rotation: parsed.value("--log-rotation").map(str::parse).transpose()?,
+        },
+    })
+}
+
+fn parse_tools(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--output"], &[])?;
+    let [endpoint_url] = parsed.expect_positionals(["<ENDPOINT_URL>"])?;
+    Ok(Command::Tools {
+        endpoint_url,
+        output: parsed.output()?,
+    })
+}
+
+fn parse_call(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--output", "--arg", "--json"], &[])?;
+    let [endpoint_url, tool] = parsed.expect_positionals(["<ENDPOINT_URL>", "<TOOL>"])?;
+
+    let mut arguments = match parsed.value("--json") {
+        Some(raw) => match serde_json::from_str(raw)? {
+            Value::Object(map) => map,
+            _ => return Err(anyhow!("--json must be a JSON object")),
+        },
+        None => Map::new(),
+    };
+    for pair in parsed.values("--arg") {
+        let (key, value) = parse_key_value(pair)?;
+        arguments.insert(key, value);
+    }
+
+    Ok(Command::Call {
+        endpoint_url,
+        tool,
+        arguments,
+        output: parsed.output()?,
+    })
+}
+
+fn parse_ask(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--output", "--endpoint"], &[])?;
+    let [repo, question] = parsed.expect_positionals(["<OWNER/REPO>", "<QUESTION>"])?;
+    validate_repo(&repo)?;
+
+    Ok(Command::Ask {
+        endpoint_url: parsed.endpoint(),
+        repo,
+        question,
+        output: parsed.output()?,
+    })
+}
+
+fn parse_mirror(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--out", "--endpoint"], &[])?;
+    let [repo] = parsed.expect_positionals(["<OWNER/REPO>"])?;
+    validate_repo(&repo)?;
+
+    Ok(Command::Mirror {
+        endpoint_url: parsed.endpoint(),
+        repo,
+        out: PathBuf::from(parsed.value("--out").unwrap_or(DEFAULT_MIRROR_DIR)),
+    })
+}
+
+fn validate_repo(repo: &str) -> Result<()> {
+    if repo.split('/').filter(|part| !part.is_empty()).count() != 2 {
+        return Err(anyhow!(
+            "Invalid repository: {repo}. Expected the form owner/repo"
+        ));
+    }
+    Ok(())
+}
+
+fn parse_repl(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &[], &[])?;
+    let [endpoint_url] = parsed.expect_positionals(["<ENDPOINT_URL>"])?;
+    Ok(Command::Repl { endpoint_url })
+}
+
+fn parse_replay(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--upstream"], &[])?;
+    let [file] = parsed.expect_positionals(["<FILE>"])?;
+    Ok(Command::Replay {
+        file: PathBuf::from(file),
+        upstream: parsed.value("--upstream").map(str::to_string),
+    })
+}
+
+fn parse_cache(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--output"], &[])?;
+    let [action] = parsed.expect_positionals(["<clear|stats>"])?;
+    let action = match action.as_str() {
+        "clear" => CacheAction::Clear,
+        "stats" => CacheAction::Stats,
+        other => {
+            return Err(anyhow!(
+                "Unknown cache action: {other}. Expected 'clear' or 'stats'"
+            ))
+        }
+    };
+    Ok(Command::Cache {
+        action,
+        output: parsed.output()?,
+    })
+}
+
+fn parse_doctor(args: &[String]) -> Result<Command> {
+    let parsed = ParsedArgs::parse(args, &["--endpoint", "--metrics-addr", "--output"], &[])?;
+    parsed.expect_positionals([])?;
+    Ok(Command::Doctor {
+        endpoint_url: parsed.endpoint(),
+        metrics_addr: parsed.metrics_addr()?,
+        output: parsed.output()?,
+    })
+}
+
+/// Parse a `key=value` tool argument
+///
+/// Values that parse as JSON (numbers, booleans, arrays, objects) keep their type;
+/// anything else is passed through as a plain string.
+fn parse_key_value(pair: &str) -> Result<(String, Value)> {
+    let (key, raw) = pair
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid --arg value: {pair}. Expected key=value"))?;
+    if key.is_empty() {
+        return Err(anyhow!(
+            "Invalid --arg value: {pair}. Key must not be empty"
+        ));
+    }
+
+    let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+    Ok((key.to_string(), value))
+}
+
+/// Arguments split into positionals and `--option` values
+struct ParsedArgs {
+    positionals: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl ParsedArgs {
+    /// Split `args`, accepting only the given value-taking and boolean options
+    fn parse(args: &[String], value_options: &[&str], flag_options: &[&str]) -> Result<Self> {
+        let mut positionals = Vec::new();
+        let mut options = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                positionals.push(arg.clone());
+                continue;
+            }
+
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if value_options.contains(&name) {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => iter
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| anyhow!("Option {name} requires a value"))?,
+                };
+                options.push((name.to_string(), Some(value)));
+            } else if flag_options.contains(&name) && inline_value.is_none() {
+                options.push((name.to_string(), None));
+            } else {
+                return Err(anyhow!("Unknown option: {arg}"));
+            }
+        }
+
+        Ok(Self {
+            positionals,
+            options,
+        })
+    }
+
+    /// Require exactly `N` positionals, naming the missing ones in the error
+    fn expect_positionals<const N: usize>(&self, names: [&str; N]) -> Result<[String; N]> {
+        if self.positionals.len() > N {
+            return Err(anyhow!(
+                "Unexpected argument: {}",
+                self.positionals[N..].join(" ")
+            ));
+        }
+        if let Some(missing) = names.get(self.positionals.len()) {
+            return Err(anyhow!("Missing {missing} argument"));
+        }
+
+        let mut values = self.positionals.iter().cloned();
+        Ok(names.map(|_| values.next().unwrap_or_default()))
+    }
+
+    /// The last value given for an option
+    fn value<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        self.values(name).last()
+    }
+
+    /// Every value given for a repeatable option, in order
+    fn values<'a>(&'a self, name: &'a str) -> impl DoubleEndedIterator<Item = &'a str> + 'a {
+        self.options
+            .iter()
+            .filter(move |(option, _)| option == name)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    /// The `--endpoint` option, defaulting to the public `DeepWiki` server
+    fn endpoint(&self) -> String {
+        self.value("--endpoint")
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .to_string()
+    }
+
+    /// Whether a boolean option was given
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    fn output(&self) -> Result<OutputFormat> {
+        self.value("--output")
+            .map_or(Ok(OutputFormat::default()), OutputFormat::parse)
+    }
+
+    fn metrics_addr(&self) -> Result<Option<SocketAddr>> {
+        self.value("--metrics-addr")
+            .map(|addr| {
+                addr.parse()
+                    .map_err(|_| anyhow!("Invalid --metrics-addr: {addr}. Expected HOST:PORT"))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{LogFormat, LogRotation};
+    use serde_json::json;
+
+    fn args(raw: &[&str]) -> Vec<String> {
+        std::iter::once("deepwiki-mcp-bridge")
+            .chain(raw.iter().copied())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_proxy_mode() {
+        assert_eq!(
+            parse_args(&args(&["https://mcp.deepwiki.com"])).unwrap(),
+            Command::Proxy {
+                endpoint_url: "https://mcp.deepwiki.com".to_string(),
+                offline: false,
+                repo: None,
+                record: None,
+                metrics_addr: None,
+                log: LogOptions::default(),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&[
+                "--offline",
+                "https://mcp.deepwiki.com",
+                "--repo",
+                "zed-industries/zed",
+                "--record",
+                "traffic.jsonl",
+                "--metrics-addr",
+                "127.0.0.1:9464",
+                "--log-format",
+                "json",
+                "--log-file=bridge.log",
+                "--log-rotation",
+                "10MB"
+            ]))
+            .unwrap(),
+            Command::Proxy {
+                endpoint_url: "https://mcp.deepwiki.com".to_string(),
+                offline: true,
+                repo: Some("zed-industries/zed".to_string()),
+                record: Some(PathBuf::from("traffic.jsonl")),
+                metrics_addr: Some(SocketAddr::from(([127, 0, 0, 1], 9464))),
+                log: LogOptions {
+                    format: Some(LogFormat::Json),
+                    file: Some(PathBuf::from("bridge.log")),
+                    rotation: Some(LogRotation::Size(10 * 1024 * 1024)),
+                },
+            }
+        );
+        assert!(parse_args(&args(&["https://mcp.deepwiki.com", "--repo", "zed"])).is_err());
+        assert!(parse_args(&args(&["https://mcp.deepwiki.com", "--offline=yes"])).is_err());
+        assert!(parse_args(&args(&[
+            "https://mcp.deepwiki.com",
+            "--metrics-addr",
+            "9464"
+        ]))
+        .is_err());
+        assert!(parse_args(&args(&["https://mcp.deepwiki.com", "--log-format", "xml"])).is_err());
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["https://mcp.deepwiki.com", "extra"])).is_err());
+        assert_eq!(parse_args(&args(&["--help"])).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn test_parse_tools_subcommand() {
+        assert_eq!(
+            parse_args(&args(&[
+                "tools",
+                "https://mcp.deepwiki.com",
+                "--output",
+                "json"
+            ]))
+            .unwrap(),
+            Command::Tools {
+                endpoint_url: "https://mcp.deepwiki.com".to_string(),
+                output: OutputFormat::Json,
+            }
+        );
+        assert!(parse_args(&args(&["tools"])).is_err());
+        assert!(parse_args(&args(&["tools", "https://x.dev", "--output=yaml"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_call_subcommand_merges_arguments() {
+        let command = parse_args(&args(&[
+            "call",
+            "https://mcp.deepwiki.com",
+            "read_wiki_structure",
+            "--json",
+            r#"{"repoName": "tokio-rs/tokio", "depth": 1}"#,
+            "--arg",
+            "depth=2",
+            "--arg=verbose=true",
+        ]))
+        .unwrap();
+
+        let Command::Call {
+            tool, arguments, ..
+        } = command
+        else {
+            panic!("expected call command");
+        };
+        assert_eq!(tool, "read_wiki_structure");
+        assert_eq!(
+            Value::Object(arguments),
+            json!({"repoName": "tokio-rs/tokio", "depth": 2, "verbose": true})
+        );
+    }
+
+    #[test]
+    fn test_parse_call_rejects_bad_arguments() {
+        let base = ["call", "https://mcp.deepwiki.com", "tool"];
+        let with = |extra: &[&str]| {
+            let mut raw = base.to_vec();
+            raw.extend_from_slice(extra);
+            parse_args(&args(&raw))
+        };
+
+        assert!(with(&["--arg", "no-equals-sign"]).is_err());
+        assert!(with(&["--arg", "=value"]).is_err());
+        assert!(with(&["--json", "[1, 2]"]).is_err());
+        assert!(with(&["--arg"]).is_err());
+        assert!(with(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_ask_subcommand() {
+        assert_eq!(
+            parse_args(&args(&[
+                "ask",
+                "tokio-rs/tokio",
+                "How does the scheduler work?"
+            ]))
+            .unwrap(),
+            Command::Ask {
+                endpoint_url: DEFAULT_ENDPOINT.to_string(),
+                repo: "tokio-rs/tokio".to_string(),
+                question: "How does the scheduler work?".to_string(),
+                output: OutputFormat::Text,
+            }
+        );
+        assert!(parse_args(&args(&["ask", "tokio", "question"])).is_err());
+        assert!(parse_args(&args(&["ask", "tokio-rs/tokio"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_repl_subcommand() {
+        assert_eq!(
+            parse_args(&args(&["repl", "https://mcp.deepwiki.com"])).unwrap(),
+            Command::Repl {
+                endpoint_url: "https://mcp.deepwiki.com".to_string()
+            }
+        );
+        assert!(parse_args(&args(&["repl"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_mirror_subcommand() {
+        assert_eq!(
+            parse_args(&args(&["mirror", "tokio-rs/tokio"])).unwrap(),
+            Command::Mirror {
+                endpoint_url: DEFAULT_ENDPOINT.to_string(),
+                repo: "tokio-rs/tokio".to_string(),
+                out: PathBuf::from(DEFAULT_MIRROR_DIR),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["mirror", "tokio-rs/tokio", "--out=docs"])).unwrap(),
+            Command::Mirror {
+                endpoint_url: DEFAULT_ENDPOINT.to_string(),
+                repo: "tokio-rs/tokio".to_string(),
+                out: PathBuf::from("docs"),
+            }
+        );
+        assert!(parse_args(&args(&["mirror", "tokio"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_replay_subcommand() {
+        assert_eq!(
+            parse_args(&args(&["replay", "session.jsonl", "--upstream", "devin"])).unwrap(),
+            Command::Replay {
+                file: PathBuf::from("session.jsonl"),
+                upstream: Some("devin".to_string()),
+            }
+        );
+        assert!(parse_args(&args(&["replay"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_cache_subcommand() {
+        assert_eq!(
+            parse_args(&args(&["cache", "stats", "--output", "json"])).unwrap(),
+            Command::Cache {
+                action: CacheAction::Stats,
+                output: OutputFormat::Json,
+            }
+        );
+        assert!(parse_args(&args(&["cache"])).is_err());
+        assert!(parse_args(&args(&["cache", "purge"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_doctor_subcommand() {
+        assert_eq!(
+            parse_args(&args(&["doctor", "--metrics-addr", "127.0.0.1:9464"])).unwrap(),
+            Command::Doctor {
+                endpoint_url: DEFAULT_ENDPOINT.to_string(),
+                metrics_addr: Some("127.0.0.1:9464".parse().unwrap()),
+                output: OutputFormat::Text,
+            }
+        );
+        assert!(parse_args(&args(&["doctor", "extra"])).is_err());
+        assert!(parse_args(&args(&["doctor", "--metrics-addr", "9464"])).is_err());
+    }
+
+    #[test]
+    fn test_key_value_typing() {
+        assert_eq!(
+            parse_key_value("repoName=facebook/react").unwrap(),
+            ("repoName".to_string(), json!("facebook/react"))
+        );
+        assert_eq!(
+            parse_key_value("limit=5").unwrap(),
+            ("limit".to_string(), json!(5))
+        );
+        assert_eq!(
+            parse_key_value("query=a=b").unwrap(),
+            ("query".to_string(), json!("a=b"))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Log messages for Zed over MCP (`logging/setLevel` and `notifications/message`)
+//!
+//! The bridge declares the logging capability, so Zed shows its log messages in the
+//! context-server log panel. The bridge's own events (reconnects, authorization, cache
+//! fallbacks, ...) and the upstreams' `notifications/message` are forwarded once they reach the
+//! level Zed asked for with `logging/setLevel`, or warnings and above until it does.
+//!
+//! Messages logged before Zed finishes initializing are queued and sent once it has.
+
+use crate::retry::HttpFailures;
+use rmcp::model::{ClientInfo, LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::service::{NotificationContext, Peer};
+use rmcp::{ClientHandler, RoleClient, RoleServer};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Logger name of the bridge's own messages
+const BRIDGE_LOGGER: &str = env!("CARGO_PKG_NAME");
+
+/// Messages held while Zed is not connected; later ones are dropped
+const QUEUE_SIZE: usize = 256;
+
+/// Level used until Zed sets one
+const DEFAULT_LEVEL: LoggingLevel = LoggingLevel::Warning;
+
+/// Messages for the client, filtered by the level it asked for
+pub struct ClientLog {
+    /// [`LoggingLevel`] as its severity rank
+    level: AtomicU8,
+    sender: mpsc::Sender<LoggingMessageNotificationParam>,
+    /// Taken when the client connects
+    receiver: Mutex<Option<mpsc::Receiver<LoggingMessageNotificationParam>>>,
+}
+
+impl Default for ClientLog {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        Self {
+            level: AtomicU8::new(severity(DEFAULT_LEVEL)),
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+}
+
+impl ClientLog {
+    /// Start delivering messages to the client; later calls are ignored
+    pub fn connect(&self, peer: Peer<RoleServer>) {
+        let Some(mut receiver) = self.receiver.lock().ok().and_then(|mut r| r.take()) else {
+            return;
+        };
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                // Failures are not logged, since that would log another message to send
+                if peer.notify_logging_message(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    pub fn set_level(&self, level: LoggingLevel) {
+        self.level.store(severity(level), Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self, level: LoggingLevel) -> bool {
+        severity(level) >= self.level.load(Ordering::Relaxed)
+    }
+
+    /// Queue a message if it reaches the client's level
+    pub fn log(&self, level: LoggingLevel, logger: String, data: Value) {
+        if self.enabled(level) {
+            let _ = self.sender.try_send(LoggingMessageNotificationParam {
+                level,
+                logger: Some(logger),
+                data,
+            });
+        }
+    }
+
+    /// Pass on an upstream's message, naming the upstream in the logger
+    pub fn forward(&self, upstream: &str, message: LoggingMessageNotificationParam) {
+        let logger = match message.logger {
+            Some(logger) => format!("{upstream}/{logger}"),
+            None => upstream.to_string(),
+        };
+        self.log(message.level, logger, message.data);
+    }
+}
+
+/// Rank of a level, from `debug` (0) to `emergency` (7)
+fn severity(level: LoggingLevel) -> u8 {
+    level as u8
+}
+
+fn mcp_level(level: Level) -> LoggingLevel {
+    match level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// An event's fields, with `message` kept apart
+#[derive(Default)]
+struct JsonFields {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl JsonFields {
+    fn insert(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::String(message)) => self.message = Some(message),
+            (name, value) => {
+                self.fields.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::Bool(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::String(format!("{value:?}")));
+    }
+}
+
+/// `tracing` layer sending the bridge's events to the client
+pub struct ClientLogLayer(pub Arc<ClientLog>);
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = mcp_level(*event.metadata().level());
+        if !self.0.enabled(level) {
+            return;
+        }
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        let message = Value::String(fields.message.unwrap_or_default());
+        let data = if fields.fields.is_empty() {
+            message
+        } else {
+            let mut data = fields.fields;
+            data.insert("message".to_string(), message);
+            Value::Object(data)
+        };
+        self.0.log(level, BRIDGE_LOGGER.to_string(), data);
+    }
+}
+
+/// Client handler for an upstream session, passing its log messages on to Zed
+///
+/// It also holds the session's [`HttpFailures`], shared with its HTTP client, so the proxy can
+/// tell which failed requests are worth retrying.
+pub struct UpstreamHandler {
+    info: ClientInfo,
+    upstream: String,
+    log: Option<Arc<ClientLog>>,
+    http_failures: Arc<HttpFailures>,
+}
+
+impl UpstreamHandler {
+    pub fn new(
+        info: ClientInfo,
+        upstream: &str,
+        log: Option<Arc<ClientLog>>,
+        http_failures: Arc<HttpFailures>,
+    ) -> Self {
+        Self {
+            info,
+            upstream: upstream.to_string(),
+            log,
+            http_failures,
+        }
+    }
+
+    pub fn http_failures(&self) -> &HttpFailures {
+        &self.http_failures
+    }
+}
+
+impl ClientHandler for UpstreamHandler {
+    async fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        if let Some(log) = &self.log {
+            log.forward(&self.upstream, params);
+        }
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        self.info.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn queued(log: &ClientLog) -> Vec<LoggingMessageNotificationParam> {
+        let mut receiver = log.receiver.lock().unwrap().take().unwrap();
+        let mut messages = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn test_filters_by_level_and_names_upstreams() {
+        let log = ClientLog::default();
+        log.log(LoggingLevel::Info, "bridge".to_string(), json!("quiet"));
+        log.forward(
+            "docs",
+            LoggingMessageNotificationParam {
+                level: LoggingLevel::Error,
+                logger: Some("indexer".to_string()),
+                data: json!("failed"),
+            },
+        );
+        log.set_level(LoggingLevel::Debug);
+        log.forward(
+            "deepwiki",
+            LoggingMessageNotificationParam {
+                level: LoggingLevel::Debug,
+                logger: None,
+                data: json!("details"),
+            },
+        );
+
+        let messages = queued(&log);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].logger.as_deref(), Some("docs/indexer"));
+        assert_eq!(messages[1].logger.as_deref(), Some("deepwiki"));
+        assert_eq!(messages[1].data, "details");
+    }
+
+    #[test]
+    fn test_layer_sends_bridge_events() {
+        let log = Arc::new(ClientLog::default());
+        let subscriber = tracing_subscriber::registry().with(ClientLogLayer(log.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Connected");
+            tracing::warn!("Reconnecting to deepwiki");
+            tracing::error!(attempt = 3, "Giving up");
+        });
+
+        let messages = queued(&log);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].level, LoggingLevel::Warning);
+        assert_eq!(messages[0].logger.as_deref(), Some(BRIDGE_LOGGER));
+        assert_eq!(messages[0].data, "Reconnecting to deepwiki");
+        assert_eq!(
+            messages[1].data,
+            json!({"message": "Giving up", "attempt": 3})
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Coalescing identical concurrent requests
+//!
+//! While an idempotent request is in flight to an upstream, an identical one (same upstream,
+//! method and canonicalized params, ignoring `_meta`) waits for the same response instead of
+//! being sent again. The first request leads the flight; every waiter gets a copy of its
+//! response under its own JSON-RPC id.
+//!
+//! A waiter that is cancelled stops waiting without affecting the others, even if it led the
+//! flight: the upstream call is only cancelled once every waiter has been.
+
+use crate::cache::canonical_json;
+use rmcp::model::{ClientRequest, ServerResult};
+use rmcp::service::ServiceError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// A response shared by every waiter
+type Shared = Result<ServerResult, Arc<ServiceError>>;
+
+/// A transport error shared by the waiters of a flight
+#[derive(Debug)]
+struct SharedError(Arc<ServiceError>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match send_error(&self.0) {
+            Some(error) => fmt::Display::fmt(error, f),
+            None => self.0.fmt(f),
+        }
+    }
+}
+
+impl Error for SharedError {}
+
+/// The error behind a [`ServiceError::TransportSend`], also for coalesced requests
+pub fn send_error(error: &ServiceError) -> Option<&(dyn Error + Send + Sync + 'static)> {
+    let ServiceError::TransportSend(error) = error else {
+        return None;
+    };
+    match error.downcast_ref::<SharedError>() {
+        Some(shared) => send_error(&shared.0),
+        None => Some(error.as_ref()),
+    }
+}
+
+/// A waiter's copy of a flight's error
+fn copy_error(error: &Arc<ServiceError>) -> ServiceError {
+    match &**error {
+        ServiceError::McpError(error) => ServiceError::McpError(error.clone()),
+        ServiceError::TransportClosed => ServiceError::TransportClosed,
+        ServiceError::UnexpectedResponse => ServiceError::UnexpectedResponse,
+        ServiceError::Cancelled { reason } => ServiceError::Cancelled {
+            reason: reason.clone(),
+        },
+        ServiceError::Timeout { timeout } => ServiceError::Timeout { timeout: *timeout },
+        _ => ServiceError::TransportSend(Box::new(SharedError(error.clone()))),
+    }
+}
+
+fn cancelled() -> ServiceError {
+    ServiceError::Cancelled {
+        reason: Some("cancelled by client".to_string()),
+    }
+}
+
+/// Key of a request to an upstream, or `None` when it cannot be serialized
+pub fn key(upstream: &str, request: &ClientRequest) -> Option<String> {
+    let mut request = serde_json::to_value(request).ok()?;
+    if let Some(params) = request.get_mut("params").and_then(Value::as_object_mut) {
+        params.remove("_meta");
+    }
+    Some(format!("{upstream}\n{}", canonical_json(&request)))
+}
+
+#[derive(Debug)]
+struct Flight {
+    waiters: AtomicUsize,
+    /// Cancels the upstream call once every waiter has left
+    ct: CancellationToken,
+    response: watch::Receiver<Option<Shared>>,
+}
+
+impl Flight {
+    /// Called with the flights locked, like [`Coalescer::leave`]
+    fn join(&self) -> bool {
+        // Joining a flight every waiter has left would only get its cancellation
+        if self.ct.is_cancelled() {
+            return false;
+        }
+        self.waiters.fetch_add(1, Ordering::AcqRel);
+        true
+    }
+}
+
+/// Requests in flight, by [`key`]
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+/// Removes a flight when its leader finishes, or is dropped
+struct Landing<'a> {
+    coalescer: &'a Coalescer,
+    key: &'a str,
+    flight: Arc<Flight>,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut flights) = self.coalescer.flights.lock() {
+            if flights
+                .get(self.key)
+                .is_some_and(|flight| Arc::ptr_eq(flight, &self.flight))
+            {
+                flights.remove(self.key);
+            }
+        }
+    }
+}
+
+impl Coalescer {
+    /// Stop waiting for a flight, cancelling it when no waiter is left
+    ///
+    /// The flight is removed in the same critical section, so a request joining it either
+    /// counts as a waiter first or starts a flight of its own.
+    fn leave(&self, key: &str, flight: &Arc<Flight>) {
+        let mut flights = self.flights.lock().ok();
+        if flight.waiters.fetch_sub(1, Ordering::AcqRel) == 1 {
+            flight.ct.cancel();
+            if let Some(flights) = &mut flights {
+                if flights
+                    .get(key)
+                    .is_some_and(|landing| Arc::ptr_eq(landing, flight))
+                {
+                    flights.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Run `call` for the request with `key`, or wait for the identical request in flight
+    ///
+    /// `call` gets the token that cancels the flight, rather than `ct`.
+    pub async fn run<F, Fut>(
+        &self,
+        key: String,
+        ct: &CancellationToken,
+        call: F,
+    ) -> Result<ServerResult, ServiceError>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = Result<ServerResult, ServiceError>>,
+    {
+        let (flight, sender) = {
+            let Ok(mut flights) = self.flights.lock() else {
+                return call(ct.clone()).await;
+            };
+            match flights.get(&key).filter(|flight| flight.join()) {
+                Some(flight) => (flight.clone(), None),
+                None => {
+                    let (sender, response) = watch::channel(None);
+                    let flight = Arc::new(Flight {
+                        waiters: AtomicUsize::new(1),
+                        ct: CancellationToken::new(),
+                        response,
+                    });
+                    flights.insert(key.clone(), flight.clone());
+                    (flight, Some(sender))
+                }
+            }
+        };
+
+        let Some(sender) = sender else {
+            debug!("Sharing the response of an identical request in flight");
+            let mut response = flight.response.clone();
+            return tokio::select! {
+                shared = response.wait_for(Option::is_some) => match shared.as_deref() {
+                    Ok(Some(Ok(result))) => Ok(result.clone()),
+                    Ok(Some(Err(error))) => Err(copy_error(error)),
+                    // The leader was dropped without answering
+                    _ => Err(ServiceError::TransportClosed),
+                },
+                () = ct.cancelled() => {
+                    self.leave(&key, &flight);
+                    Err(cancelled())
+                }
+            };
+        };
+
+        let landing = Landing {
+            coalescer: self,
+            key: &key,
+            flight: flight.clone(),
+        };
+        let call = call(flight.ct.clone());
+        tokio::pin!(call);
+        let result = tokio::select! {
+            result = &mut call => result,
+            () = ct.cancelled() => {
+                // Keep the call going for the other waiters, if any
+                self.leave(&key, &flight);
+                call.await
+            }
+        };
+        drop(landing);
+
+        // The leader's error is shared too, since a transport error cannot be copied
+        let shared = result.map_err(Arc::new);
+        let _ = sender.send(Some(shared.clone()));
+        if ct.is_cancelled() {
+            return Err(cancelled());
+        }
+        shared.map_err(|error| copy_error(&error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{rejected, Rejected};
+    use rmcp::model::{CallToolRequest, CallToolRequestParam, CallToolResult, Content, GetMeta};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn call(arguments: Value) -> ClientRequest {
+        ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+            name: "read_wiki_structure".into(),
+            arguments: arguments.as_object().cloned(),
+        }))
+    }
+
+    fn text(result: &ServerResult) -> String {
+        match result {
+            ServerResult::CallToolResult(result) => serde_json::to_string(&result.content).unwrap(),
+            _ => String::new(),
+        }
+    }
+
+    /// A call answering `answer` after a short wait, counting its executions
+    async fn slow_call(
+        calls: &AtomicUsize,
+        ct: CancellationToken,
+        answer: &str,
+    ) -> Result<ServerResult, ServiceError> {
+        calls.fetch_add(1, Ordering::Relaxed);
+        tokio::select! {
+            () = tokio::time::sleep(Duration::from_millis(50)) => Ok(
+                ServerResult::CallToolResult(CallToolResult::success(vec![Content::text(answer)]))
+            ),
+            () = ct.cancelled() => Err(cancelled()),
+        }
+    }
+
+    #[test]
+    fn test_keys_ignore_meta_and_key_order() {
+        let mut with_meta = call(json!({"repoName": "a/b", "page": 1}));
+        with_meta
+            .get_meta_mut()
+            .0
+            .insert("traceparent".to_string(), json!("00-abc"));
+        let reordered = call(json!({"page": 1, "repoName": "a/b"}));
+        assert_eq!(key("deepwiki", &with_meta), key("deepwiki", &reordered));
+        assert_ne!(key("deepwiki", &reordered), key("devin", &reordered));
+        assert_ne!(
+            key("deepwiki", &reordered),
+            key("deepwiki", &call(json!({"page": 2, "repoName": "a/b"})))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identical_requests_share_one_call() {
+        let coalescer = Coalescer::default();
+        let calls = AtomicUsize::new(0);
+        let ct = CancellationToken::new();
+        let run = |answer: &'static str| {
+            coalescer.run("key".to_string(), &ct, |ct| slow_call(&calls, ct, answer))
+        };
+        let (first, second) = tokio::join!(run("first"), run("second"));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(text(&first.unwrap()), text(&second.unwrap()));
+        assert!(coalescer.flights.lock().unwrap().is_empty());
+
+        // Once the flight has landed, the next request is sent again
+        run("third").await.unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_leader_still_answers_the_others() {
+        let coalescer = Coalescer::default();
+        let calls = AtomicUsize::new(0);
+        let leader_ct = CancellationToken::new();
+        let follower_ct = CancellationToken::new();
+        let leader = coalescer.run("key".to_string(), &leader_ct, |ct| {
+            slow_call(&calls, ct, "answer")
+        });
+        let follower = coalescer.run("key".to_string(), &follower_ct, |ct| {
+            slow_call(&calls, ct, "other")
+        });
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            leader_ct.cancel();
+        };
+        let (leader, follower, ()) = tokio::join!(leader, follower, cancel);
+        assert!(matches!(leader, Err(ServiceError::Cancelled { .. })));
+        assert!(text(&follower.unwrap()).contains("answer"));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_every_waiter_cancels_the_call() {
+        let coalescer = Coalescer::default();
+        let calls = AtomicUsize::new(0);
+        let ct = CancellationToken::new();
+        let first = coalescer.run("key".to_string(), &ct, |ct| slow_call(&calls, ct, "a"));
+        let second = coalescer.run("key".to_string(), &ct, |ct| slow_call(&calls, ct, "b"));
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            ct.cancel();
+        };
+        let started = std::time::Instant::now();
+        let (first, second, ()) = tokio::join!(first, second, cancel);
+        assert!(matches!(first, Err(ServiceError::Cancelled { .. })));
+        assert!(matches!(second, Err(ServiceError::Cancelled { .. })));
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_joining_while_the_last_waiter_leaves() {
+        let coalescer = Arc::new(Coalescer::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let leader_ct = CancellationToken::new();
+            let leader = tokio::spawn({
+                let (coalescer, calls, ct) = (coalescer.clone(), calls.clone(), leader_ct.clone());
+                async move {
+                    coalescer
+                        .run("key".to_string(), &ct, |ct| slow_call(&calls, ct, "a"))
+                        .await
+                }
+            });
+            while coalescer.flights.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            // Whether it joins before or after the leader leaves, the new caller gets an answer
+            let joiner = tokio::spawn({
+                let (coalescer, calls) = (coalescer.clone(), calls.clone());
+                async move {
+                    let ct = CancellationToken::new();
+                    coalescer
+                        .run("key".to_string(), &ct, |ct| slow_call(&calls, ct, "b"))
+                        .await
+                }
+            });
+            leader_ct.cancel();
+            assert!(matches!(
+                leader.await.unwrap(),
+                Err(ServiceError::Cancelled { .. })
+            ));
+            assert!(joiner.await.unwrap().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shares_errors_with_their_cause() {
+        let coalescer = Coalescer::default();
+        let ct = CancellationToken::new();
+        let fail = |_ct| async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Err(ServiceError::TransportSend(Box::new(Rejected {
+                scope: "deepwiki".to_string(),
+                queued: 16,
+            })))
+        };
+        let (first, second) = tokio::join!(
+            coalescer.run("key".to_string(), &ct, fail),
+            coalescer.run("key".to_string(), &ct, fail)
+        );
+        for error in [first.unwrap_err(), second.unwrap_err()] {
+            assert_eq!(rejected(&error).unwrap().scope, "deepwiki");
+            assert!(error.to_string().contains("Too many requests to deepwiki"));
+        }
+    }
+}
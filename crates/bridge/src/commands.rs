@@ -0,0 +1,206 @@
+//! Standalone subcommands for querying an MCP server from the terminal
+//!
+//! These reuse the same transport auto-detection and authentication path as the proxy,
+//! but issue a single request and print the result to stdout.
+
+use crate::cache::ResponseCache;
+use crate::cli::{CacheAction, OutputFormat};
+use crate::connect_remote;
+use crate::settings::BridgeSettings;
+use anyhow::{anyhow, Result};
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
+use serde_json::{Map, Value};
+use std::fmt::Write as _;
+use tracing::warn;
+
+/// Name of the `DeepWiki` tool used by the `ask` shortcut
+pub const ASK_QUESTION_TOOL: &str = "ask_question";
+
+/// List every tool exposed by the endpoint
+pub async fn list_tools(endpoint_url: &str, output: OutputFormat) -> Result<()> {
+    let client = connect_remote(endpoint_url, None).await?;
+    let tools = client.list_all_tools().await;
+    shutdown(client).await;
+
+    let tools = tools.map_err(|e| anyhow!("Failed to list tools: {}", e))?;
+    match output {
+        OutputFormat::Text => print!("{}", format_tools_text(&tools)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&tools)?),
+    }
+    Ok(())
+}
+
+/// Invoke a tool with the given arguments and print its result
+pub async fn call_tool(
+    endpoint_url: &str,
+    tool: &str,
+    arguments: Map<String, Value>,
+    output: OutputFormat,
+) -> Result<()> {
+    let client = connect_remote(endpoint_url, None).await?;
+    let result = client
+        .call_tool(CallToolRequestParam {
+            name: tool.to_string().into(),
+            arguments: Some(arguments),
+        })
+        .await;
+    shutdown(client).await;
+
+    let result = result.map_err(|e| anyhow!("Tool call '{}' failed: {}", tool, e))?;
+    match output {
+        OutputFormat::Text => println!("{}", format_call_result_text(&result)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+    }
+
+    if result.is_error == Some(true) {
+        return Err(anyhow!("Tool '{}' reported an error", tool));
+    }
+    Ok(())
+}
+
+/// Ask a question about a repository via `ask_question`
+pub async fn ask(
+    endpoint_url: &str,
+    repo: &str,
+    question: &str,
+    output: OutputFormat,
+) -> Result<()> {
+    call_tool(
+        endpoint_url,
+        ASK_QUESTION_TOOL,
+        ask_arguments(repo, question),
+        output,
+    )
+    .await
+}
+
+fn ask_arguments(repo: &str, question: &str) -> Map<String, Value> {
+    let mut arguments = Map::new();
+    arguments.insert("repoName".to_string(), Value::String(repo.to_string()));
+    arguments.insert("question".to_string(), Value::String(question.to_string()));
+    arguments
+}
+
+/// Clear the response cache or print statistics about it
+pub fn cache(action: CacheAction, output: OutputFormat) -> Result<()> {
+    let settings = BridgeSettings::from_env();
+    let cache = ResponseCache::open(&settings.cache)
+        .ok_or_else(|| anyhow!("No cache directory available on this platform"))?;
+
+    match action {
+        CacheAction::Clear => {
+            let removed = cache.clear()?;
+            match output {
+                OutputFormat::Text => println!(
+                    "Removed {removed} cached responses from {}",
+                    cache.dir().display()
+                ),
+                OutputFormat::Json => println!("{}", serde_json::json!({ "removed": removed })),
+            }
+        }
+        CacheAction::Stats => {
+            let stats = cache.stats();
+            match output {
+                OutputFormat::Text => {
+                    println!("Directory: {}", stats.dir.display());
+                    println!("Entries:   {} ({} expired)", stats.entries, stats.expired);
+                    println!("Size:      {} bytes", stats.total_bytes);
+                    for (tool, count) in &stats.by_tool {
+                        println!("  {tool:<28} {count}");
+                    }
+                }
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn shutdown(client: crate::RemoteClient) {
+    if let Err(e) = client.cancel().await {
+        warn!("Failed to close remote connection cleanly: {}", e);
+    }
+}
+
+/// Render tools as a human-readable listing with their input schemas
+fn format_tools_text(tools: &[Tool]) -> String {
+    let mut out = String::new();
+    for tool in tools {
+        let _ = writeln!(out, "{}", tool.name);
+        if let Some(description) = &tool.description {
+            for line in description.lines() {
+                let _ = writeln!(out, "    {line}");
+            }
+        }
+        let schema = serde_json::to_string_pretty(tool.input_schema.as_ref()).unwrap_or_default();
+        let _ = writeln!(out, "    schema:");
+        for line in schema.lines() {
+            let _ = writeln!(out, "        {line}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Render a tool result as plain text, falling back to JSON for non-text content
+pub fn format_call_result_text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .map(format_content_text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_content_text(content: &Content) -> String {
+    content.as_text().map_or_else(
+        || serde_json::to_string_pretty(content).unwrap_or_default(),
+        |text| text.text.clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_ask_arguments() {
+        assert_eq!(
+            Value::Object(ask_arguments("tokio-rs/tokio", "What is a runtime?")),
+            json!({"repoName": "tokio-rs/tokio", "question": "What is a runtime?"})
+        );
+    }
+
+    #[test]
+    fn test_format_tools_text() {
+        let schema = json!({"type": "object", "properties": {"repoName": {"type": "string"}}});
+        let Value::Object(schema) = schema else {
+            unreachable!()
+        };
+        let tools = [Tool {
+            name: "read_wiki_structure".into(),
+            description: Some("Get a list of documentation topics".into()),
+            input_schema: Arc::new(schema),
+            annotations: None,
+        }];
+
+        let text = format_tools_text(&tools);
+        assert!(text.starts_with("read_wiki_structure\n"));
+        assert!(text.contains("    Get a list of documentation topics\n"));
+        assert!(text.contains("\"repoName\""));
+    }
+
+    #[test]
+    fn test_format_call_result_text() {
+        let result = CallToolResult::success(vec![
+            Content::text("first"),
+            Content::image("aGVsbG8=", "image/png"),
+        ]);
+
+        let text = format_call_result_text(&result);
+        assert!(text.starts_with("first\n"));
+        assert!(text.contains("image/png"));
+    }
+}
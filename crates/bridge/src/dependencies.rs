@@ -0,0 +1,680 @@
+//! Project dependencies and the GitHub repositories behind them
+//!
+//! Dependencies are read from `Cargo.lock`, `package-lock.json`, `go.mod` and `pyproject.toml`
+//! in the project root. Repositories are resolved locally where possible (git sources, Go module
+//! paths, the Cargo registry sources and `node_modules`), and otherwise looked up on the
+//! package registry the first time they are needed.
+//!
+//! This backs the bridge-provided [`DEPENDENCIES_TOOL`] and lets DeepWiki tools accept a
+//! package name such as `tokio` in place of `owner/repo`.
+
+use crate::project::{cargo_repository, package_json_repository, parse_github_url};
+use anyhow::{anyhow, Result};
+use rmcp::model::{JsonObject, Tool};
+use serde_json::{json, Value};
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::debug;
+
+/// Name of the synthetic tool listed next to the upstream tools
+pub const DEPENDENCIES_TOOL: &str = "list_project_dependencies";
+
+const MANIFESTS: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "go.mod",
+    "pyproject.toml",
+];
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecosystem {
+    Cargo,
+    Npm,
+    Go,
+    Python,
+}
+
+impl Ecosystem {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "cargo" | "rust" | "crates" => Some(Self::Cargo),
+            "npm" | "node" | "javascript" => Some(Self::Npm),
+            "go" | "golang" => Some(Self::Go),
+            "python" | "pypi" | "pip" => Some(Self::Python),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Ecosystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cargo => "cargo",
+            Self::Npm => "npm",
+            Self::Go => "go",
+            Self::Python => "pypi",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub version: Option<String>,
+    pub ecosystem: Ecosystem,
+    /// `owner/repo`, once known
+    pub repository: Option<String>,
+}
+
+impl Dependency {
+    fn new(ecosystem: Ecosystem, name: &str, version: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            ecosystem,
+            repository: None,
+        }
+    }
+
+    /// Whether `name` refers to this package, ignoring case and Python's `-`/`_`/`.` spelling
+    fn matches(&self, name: &str) -> bool {
+        let normalize = |s: &str| s.to_ascii_lowercase().replace(['_', '.'], "-");
+        let wanted = normalize(name);
+        normalize(&self.name) == wanted
+            || (self.ecosystem == Ecosystem::Go
+                && self
+                    .name
+                    .rsplit('/')
+                    .next()
+                    .is_some_and(|last| normalize(last) == wanted))
+    }
+}
+
+/// The dependencies of the current project, shared between the handler and background tasks
+#[derive(Clone, Default)]
+pub struct ProjectDependencies {
+    inner: Arc<RwLock<Loaded>>,
+    http: reqwest::Client,
+}
+
+#[derive(Default)]
+struct Loaded {
+    root: Option<PathBuf>,
+    dependencies: Vec<Dependency>,
+}
+
+impl ProjectDependencies {
+    /// Load from the nearest ancestor of `dir` that has a supported manifest
+    pub fn load(dir: &Path) -> Self {
+        let dependencies = Self::default();
+        dependencies.load_from(dir);
+        dependencies
+    }
+
+    /// Replace the loaded dependencies with those of the project containing `dir`
+    pub fn load_from(&self, dir: &Path) -> bool {
+        let Some(root) = dir
+            .ancestors()
+            .find(|dir| MANIFESTS.iter().any(|name| dir.join(name).is_file()))
+        else {
+            return false;
+        };
+
+        let found = read_dependencies(root);
+        debug!(
+            "Loaded {} dependencies from {}",
+            found.len(),
+            root.display()
+        );
+        if let Ok(mut loaded) = self.inner.write() {
+            *loaded = Loaded {
+                root: Some(root.to_path_buf()),
+                dependencies: found,
+            };
+        }
+        true
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.inner.read().is_ok_and(|loaded| loaded.root.is_some())
+    }
+
+    fn snapshot(&self) -> (Option<PathBuf>, Vec<Dependency>) {
+        self.inner
+            .read()
+            .map(|loaded| (loaded.root.clone(), loaded.dependencies.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Map a package name to its repository, consulting the registry if needed
+    pub async fn resolve(&self, name: &str) -> Result<String> {
+        let (_, dependencies) = self.snapshot();
+        let dependency = dependencies
+            .iter()
+            .find(|dependency| dependency.matches(name))
+            .ok_or_else(|| {
+                anyhow!(
+                    "'{name}' is neither owner/repo nor a dependency of this project. \
+                     Use list_project_dependencies to see known packages."
+                )
+            })?;
+
+        if let Some(repository) = &dependency.repository {
+            return Ok(repository.clone());
+        }
+        let repository = self.lookup_registry(dependency).await?.ok_or_else(|| {
+            anyhow!(
+                "Could not find a GitHub repository for {} package '{}'; pass owner/repo instead",
+                dependency.ecosystem,
+                dependency.name
+            )
+        })?;
+
+        if let Ok(mut loaded) = self.inner.write() {
+            for cached in loaded
+                .dependencies
+                .iter_mut()
+                .filter(|d| d.ecosystem == dependency.ecosystem && d.name == dependency.name)
+            {
+                cached.repository = Some(repository.clone());
+            }
+        }
+        Ok(repository)
+    }
+
+    async fn lookup_registry(&self, dependency: &Dependency) -> Result<Option<String>> {
+        let url = match dependency.ecosystem {
+            Ecosystem::Cargo => format!("https://crates.io/api/v1/crates/{}", dependency.name),
+            Ecosystem::Npm => format!("https://registry.npmjs.org/{}", dependency.name),
+            Ecosystem::Python => format!("https://pypi.org/pypi/{}/json", dependency.name),
+            Ecosystem::Go => return Ok(None),
+        };
+        debug!("Looking up repository for {} at {}", dependency.name, url);
+
+        let body = self
+            .http
+            .get(&url)
+            .header(
+                "User-Agent",
+                concat!("deepwiki-mcp-bridge/", env!("CARGO_PKG_VERSION")),
+            )
+            .timeout(REGISTRY_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| anyhow!("Registry lookup for '{}' failed: {}", dependency.name, e))?
+            .text()
+            .await?;
+        let metadata: Value = serde_json::from_str(&body)?;
+        Ok(registry_repository(dependency.ecosystem, &metadata))
+    }
+
+    /// Run the [`DEPENDENCIES_TOOL`] with its JSON arguments, returning the result text
+    pub fn call(&self, arguments: &JsonObject) -> Result<String> {
+        let ecosystem = match arguments.get("ecosystem").and_then(Value::as_str) {
+            Some(value) => Some(
+                Ecosystem::parse(value).ok_or_else(|| anyhow!("Unknown ecosystem '{value}'"))?,
+            ),
+            None => None,
+        };
+        let query = arguments
+            .get("query")
+            .and_then(Value::as_str)
+            .map(str::to_ascii_lowercase);
+
+        let (root, dependencies) = self.snapshot();
+        let Some(root) = root else {
+            return Ok(
+                "No Cargo.lock, package-lock.json, go.mod or pyproject.toml found in the project."
+                    .to_string(),
+            );
+        };
+
+        let selected: Vec<&Dependency> = dependencies
+            .iter()
+            .filter(|d| ecosystem.is_none_or(|e| d.ecosystem == e))
+            .filter(|d| {
+                query
+                    .as_deref()
+                    .is_none_or(|q| d.name.to_ascii_lowercase().contains(q))
+            })
+            .collect();
+        Ok(format_dependencies(&root, &selected))
+    }
+}
+
+/// Definition of the [`DEPENDENCIES_TOOL`] advertised in `tools/list`
+pub fn dependencies_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "ecosystem": {
+                "type": "string",
+                "enum": ["cargo", "npm", "go", "pypi"],
+                "description": "Only list dependencies from this ecosystem"
+            },
+            "query": {
+                "type": "string",
+                "description": "Only list packages whose name contains this text"
+            }
+        }
+    });
+    Tool::new(
+        DEPENDENCIES_TOOL,
+        "List the current project's dependencies from its lockfiles, with their GitHub \
+         repositories where known. Any of these package names can be passed as repoName \
+         to the DeepWiki tools.",
+        schema.as_object().cloned().unwrap_or_default(),
+    )
+}
+
+fn format_dependencies(root: &Path, dependencies: &[&Dependency]) -> String {
+    let mut text = format!(
+        "{} dependencies of {}\n",
+        dependencies.len(),
+        root.display()
+    );
+    for dependency in dependencies {
+        let _ = write!(
+            text,
+            "\n{} {} {}",
+            dependency.ecosystem,
+            dependency.name,
+            dependency.version.as_deref().unwrap_or("")
+        );
+        if let Some(repository) = &dependency.repository {
+            let _ = write!(text, " -> {repository}");
+        }
+    }
+    text.push('\n');
+    text
+}
+
+/// Parse every supported manifest in `root` and resolve what can be resolved locally
+fn read_dependencies(root: &Path) -> Vec<Dependency> {
+    let read = |name: &str| fs::read_to_string(root.join(name)).ok();
+    let mut dependencies = Vec::new();
+
+    if let Some(lock) = read("Cargo.lock") {
+        dependencies.extend(parse_cargo_lock(&lock));
+    }
+    if let Some(lock) = read("package-lock.json") {
+        dependencies.extend(parse_package_lock(&lock));
+    }
+    if let Some(go_mod) = read("go.mod") {
+        dependencies.extend(parse_go_mod(&go_mod));
+    }
+    if let Some(pyproject) = read("pyproject.toml") {
+        dependencies.extend(parse_pyproject(&pyproject));
+    }
+
+    let cargo_sources = cargo_registry_sources();
+    for dependency in &mut dependencies {
+        if dependency.repository.is_none() {
+            dependency.repository = local_repository(root, &cargo_sources, dependency);
+        }
+    }
+    dependencies
+}
+
+/// Repository known without network access
+fn local_repository(
+    root: &Path,
+    cargo_sources: &[PathBuf],
+    dependency: &Dependency,
+) -> Option<String> {
+    match dependency.ecosystem {
+        Ecosystem::Cargo => {
+            let version = dependency.version.as_deref()?;
+            let dir_name = format!("{}-{}", dependency.name, version);
+            cargo_sources
+                .iter()
+                .find_map(|registry| cargo_repository(&registry.join(&dir_name)))
+        }
+        Ecosystem::Npm => {
+            package_json_repository(&root.join("node_modules").join(&dependency.name))
+        }
+        Ecosystem::Go => go_module_repository(&dependency.name),
+        Ecosystem::Python => None,
+    }
+}
+
+/// Unpacked crate directories under `$CARGO_HOME/registry/src`
+fn cargo_registry_sources() -> Vec<PathBuf> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")));
+    cargo_home
+        .and_then(|home| fs::read_dir(home.join("registry").join("src")).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect()
+}
+
+fn go_module_repository(module: &str) -> Option<String> {
+    let mut parts = module.split('/');
+    match parts.next()? {
+        "github.com" => {
+            let owner = parts.next()?;
+            let repo = parts.next()?;
+            Some(format!("{owner}/{repo}"))
+        }
+        "golang.org" if parts.next()? == "x" => Some(format!("golang/{}", parts.next()?)),
+        _ => None,
+    }
+}
+
+/// Extract the repository from crates.io, npm or PyPI package metadata
+fn registry_repository(ecosystem: Ecosystem, metadata: &Value) -> Option<String> {
+    match ecosystem {
+        Ecosystem::Cargo => {
+            let krate = metadata.get("crate")?;
+            ["repository", "homepage"]
+                .iter()
+                .filter_map(|key| krate.get(*key).and_then(Value::as_str))
+                .find_map(parse_github_url)
+        }
+        Ecosystem::Npm => {
+            let repository = metadata.get("repository")?;
+            let url = repository
+                .as_str()
+                .or_else(|| repository.get("url").and_then(Value::as_str))?;
+            parse_github_url(url)
+        }
+        Ecosystem::Python => {
+            let info = metadata.get("info")?;
+            let project_urls = info
+                .get("project_urls")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flat_map(|urls| urls.values());
+            project_urls
+                .chain(info.get("home_page"))
+                .filter_map(Value::as_str)
+                .find_map(parse_github_url)
+        }
+        Ecosystem::Go => None,
+    }
+}
+
+fn parse_cargo_lock(lock: &str) -> Vec<Dependency> {
+    // `[[package]]` tables as (name, version, source)
+    let mut packages: Vec<(String, String, Option<String>)> = Vec::new();
+    let mut in_package = false;
+
+    for line in lock.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[[package]]";
+            if in_package {
+                packages.push((String::new(), String::new(), None));
+            }
+        } else if let (true, Some((key, value))) = (in_package, line.split_once(" = ")) {
+            let Some((name, version, source)) = packages.last_mut() else {
+                continue;
+            };
+            let value = value.trim_matches('"').to_string();
+            match key {
+                "name" => *name = value,
+                "version" => *version = value,
+                "source" => *source = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    packages
+        .into_iter()
+        // Workspace members have no source
+        .filter_map(|(name, version, source)| {
+            let source = source?;
+            let mut dependency = Dependency::new(Ecosystem::Cargo, &name, Some(&version));
+            if let Some(url) = source.strip_prefix("git+") {
+                dependency.repository = url.split(['?', '#']).next().and_then(parse_github_url);
+            }
+            Some(dependency)
+        })
+        .collect()
+}
+
+fn parse_package_lock(lock: &str) -> Vec<Dependency> {
+    let Ok(lock) = serde_json::from_str::<Value>(lock) else {
+        return Vec::new();
+    };
+    let mut dependencies: Vec<Dependency> = Vec::new();
+    let mut push = |name: &str, version: Option<&str>| {
+        if !dependencies.iter().any(|d| d.name == name) {
+            dependencies.push(Dependency::new(Ecosystem::Npm, name, version));
+        }
+    };
+
+    if let Some(packages) = lock.get("packages").and_then(Value::as_object) {
+        // lockfileVersion 2 and 3: keys are install paths such as `node_modules/a/node_modules/b`
+        for (path, package) in packages {
+            if let Some((_, name)) = path.rsplit_once("node_modules/") {
+                push(name, package.get("version").and_then(Value::as_str));
+            }
+        }
+    } else if let Some(packages) = lock.get("dependencies").and_then(Value::as_object) {
+        // lockfileVersion 1
+        for (name, package) in packages {
+            push(name, package.get("version").and_then(Value::as_str));
+        }
+    }
+    dependencies
+}
+
+fn parse_go_mod(go_mod: &str) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut in_block = false;
+
+    for line in go_mod.lines().map(str::trim) {
+        let requirement = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if line == "require (" {
+            in_block = true;
+            continue;
+        } else if let Some(requirement) = line.strip_prefix("require ") {
+            requirement
+        } else {
+            continue;
+        };
+
+        let mut fields = requirement.split_whitespace();
+        if let (Some(module), Some(version)) = (fields.next(), fields.next()) {
+            if !module.starts_with("//") {
+                dependencies.push(Dependency::new(Ecosystem::Go, module, Some(version)));
+            }
+        }
+    }
+    dependencies
+}
+
+/// PEP 621 `[project] dependencies` and Poetry's `[tool.poetry.dependencies]`
+fn parse_pyproject(pyproject: &str) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut section = "";
+    let mut in_array = false;
+
+    for line in pyproject.lines().map(str::trim) {
+        if line.starts_with('[') && !in_array {
+            section = line;
+            continue;
+        }
+
+        if section == "[project]" {
+            let items = if in_array {
+                line
+            } else if let Some(rest) = line
+                .strip_prefix("dependencies")
+                .and_then(|rest| rest.trim_start().strip_prefix('='))
+            {
+                in_array = true;
+                rest.trim_start().trim_start_matches('[')
+            } else {
+                continue;
+            };
+            if items.contains(']') {
+                in_array = false;
+            }
+            for requirement in items.split(',') {
+                let requirement = requirement
+                    .trim()
+                    .trim_end_matches(']')
+                    .trim_matches(['"', '\'']);
+                let name: String = requirement
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+                    .collect();
+                if !name.is_empty() {
+                    let version = requirement[name.len()..].trim();
+                    let version = (!version.is_empty()).then_some(version);
+                    dependencies.push(Dependency::new(Ecosystem::Python, &name, version));
+                }
+            }
+        } else if section == "[tool.poetry.dependencies]" {
+            if let Some((name, version)) = line.split_once('=') {
+                let name = name.trim();
+                if name != "python" && !name.is_empty() && !name.starts_with('#') {
+                    let version = version.trim().trim_matches('"');
+                    dependencies.push(Dependency::new(Ecosystem::Python, name, Some(version)));
+                }
+            }
+        }
+    }
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cargo_lock() {
+        let lock = r#"
+version = 4
+
+[[package]]
+name = "my-app"
+version = "0.1.0"
+
+[[package]]
+name = "tokio"
+version = "1.45.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = [
+ "bytes",
+]
+
+[[package]]
+name = "zed_extension_api"
+version = "0.6.0"
+source = "git+https://github.com/zed-industries/zed?branch=main#abc123"
+"#;
+        let dependencies = parse_cargo_lock(lock);
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(dependencies[0].name, "tokio");
+        assert_eq!(dependencies[0].version.as_deref(), Some("1.45.0"));
+        assert_eq!(
+            dependencies[1].repository.as_deref(),
+            Some("zed-industries/zed")
+        );
+    }
+
+    #[test]
+    fn test_parse_package_lock() {
+        let lock = r#"{
+            "lockfileVersion": 3,
+            "packages": {
+                "": {"name": "app"},
+                "node_modules/react": {"version": "18.3.1"},
+                "node_modules/@types/node": {"version": "20.1.0"},
+                "node_modules/a/node_modules/react": {"version": "17.0.0"}
+            }
+        }"#;
+        let names: Vec<String> = parse_package_lock(lock)
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["@types/node", "react"]);
+    }
+
+    #[test]
+    fn test_parse_go_mod_and_pyproject() {
+        let go_mod = "module example.com/app\n\ngo 1.22\n\n\
+            require github.com/spf13/cobra v1.8.0\n\
+            require (\n\tgolang.org/x/net v0.25.0 // indirect\n\tgopkg.in/yaml.v3 v3.0.1\n)\n";
+        let dependencies = parse_go_mod(go_mod);
+        assert_eq!(dependencies.len(), 3);
+        assert_eq!(
+            go_module_repository(&dependencies[0].name).as_deref(),
+            Some("spf13/cobra")
+        );
+        assert_eq!(
+            go_module_repository(&dependencies[1].name).as_deref(),
+            Some("golang/net")
+        );
+        assert_eq!(go_module_repository(&dependencies[2].name), None);
+
+        let pyproject = "[project]\nname = \"app\"\ndependencies = [\n  \"requests>=2.31\",\n  \
+            \"pydantic[email]\",\n]\n\n[tool.poetry.dependencies]\npython = \"^3.11\"\nrich = \"^13\"\n";
+        let dependencies = parse_pyproject(pyproject);
+        let names: Vec<&str> = dependencies.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["requests", "pydantic", "rich"]);
+        assert_eq!(dependencies[0].version.as_deref(), Some(">=2.31"));
+    }
+
+    #[test]
+    fn test_registry_metadata() {
+        let krate = json!({"crate": {"repository": "https://github.com/tokio-rs/tokio"}});
+        assert_eq!(
+            registry_repository(Ecosystem::Cargo, &krate).as_deref(),
+            Some("tokio-rs/tokio")
+        );
+        let npm = json!({"repository": {"type": "git", "url": "git+https://github.com/facebook/react.git"}});
+        assert_eq!(
+            registry_repository(Ecosystem::Npm, &npm).as_deref(),
+            Some("facebook/react")
+        );
+        let pypi = json!({"info": {"project_urls": {"Documentation": "https://docs.pydantic.dev",
+            "Source": "https://github.com/pydantic/pydantic"}, "home_page": null}});
+        assert_eq!(
+            registry_repository(Ecosystem::Python, &pypi).as_deref(),
+            Some("pydantic/pydantic")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("go.mod"),
+            "module app\n\nrequire github.com/spf13/cobra v1.8.0\n",
+        )
+        .unwrap();
+        let nested = dir.path().join("cmd");
+        fs::create_dir(&nested).unwrap();
+
+        let dependencies = ProjectDependencies::load(&nested);
+        assert!(dependencies.is_loaded());
+        assert_eq!(dependencies.resolve("cobra").await.unwrap(), "spf13/cobra");
+        assert!(dependencies.resolve("left-pad").await.is_err());
+
+        let text = dependencies.call(&JsonObject::new()).unwrap();
+        assert!(text.contains("go github.com/spf13/cobra v1.8.0 -> spf13/cobra"));
+        let args = json!({"ecosystem": "cargo"}).as_object().cloned().unwrap();
+        assert!(dependencies
+            .call(&args)
+            .unwrap()
+            .starts_with("0 dependencies"));
+    }
+}
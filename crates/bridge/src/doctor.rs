@@ -0,0 +1,211 @@
+//! The `doctor` subcommand: check the bridge's configuration and upstreams
+//!
+//! Validates the settings, connects to every upstream and lists its tools. Circuit breakers
+//! live in the running bridge, so their states are read from its metrics endpoint (the
+//! `--metrics-addr` given here or `metrics_addr` in the settings) when it serves one.
+
+use crate::breaker::CircuitState;
+use crate::cli::OutputFormat;
+use crate::commands::shutdown;
+use crate::metrics::parse_circuit_states;
+use crate::settings::{BridgeSettings, UpstreamSettings, SETTINGS_ENV_VAR};
+use crate::{connect_upstream, upstream, validate_url};
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Time allowed for connecting to an upstream and listing its tools
+const CHECK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Time allowed for scraping a running bridge's metrics
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+struct Report {
+    /// Why the settings are invalid, if they are
+    settings_error: Option<String>,
+    /// Where circuit states were read from, or why they could not be
+    circuits_source: String,
+    upstreams: Vec<UpstreamReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct UpstreamReport {
+    name: String,
+    url: String,
+    latency_ms: Option<u128>,
+    tools: Option<usize>,
+    error: Option<String>,
+    /// `None` when no running bridge reported it
+    circuit: Option<&'static str>,
+}
+
+/// Run every check and print the report; fails when an upstream cannot be reached
+pub async fn run(
+    endpoint_url: &str,
+    metrics_addr: Option<SocketAddr>,
+    output: OutputFormat,
+) -> Result<()> {
+    let (settings, settings_error) = match std::env::var(SETTINGS_ENV_VAR) {
+        Ok(raw) => match BridgeSettings::from_json(&raw) {
+            Ok(settings) => (settings, None),
+            Err(e) => (BridgeSettings::default(), Some(format!("{e:#}"))),
+        },
+        Err(_) => (BridgeSettings::default(), None),
+    };
+    let upstreams = if settings.upstreams.is_empty() {
+        vec![UpstreamSettings::from_url(endpoint_url)]
+    } else {
+        settings.upstreams.clone()
+    };
+    let settings_error = settings_error.or_else(|| {
+        upstream::validate_upstreams(&upstreams)
+            .and_then(|()| upstreams.iter().try_for_each(|u| validate_url(&u.url)))
+            .err()
+            .map(|e| e.to_string())
+    });
+
+    let (circuits, circuits_source) = match metrics_addr.or(settings.metrics_addr) {
+        Some(addr) => match scrape_circuits(addr).await {
+            Ok(circuits) => (circuits, format!("http://{addr}/metrics")),
+            Err(e) => (BTreeMap::new(), format!("unavailable: {e}")),
+        },
+        None => (
+            BTreeMap::new(),
+            "unavailable: no running bridge's --metrics-addr given".to_string(),
+        ),
+    };
+
+    let upstreams = join_all(upstreams.iter().map(check_upstream)).await;
+    let report = Report {
+        settings_error,
+        circuits_source,
+        upstreams: upstreams
+            .into_iter()
+            .map(|mut upstream| {
+                upstream.circuit = circuits.get(&upstream.name).map(|state| state.name());
+                upstream
+            })
+            .collect(),
+    };
+
+    match output {
+        OutputFormat::Text => print!("{}", format_report_text(&report)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    let failed = report
+        .upstreams
+        .iter()
+        .filter(|u| u.error.is_some())
+        .count();
+    if report.settings_error.is_some() {
+        return Err(anyhow!("Invalid {}", SETTINGS_ENV_VAR));
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} upstreams failed their check",
+            report.upstreams.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Connect to an upstream and list its tools
+async fn check_upstream(upstream: &UpstreamSettings) -> UpstreamReport {
+    let started = Instant::now();
+    let check = async {
+        let client = connect_upstream(upstream, None, None, None).await?;
+        let tools = client.list_all_tools().await;
+        shutdown(client).await;
+        tools.map_err(|e| anyhow!("Failed to list tools: {}", e))
+    };
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("No answer within {}s", CHECK_TIMEOUT.as_secs())));
+    let (latency_ms, tools, error) = match result {
+        Ok(tools) => (Some(started.elapsed().as_millis()), Some(tools.len()), None),
+        Err(e) => (None, None, Some(e.to_string())),
+    };
+    UpstreamReport {
+        name: upstream.name.clone(),
+        url: upstream.url.clone(),
+        latency_ms,
+        tools,
+        error,
+        circuit: None,
+    }
+}
+
+/// Circuit states reported by a running bridge's metrics endpoint
+async fn scrape_circuits(addr: SocketAddr) -> Result<BTreeMap<String, CircuitState>> {
+    let client = reqwest::Client::builder().timeout(SCRAPE_TIMEOUT).build()?;
+    let text = client
+        .get(format!("http://{addr}/metrics"))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| anyhow!("Cannot read metrics from {}: {}", addr, e))?
+        .text()
+        .await?;
+    Ok(parse_circuit_states(&text))
+}
+
+fn format_report_text(report: &Report) -> String {
+    let mut out = String::new();
+    let settings = report.settings_error.as_deref().unwrap_or("ok");
+    let _ = writeln!(out, "Settings:  {settings}");
+    let _ = writeln!(out, "Circuits:  {}", report.circuits_source);
+    for upstream in &report.upstreams {
+        let status = match (&upstream.error, upstream.latency_ms, upstream.tools) {
+            (Some(error), ..) => format!("FAILED: {error}"),
+            (None, Some(latency), Some(tools)) => format!("ok ({tools} tools in {latency} ms)"),
+            (None, ..) => "ok".to_string(),
+        };
+        let circuit = upstream.circuit.unwrap_or("unknown");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}  {}", upstream.name, upstream.url);
+        let _ = writeln!(out, "    connection: {status}");
+        let _ = writeln!(out, "    circuit:    {circuit}");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_report() {
+        let report = Report {
+            settings_error: None,
+            circuits_source: "http://127.0.0.1:9464/metrics".to_string(),
+            upstreams: vec![
+                UpstreamReport {
+                    name: "deepwiki".to_string(),
+                    url: "https://mcp.deepwiki.com/mcp".to_string(),
+                    latency_ms: Some(312),
+                    tools: Some(3),
+                    error: None,
+                    circuit: Some("closed"),
+                },
+                UpstreamReport {
+                    name: "devin".to_string(),
+                    url: "https://mcp.devin.ai/mcp".to_string(),
+                    latency_ms: None,
+                    tools: None,
+                    error: Some("connection refused".to_string()),
+                    circuit: None,
+                },
+            ],
+        };
+        let text = format_report_text(&report);
+        assert!(text.starts_with("Settings:  ok\nCircuits:  http://127.0.0.1:9464/metrics\n"));
+        assert!(text.contains("    connection: ok (3 tools in 312 ms)\n    circuit:    closed"));
+        assert!(text.contains("    connection: FAILED: connection refused"));
+        assert!(text.contains("    circuit:    unknown"));
+    }
+}
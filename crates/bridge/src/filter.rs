@@ -0,0 +1,120 @@
+//! Allow and deny lists for the tools, prompts and resources the bridge exposes
+//!
+//! Each list holds glob patterns where `*` matches any run of characters (including `/`) and
+//! `?` matches a single character. A name is exposed when it matches an `allow` pattern (or
+//! `allow` is empty) and matches no `deny` pattern. Tools and prompts are matched by name,
+//! resources by URI and resource templates by URI template. Tool patterns match the upstream
+//! names, before any renaming.
+
+use crate::settings::{BridgeSettings, FilterSettings};
+
+/// Compiled `allow` / `deny` patterns for one kind of item
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl NameFilter {
+    pub fn new(settings: &FilterSettings) -> Self {
+        Self {
+            allow: settings.allow.clone(),
+            deny: settings.deny.clone(),
+        }
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|p| glob_match(p, name));
+        allowed && !self.deny.iter().any(|p| glob_match(p, name))
+    }
+}
+
+/// Filters for everything the bridge exposes to Zed
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    pub tools: NameFilter,
+    pub prompts: NameFilter,
+    pub resources: NameFilter,
+}
+
+impl AccessPolicy {
+    pub fn from_settings(settings: &BridgeSettings) -> Self {
+        Self {
+            tools: NameFilter::new(&settings.tools.filter),
+            prompts: NameFilter::new(&settings.prompts),
+            resources: NameFilter::new(&settings.resources),
+        }
+    }
+}
+
+/// Match `text` against a glob `pattern` supporting `*` and `?`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(allow: &[&str], deny: &[&str]) -> NameFilter {
+        NameFilter::new(&FilterSettings {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("read_wiki_*", "read_wiki_contents"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("ask_?uestion", "ask_question"));
+        assert!(glob_match("*wiki*", "search_cached_wiki"));
+        assert!(glob_match("deepwiki://*/docs", "deepwiki://a/b/docs"));
+        assert!(!glob_match("read_wiki_*", "ask_question"));
+        assert!(!glob_match("ask", "ask_question"));
+        assert!(!glob_match("a*b", "acbd"));
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let everything = NameFilter::default();
+        assert!(everything.is_allowed("ask_question"));
+
+        let read_only = filter(&[], &["ask_question"]);
+        assert!(!read_only.is_allowed("ask_question"));
+        assert!(read_only.is_allowed("read_wiki_structure"));
+
+        // Deny wins over allow
+        let wiki_only = filter(&["read_wiki_*", "search_*"], &["read_wiki_contents"]);
+        assert!(wiki_only.is_allowed("read_wiki_structure"));
+        assert!(wiki_only.is_allowed("search_cached_wiki"));
+        assert!(!wiki_only.is_allowed("read_wiki_contents"));
+        assert!(!wiki_only.is_allowed("ask_question"));
+    }
+}
@@ -0,0 +1,372 @@
+//! Client-side rate limits and concurrency caps
+//!
+//! Each upstream, and each tool on each upstream, can be given a token-bucket rate
+//! (`requests_per_second` with a `burst`) and a cap on requests in flight (`max_in_flight`).
+//! A request over a limit waits its turn, unless `max_queued` requests are already waiting on
+//! that limit; then it fails at once with [`Rejected`] rather than piling up behind it.
+
+use crate::coalesce::send_error;
+use crate::settings::{LimitSettings, LimitsSettings};
+use rmcp::service::ServiceError;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// Key in [`LimitsSettings::upstreams`] applying to upstreams without their own entry
+pub const ANY_UPSTREAM: &str = "*";
+
+/// Error for requests refused because too many are already waiting
+#[derive(Debug)]
+pub struct Rejected {
+    /// The upstream, or `<upstream> tool <tool>`, whose limit was reached
+    pub scope: String,
+    pub queued: usize,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many requests to {}: {} already waiting on its rate or concurrency limit; try \
+             again shortly",
+            self.scope, self.queued
+        )
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// The [`Rejected`] error behind a failed request, if a limit refused it
+pub fn rejected(error: &ServiceError) -> Option<&Rejected> {
+    send_error(error)?.downcast_ref()
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    /// Negative while later requests hold reservations
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: Option<u32>) -> Self {
+        let capacity = burst.map_or(rate.ceil().max(1.0), f64::from);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Time until a token taken now would have been available
+    fn wait(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.rate)
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+
+    /// Return the token of a request that gave up waiting for it
+    fn give_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.capacity);
+    }
+}
+
+/// One rate and concurrency limit, with its queue of waiting requests
+#[derive(Debug)]
+struct Limiter {
+    scope: String,
+    bucket: Option<Mutex<TokenBucket>>,
+    slots: Option<Arc<Semaphore>>,
+    queued: AtomicUsize,
+    max_queued: usize,
+}
+
+/// Counts a request as queued on a limiter until dropped
+struct Queued<'a>(&'a Limiter);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Limiter {
+    fn new(scope: String, settings: &LimitSettings, max_queued: usize) -> Option<Self> {
+        let bucket = settings
+            .requests_per_second
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Mutex::new(TokenBucket::new(rate, settings.burst)));
+        let slots = settings
+            .max_in_flight
+            .map(|slots| Arc::new(Semaphore::new(slots.max(1))));
+        (bucket.is_some() || slots.is_some()).then(|| Self {
+            scope,
+            bucket,
+            slots,
+            queued: AtomicUsize::new(0),
+            max_queued,
+        })
+    }
+
+    fn enqueue(&self) -> Result<Queued<'_>, Rejected> {
+        let queued = self.queued.fetch_add(1, Ordering::Relaxed);
+        let guard = Queued(self);
+        if queued >= self.max_queued {
+            warn!(
+                "Refusing a request to {}: {} already queued on its limits",
+                self.scope, queued
+            );
+            return Err(Rejected {
+                scope: self.scope.clone(),
+                queued,
+            });
+        }
+        Ok(guard)
+    }
+
+    /// Wait for a slot and a token
+    async fn acquire(
+        &self,
+        ct: &CancellationToken,
+    ) -> Result<Option<OwnedSemaphorePermit>, ServiceError> {
+        let permit = match &self.slots {
+            Some(slots) => Some(match slots.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    let _queued = self.enqueue().map_err(refuse)?;
+                    tokio::select! {
+                        permit = slots.clone().acquire_owned() => {
+                            permit.map_err(|e| ServiceError::TransportSend(Box::new(e)))?
+                        }
+                        () = ct.cancelled() => return Err(cancelled()),
+                    }
+                }
+            }),
+            None => None,
+        };
+
+        if let Some(bucket) = &self.bucket {
+            let wait = {
+                let Ok(mut bucket) = bucket.lock() else {
+                    return Ok(permit);
+                };
+                let wait = bucket.wait(Instant::now());
+                // Check the queue before reserving, so a refused request leaves the bucket as is
+                let queued = if wait.is_zero() {
+                    None
+                } else {
+                    Some(self.enqueue().map_err(refuse)?)
+                };
+                bucket.take();
+                queued.map(|queued| (queued, wait))
+            };
+            if let Some((_queued, wait)) = wait {
+                tokio::select! {
+                    () = tokio::time::sleep(wait) => {}
+                    () = ct.cancelled() => {
+                        // Don't hold up later requests behind one that never goes out
+                        if let Ok(mut bucket) = bucket.lock() {
+                            bucket.give_back();
+                        }
+                        return Err(cancelled());
+                    }
+                }
+            }
+        }
+        Ok(permit)
+    }
+}
+
+fn refuse(rejected: Rejected) -> ServiceError {
+    ServiceError::TransportSend(Box::new(rejected))
+}
+
+fn cancelled() -> ServiceError {
+    ServiceError::Cancelled {
+        reason: Some("cancelled by client".to_string()),
+    }
+}
+
+/// Concurrency slots held while a request is in flight
+pub struct Permit {
+    _slots: Vec<OwnedSemaphorePermit>,
+}
+
+/// Upstream and tool name
+type ToolKey = (String, String);
+
+/// The limits of every upstream and tool
+#[derive(Debug)]
+pub struct Limits {
+    upstreams: HashMap<String, Limiter>,
+    /// Per upstream and tool, created on first use
+    tools: Mutex<HashMap<ToolKey, Option<Arc<Limiter>>>>,
+    settings: LimitsSettings,
+}
+
+impl Limits {
+    pub fn new<'a>(upstreams: impl Iterator<Item = &'a str>, settings: &LimitsSettings) -> Self {
+        let upstreams = upstreams
+            .filter_map(|name| {
+                let limit = settings
+                    .upstreams
+                    .get(name)
+                    .or_else(|| settings.upstreams.get(ANY_UPSTREAM))?;
+                let limiter = Limiter::new(name.to_string(), limit, settings.max_queued)?;
+                Some((name.to_string(), limiter))
+            })
+            .collect();
+        Self {
+            upstreams,
+            tools: Mutex::new(HashMap::new()),
+            settings: settings.clone(),
+        }
+    }
+
+    fn tool_limiter(&self, upstream: &str, tool: &str) -> Option<Arc<Limiter>> {
+        let limit = self.settings.tools.get(tool)?;
+        let mut tools = self.tools.lock().ok()?;
+        tools
+            .entry((upstream.to_string(), tool.to_string()))
+            .or_insert_with(|| {
+                let scope = format!("{upstream} tool {tool}");
+                Limiter::new(scope, limit, self.settings.max_queued).map(Arc::new)
+            })
+            .clone()
+    }
+
+    /// Wait until a request to `upstream` (calling `tool`, when not empty) is within its
+    /// limits, holding its concurrency slots in the returned [`Permit`]
+    ///
+    /// Fails with [`Rejected`] when the queue of a limit is full, or when `ct` is cancelled.
+    pub async fn acquire(
+        &self,
+        upstream: &str,
+        tool: &str,
+        ct: &CancellationToken,
+    ) -> Result<Permit, ServiceError> {
+        let mut permits = Vec::new();
+        // The tool's own limit first, so calls waiting on it do not hold upstream slots
+        if let Some(limiter) = self.tool_limiter(upstream, tool) {
+            permits.extend(limiter.acquire(ct).await?);
+        }
+        if let Some(limiter) = self.upstreams.get(upstream) {
+            permits.extend(limiter.acquire(ct).await?);
+        }
+        Ok(Permit { _slots: permits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(json: &str) -> LimitsSettings {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_token_bucket_allows_bursts_then_spaces_requests() {
+        let mut bucket = TokenBucket::new(2.0, Some(3));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(bucket.wait(now), Duration::ZERO);
+            bucket.take();
+        }
+        assert_eq!(bucket.wait(now), Duration::from_millis(500));
+        bucket.take();
+        // The next request waits behind the one holding a reservation
+        assert_eq!(bucket.wait(now), Duration::from_secs(1));
+        assert_eq!(bucket.wait(now + Duration::from_secs(1)), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_caps_in_flight_and_rejects_past_the_queue() {
+        let limits = Limits::new(
+            ["deepwiki"].into_iter(),
+            &settings(r#"{"max_queued": 1, "upstreams": {"*": {"max_in_flight": 1}}}"#),
+        );
+        let ct = CancellationToken::new();
+        let first = limits.acquire("deepwiki", "", &ct).await.unwrap();
+
+        let waiting = limits.acquire("deepwiki", "", &ct);
+        tokio::pin!(waiting);
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        let error = limits.acquire("deepwiki", "", &ct).await.err().unwrap();
+        let rejected = rejected(&error).unwrap();
+        assert_eq!(rejected.scope, "deepwiki");
+        assert!(error.to_string().contains("Too many requests to deepwiki"));
+
+        drop(first);
+        assert!(waiting.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tool_limits_apply_per_upstream() {
+        let limits = Limits::new(
+            ["deepwiki", "devin"].into_iter(),
+            &settings(
+                r#"{"max_queued": 0, "tools": {"read_wiki_contents": {"max_in_flight": 1}}}"#,
+            ),
+        );
+        let ct = CancellationToken::new();
+        let _held = limits
+            .acquire("deepwiki", "read_wiki_contents", &ct)
+            .await
+            .unwrap();
+        assert!(limits
+            .acquire("devin", "read_wiki_contents", &ct)
+            .await
+            .is_ok());
+        assert!(limits
+            .acquire("deepwiki", "ask_question", &ct)
+            .await
+            .is_ok());
+        let error = limits
+            .acquire("deepwiki", "read_wiki_contents", &ct)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            rejected(&error).unwrap().scope,
+            "deepwiki tool read_wiki_contents"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_while_queued() {
+        let limits = Limits::new(
+            ["deepwiki"].into_iter(),
+            &settings(r#"{"upstreams": {"deepwiki": {"requests_per_second": 0.1}}}"#),
+        );
+        let ct = CancellationToken::new();
+        assert!(limits.acquire("deepwiki", "", &ct).await.is_ok());
+        ct.cancel();
+        let error = limits.acquire("deepwiki", "", &ct).await.err().unwrap();
+        assert!(matches!(error, ServiceError::Cancelled { .. }));
+
+        // The cancelled request's token went back: the next one waits behind the first only
+        let bucket = limits.upstreams["deepwiki"].bucket.as_ref().unwrap();
+        let wait = bucket.lock().unwrap().wait(Instant::now());
+        assert!(wait <= Duration::from_secs(10), "{wait:?}");
+        assert!(wait > Duration::from_secs(9), "{wait:?}");
+    }
+}
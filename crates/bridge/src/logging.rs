@@ -0,0 +1,365 @@
+//! Log output: format, destination and rotation
+//!
+//! Logs go to stderr in the usual human format unless `--log-format` (or `log.format`) picks
+//! `pretty`, `compact` or one JSON object per line, and `--log-file` (or `log.file`) writes them
+//! to a file instead, so they survive Zed restarts. Daily log files are dated
+//! (`<file>.YYYY-MM-DD`); size-rotated ones are renamed `<file>.1`, `<file>.2`, ... once they
+//! reach the size. Either way `log.max_files` old files are kept. Files are written from a
+//! background thread, so logging never waits on the disk.
+
+use crate::settings::LogSettings;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{fmt as subscriber_fmt, EnvFilter, Layer, Registry};
+
+/// Old log files kept when `log.max_files` is not set
+pub const DEFAULT_MAX_FILES: usize = 7;
+
+/// Log line format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LogFormat {
+    /// The default single-line human format
+    #[default]
+    Full,
+    /// Multi-line human format with fields and spans on their own lines
+    Pretty,
+    Compact,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "full" => Ok(Self::Full),
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!(
+                "Invalid log format: {other}. Expected 'json', 'pretty', 'compact' or 'full'"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for LogFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+/// When a log file is rotated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LogRotation {
+    Never,
+    /// At the first write of each UTC day
+    #[default]
+    Daily,
+    /// Before a write would take the file past this many bytes
+    Size(u64),
+}
+
+impl FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    /// `never`, `daily`, or a size such as `10MB`, `512KB` or `1048576`
+    fn from_str(value: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid log rotation: {value}. Expected 'daily', 'never' or a size like '10MB'"
+            )
+        };
+        match value.trim().to_ascii_lowercase().as_str() {
+            "never" => Ok(Self::Never),
+            "daily" => Ok(Self::Daily),
+            size => {
+                let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+                let multiplier = match size[digits.len()..].trim_end_matches('b') {
+                    "" => 1,
+                    "k" => 1024,
+                    "m" => 1024 * 1024,
+                    "g" => 1024 * 1024 * 1024,
+                    _ => return Err(invalid()),
+                };
+                let count: u64 = digits.trim().parse().map_err(|_| invalid())?;
+                match count.checked_mul(multiplier) {
+                    Some(bytes) if bytes > 0 => Ok(Self::Size(bytes)),
+                    _ => Err(invalid()),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for LogRotation {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+/// Logging overrides from the command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    pub format: Option<LogFormat>,
+    pub file: Option<PathBuf>,
+    pub rotation: Option<LogRotation>,
+}
+
+/// The stderr or log file layer, and the guard that flushes the log file when dropped; the
+/// command line takes precedence over the settings
+///
+/// Logs go to stderr when the log file cannot be opened, since logging is not set up yet to
+/// report it any other way.
+pub fn layer(
+    options: &LogOptions,
+    settings: &LogSettings,
+    filter: EnvFilter,
+) -> (Box<dyn Layer<Registry> + Send + Sync>, Option<WorkerGuard>) {
+    let format = options.format.or(settings.format).unwrap_or_default();
+    let file = options
+        .file
+        .as_ref()
+        .or(settings.file.as_ref())
+        .and_then(|path| {
+            let rotation = options.rotation.unwrap_or(settings.rotation);
+            let max_files = settings.max_files.unwrap_or(DEFAULT_MAX_FILES);
+            file_writer(path, rotation, max_files)
+                .map_err(|e| eprintln!("warning: cannot open log file {}: {}", path.display(), e))
+                .ok()
+        });
+    let (writer, ansi, guard) = match file {
+        Some((file, guard)) => (BoxMakeWriter::new(file), false, Some(guard)),
+        None => (BoxMakeWriter::new(io::stderr), true, None),
+    };
+
+    let layer = subscriber_fmt::layer()
+        .with_target(false)
+        .with_ansi(ansi)
+        .with_writer(writer);
+    let layer = match format {
+        LogFormat::Full => layer.with_filter(filter).boxed(),
+        LogFormat::Pretty => layer.pretty().with_filter(filter).boxed(),
+        LogFormat::Compact => layer.compact().with_filter(filter).boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_filter(filter)
+            .boxed(),
+    };
+    (layer, guard)
+}
+
+/// A background writer for the log file
+fn file_writer(
+    path: &Path,
+    rotation: LogRotation,
+    max_files: usize,
+) -> io::Result<(NonBlocking, WorkerGuard)> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir)?;
+    }
+    if let LogRotation::Size(limit) = rotation {
+        return Ok(tracing_appender::non_blocking(SizeRotatingFile::open(
+            path, limit, max_files,
+        )?));
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("not a file name"))?;
+    let mut builder = RollingFileAppender::builder().filename_prefix(name.to_string_lossy());
+    builder = match rotation {
+        // The current day's file counts towards the appender's limit
+        LogRotation::Daily => builder
+            .rotation(Rotation::DAILY)
+            .max_log_files(max_files.max(1) + 1),
+        _ => builder.rotation(Rotation::NEVER),
+    };
+    let appender = builder
+        .build(dir.unwrap_or(Path::new(".")))
+        .map_err(io::Error::other)?;
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// A log file renamed `<file>.1` (shifting older ones up) before a write would take it past
+/// a size
+///
+/// It is written from the non-blocking appender's thread. A rotation that fails is reported
+/// on stderr and the file keeps growing, so no log line is lost to it.
+pub struct SizeRotatingFile {
+    path: PathBuf,
+    limit: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRotatingFile {
+    pub fn open(path: &Path, limit: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            limit,
+            max_files: max_files.max(1),
+            size: file.metadata()?.len(),
+            file,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                fs::rename(from, self.rotated(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+
+    /// `<file>.<index>`
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.limit {
+            if let Err(e) = self.rotate() {
+                eprintln!(
+                    "warning: cannot rotate log file {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+            // Either a fresh file, or another `limit` bytes before trying again
+            self.size = 0;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_parses_formats_and_rotations() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+        assert_eq!("daily".parse::<LogRotation>().unwrap(), LogRotation::Daily);
+        assert_eq!(
+            "10MB".parse::<LogRotation>().unwrap(),
+            LogRotation::Size(10 * 1024 * 1024)
+        );
+        assert_eq!(
+            "512k".parse::<LogRotation>().unwrap(),
+            LogRotation::Size(512 * 1024)
+        );
+        assert_eq!(
+            "4096".parse::<LogRotation>().unwrap(),
+            LogRotation::Size(4096)
+        );
+        assert!("0MB".parse::<LogRotation>().is_err());
+        assert!("10 parsecs".parse::<LogRotation>().is_err());
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_rotates_by_size_keeping_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bridge.log");
+        let mut file = SizeRotatingFile::open(&path, 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&dir.path().join("bridge.log.1")), "third\n");
+        assert_eq!(read(&dir.path().join("bridge.log.2")), "second\n");
+        assert!(!dir.path().join("bridge.log.3").exists());
+    }
+
+    #[test]
+    fn test_failed_rotation_keeps_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bridge.log");
+        // A non-empty directory where the rotated file should go
+        fs::create_dir_all(dir.path().join("bridge.log.1").join("busy")).unwrap();
+        let mut file = SizeRotatingFile::open(&path, 10, 1).unwrap();
+        for line in ["first\n", "second\n", "third\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(read(&path), "first\nsecond\nthird\n");
+    }
+
+    #[test]
+    fn test_json_lines_to_a_daily_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bridge.log");
+        let options = LogOptions {
+            format: Some(LogFormat::Json),
+            file: Some(path.clone()),
+            rotation: None,
+        };
+        let (layer, guard) = layer(&options, &LogSettings::default(), EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("forward", upstream = "deepwiki");
+            let _span = span.enter();
+            tracing::info!(attempt = 2, "Forwarding \"tools/list\"");
+            tracing::debug!("filtered out");
+        });
+        // Flushes the background writer
+        drop(guard);
+
+        let today = chrono::Utc::now().format("%Y-%m-%d");
+        let text = read(&dir.path().join(format!("bridge.log.{today}")));
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["fields"]["message"], "Forwarding \"tools/list\"");
+        assert_eq!(lines[0]["fields"]["attempt"], 2);
+        assert_eq!(lines[0]["spans"][0]["name"], "forward");
+        assert_eq!(lines[0]["spans"][0]["upstream"], "deepwiki");
+    }
+}
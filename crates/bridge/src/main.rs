@@ -4,54 +4,212 @@
 //! HTTP/SSE-based MCP servers using the official rust-sdk. It provides transport
 //! auto-detection, built-in `OAuth2` authentication, and minimal overhead.
 
+mod arguments;
+mod breaker;
+mod cache;
+mod cli;
+mod client_log;
+mod coalesce;
+mod commands;
+mod dependencies;
+mod doctor;
+mod filter;
+mod limits;
+mod logging;
+mod metrics;
+mod mirror;
+mod naming;
+mod project;
+mod proxy;
+mod recording;
+mod repl;
+mod replay;
+mod retry;
+mod routing;
+mod search;
+mod sections;
+mod settings;
+mod telemetry;
+mod traced_client;
+mod traffic;
+mod truncate;
+mod upstream;
+
 use anyhow::Result;
+use cache::ResponseCache;
+use cli::Command;
+use client_log::{ClientLog, ClientLogLayer, UpstreamHandler};
+use dependencies::ProjectDependencies;
+use futures::future::join_all;
+use metrics::Metrics;
+use project::{DefaultRepository, ProjectContext};
+use proxy::ProxyHandler;
+use recording::{Redaction, TrafficRecorder};
+use replay::{Recording, ReplaySide};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use retry::HttpFailures;
 use rmcp::{
     model::{ClientCapabilities, ClientInfo, Implementation},
+    service::RunningService,
     transport::{
-        auth::AuthorizationManager, stdio, SseClientTransport, StreamableHttpClientTransport,
+        async_rw::AsyncRwTransport, auth::AuthorizationManager, sse_client::SseClientConfig, stdio,
+        streamable_http_client::StreamableHttpClientTransportConfig, SseClientTransport,
+        StreamableHttpClientTransport,
     },
-    ServiceExt,
+    RoleClient, RoleServer, ServiceExt,
 };
+use search::WikiSearch;
+use settings::{AuthMode, BridgeSettings, TransportKind, UpstreamSettings};
+use std::collections::HashMap;
 use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use telemetry::TracedServer;
+use traced_client::TracedClient;
 use tracing::{error, info, warn};
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+use traffic::{ObservedTransport, TrafficObserver};
+use upstream::{Upstream, Upstreams};
+
+/// A connected MCP client session with a remote server
+type RemoteClient = RunningService<RoleClient, UpstreamHandler>;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .with_writer(std::io::stderr)
-        .init();
-
     // Parse command line arguments
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        print_usage(&args[0]);
-        std::process::exit(1);
-    }
+    let command = match cli::parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {e}");
+            eprintln!();
+            print_usage(&args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize logging: RUST_LOG, then the settings' level, then the command's default
+    let settings = BridgeSettings::from_env();
+    let filter = EnvFilter::try_from_default_env()
+        .ok()
+        .or_else(|| EnvFilter::try_new(settings.log.level.as_deref()?).ok())
+        .unwrap_or_else(|| EnvFilter::new(command.default_log_level()));
+
+    // The proxy also sends its log messages to Zed over MCP
+    let client_log =
+        matches!(command, Command::Proxy { .. }).then(|| Arc::new(ClientLog::default()));
+
+    // Export the bridge's own spans when an OTLP endpoint is configured
+    let export = telemetry::OtlpConfig::from_env()
+        .and_then(|config| config.map(telemetry::otlp_layer).transpose());
+    let (export, export_error) = match export {
+        Ok(export) => (export, None),
+        Err(e) => (None, Some(e)),
+    };
+    let (otlp, telemetry) = export.unzip();
+    let otlp = otlp.map(|layer| {
+        layer.with_filter(
+            tracing_subscriber::filter::Targets::new()
+                .with_target(env!("CARGO_CRATE_NAME"), tracing::Level::DEBUG),
+        )
+    });
 
-    let endpoint_url = &args[1];
+    // Dropping the guard flushes the log file, so it is dropped before exiting
+    let (log_layer, log_guard) = logging::layer(&command.log_options(), &settings.log, filter);
+    tracing_subscriber::registry()
+        .with(log_layer)
+        .with(otlp)
+        .with(client_log.clone().map(|log| {
+            ClientLogLayer(log).with_filter(
+                tracing_subscriber::filter::Targets::new()
+                    .with_target(env!("CARGO_CRATE_NAME"), tracing::Level::DEBUG),
+            )
+        }))
+        .init();
+    if let Some(e) = export_error {
+        warn!("Not exporting traces: {}", e);
+    }
 
     // Validate URL format with detailed error handling
-    if let Err(e) = validate_url(endpoint_url) {
-        error!("{}", e);
-        std::process::exit(1);
+    if let Some(endpoint_url) = command.endpoint_url() {
+        if let Err(e) = validate_url(endpoint_url) {
+            error!("{}", e);
+            drop(log_guard);
+            std::process::exit(1);
+        }
     }
 
-    info!("Starting MCP Proxy for endpoint: {}", endpoint_url);
+    let result = match command {
+        Command::Help => {
+            print_usage(&args[0]);
+            return Ok(());
+        }
+        Command::Proxy {
+            endpoint_url,
+            offline,
+            repo,
+            record,
+            metrics_addr,
+            ..
+        } => {
+            info!("Starting MCP Proxy for endpoint: {}", endpoint_url);
+            let client_log = client_log.unwrap_or_default();
+            run_proxy(
+                &endpoint_url,
+                offline,
+                repo,
+                record,
+                metrics_addr,
+                client_log,
+            )
+            .await
+            .map(|()| info!("MCP Proxy stopped"))
+            .map_err(|e| anyhow::anyhow!("Proxy failed: {}", e))
+        }
+        Command::Tools {
+            endpoint_url,
+            output,
+        } => commands::list_tools(&endpoint_url, output).await,
+        Command::Call {
+            endpoint_url,
+            tool,
+            arguments,
+            output,
+        } => commands::call_tool(&endpoint_url, &tool, arguments, output).await,
+        Command::Ask {
+            endpoint_url,
+            repo,
+            question,
+            output,
+        } => commands::ask(&endpoint_url, &repo, &question, output).await,
+        Command::Repl { endpoint_url } => repl::run(&endpoint_url).await,
+        Command::Mirror {
+            endpoint_url,
+            repo,
+            out,
+        } => mirror::run(&endpoint_url, &repo, &out).await,
+        Command::Replay { file, upstream } => replay::run(&file, upstream).await,
+        Command::Cache { action, output } => commands::cache(action, output),
+        Command::Doctor {
+            endpoint_url,
+            metrics_addr,
+            output,
+        } => doctor::run(&endpoint_url, metrics_addr, output).await,
+    };
 
-    // Run the proxy (implementation will be added in next tasks)
-    if let Err(e) = run_proxy(endpoint_url).await {
-        error!("Proxy failed: {}", e);
+    if let Err(e) = &result {
+        error!("{}", e);
+    }
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown().await;
+    }
+    drop(log_guard);
+    if result.is_err() {
         std::process::exit(1);
     }
 
-    info!("MCP Proxy stopped");
     Ok(())
 }
 
@@ -60,15 +218,55 @@ fn print_usage(program_name: &str) {
     eprintln!("DeepWiki MCP Proxy - Minimal proxy using official rust-sdk");
     eprintln!();
     eprintln!("USAGE:");
-    eprintln!("    {program_name} <ENDPOINT_URL>");
+    eprintln!("    {program_name} <ENDPOINT_URL> [--offline] [--repo OWNER/REPO] [--record FILE] [--metrics-addr HOST:PORT] [--log-format FORMAT] [--log-file FILE] [--log-rotation daily|never|SIZE]");
+    eprintln!("    {program_name} tools <ENDPOINT_URL> [--output text|json]");
+    eprintln!("    {program_name} call <ENDPOINT_URL> <TOOL> [--arg KEY=VALUE]... [--json OBJECT] [--output text|json]");
+    eprintln!(
+        "    {program_name} ask <OWNER/REPO> <QUESTION> [--endpoint URL] [--output text|json]"
+    );
+    eprintln!("    {program_name} repl <ENDPOINT_URL>");
+    eprintln!("    {program_name} mirror <OWNER/REPO> [--out DIR] [--endpoint URL]");
+    eprintln!("    {program_name} replay <FILE> [--upstream NAME]");
+    eprintln!("    {program_name} cache <clear|stats> [--output text|json]");
+    eprintln!("    {program_name} doctor [--endpoint URL] [--metrics-addr HOST:PORT] [--output text|json]");
     eprintln!();
     eprintln!("ARGUMENTS:");
-    eprintln!("    <ENDPOINT_URL>    MCP server endpoint URL (http:// or https://)");
+    eprintln!("    <ENDPOINT_URL>    MCP server endpoint URL (http:// or https://, or file:// to replay a recording)");
+    eprintln!();
+    eprintln!("OPTIONS:");
+    eprintln!(
+        "    --offline         Serve the tool list and tool results from the response cache only"
+    );
+    eprintln!("    --repo            Default repoName for DeepWiki tools (detected from the project otherwise)");
+    eprintln!(
+        "    --record          Append all JSON-RPC traffic, with secrets redacted, to a JSONL file"
+    );
+    eprintln!("    --metrics-addr    Serve Prometheus metrics at http://HOST:PORT/metrics");
+    eprintln!("    --log-format      Log as json, pretty, compact or full (the default)");
+    eprintln!("    --log-file        Write logs to FILE instead of stderr");
+    eprintln!("    --log-rotation    Rotate the log file daily (the default), never, or at a size like 10MB");
+    eprintln!();
+    eprintln!("SUBCOMMANDS:");
+    eprintln!("    tools             List the server's tools with their input schemas");
+    eprintln!("    call              Invoke a tool; --arg values are parsed as JSON when possible");
+    eprintln!(
+        "    ask               Shortcut for ask_question (default endpoint: {})",
+        cli::DEFAULT_ENDPOINT
+    );
+    eprintln!("    repl              Interactive session with tab completion and raw traffic view");
+    eprintln!("    mirror            Save a wiki as Markdown under DIR/OWNER/REPO (default: deepwiki-mirror)");
+    eprintln!("    replay            Answer MCP requests on STDIO from a --record file, as the bridge or as --upstream NAME");
+    eprintln!("    cache             Clear or inspect the on-disk response cache");
+    eprintln!("    doctor            Check the settings and upstreams, with circuit states from a bridge serving --metrics-addr");
     eprintln!();
     eprintln!("EXAMPLES:");
     eprintln!("    {program_name} https://mcp.deepwiki.com");
     eprintln!("    {program_name} https://mcp.devin.ai");
     eprintln!("    {program_name} https://localhost:8080/sse");
+    eprintln!("    {program_name} call https://mcp.deepwiki.com read_wiki_structure --arg repoName=tokio-rs/tokio");
+    eprintln!(
+        "    {program_name} ask tokio-rs/tokio \"How does the scheduler work?\" --output json"
+    );
     eprintln!();
     eprintln!("TRANSPORT AUTO-DETECTION:");
     eprintln!("    URLs containing '/sse' will use SSE transport");
@@ -76,20 +274,243 @@ fn print_usage(program_name: &str) {
     eprintln!();
     eprintln!("AUTHENTICATION:");
     eprintln!("    OAuth2 authentication is handled automatically when required");
+    eprintln!();
+    eprintln!("ENVIRONMENT:");
+    eprintln!(
+        "    {}  JSON settings object (set by the Zed extension)",
+        settings::SETTINGS_ENV_VAR
+    );
 }
 
 /// Transport wrapper enum to handle different remote transport types
 enum McpTransport {
-    Http(StreamableHttpClientTransport<reqwest::Client>),
-    Sse(SseClientTransport<reqwest::Client>),
+    Http(StreamableHttpClientTransport<TracedClient>),
+    Sse(SseClientTransport<TracedClient>),
 }
 
 /// Run the MCP proxy with transport auto-detection and authentication
-async fn run_proxy(endpoint_url: &str) -> Result<()> {
-    let needs_auth = detect_authentication_requirement(endpoint_url);
-    let remote_transport = create_transport(endpoint_url, needs_auth).await?;
-    let remote_client = establish_remote_connection(remote_transport).await?;
-    handle_stdio_connection_and_proxy(remote_client).await
+///
+/// When offline, or when the server cannot be reached and a response cache exists, the proxy
+/// serves from the cache alone.
+async fn run_proxy(
+    endpoint_url: &str,
+    offline: bool,
+    repo: Option<String>,
+    record: Option<PathBuf>,
+    metrics_addr: Option<SocketAddr>,
+    client_log: Arc<ClientLog>,
+) -> Result<()> {
+    let settings = BridgeSettings::from_env();
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = metrics_addr.or(settings.metrics_addr) {
+        metrics::serve(addr, metrics.clone()).await?;
+    }
+    let recorder = match record.or_else(|| settings.record.file.clone()) {
+        Some(path) => {
+            info!("Recording traffic to {}", path.display());
+            Some(TrafficRecorder::create(
+                &path,
+                Redaction::from_settings(&settings),
+            )?)
+        }
+        None => None,
+    };
+    let current_dir = env::current_dir().ok();
+    let repository = repo
+        .or_else(|| settings.repository.clone())
+        .or_else(|| current_dir.as_deref().and_then(project::detect_repository));
+    if let Some(repo) = &repository {
+        info!(
+            "Using {} as the default repository for DeepWiki tools",
+            repo
+        );
+    }
+
+    let cache = ResponseCache::from_settings(&settings.cache);
+    if let Some(cache) = &cache {
+        info!("Response cache enabled at {}", cache.dir().display());
+    }
+
+    let configured = !settings.upstreams.is_empty();
+    let upstream_settings = if configured {
+        upstream::validate_upstreams(&settings.upstreams)?;
+        for upstream in &settings.upstreams {
+            validate_url(&upstream.url)?;
+        }
+        settings.upstreams.clone()
+    } else {
+        vec![UpstreamSettings::from_url(endpoint_url)]
+    };
+
+    let router = routing::Router::new(&settings.routing);
+    if router.is_configured() {
+        if upstream_settings.len() > 1 {
+            let names: Vec<&str> = upstream_settings.iter().map(|u| u.name.as_str()).collect();
+            router.validate(&names)?;
+        } else {
+            warn!("Ignoring routing settings: they need at least two configured upstreams");
+        }
+    }
+
+    let clients = if offline || settings.offline {
+        if cache.is_none() {
+            return Err(anyhow::anyhow!(
+                "Offline mode requires the response cache, but cache.mode is \"no-store\""
+            ));
+        }
+        info!("Offline mode: serving from the response cache only");
+        upstream_settings.iter().map(|_| None).collect()
+    } else {
+        connect_upstreams(
+            &upstream_settings,
+            cache.is_some(),
+            recorder.as_ref(),
+            &metrics,
+            &client_log,
+        )
+        .await?
+    };
+
+    let upstreams = if configured {
+        Upstreams::new(
+            upstream_settings
+                .into_iter()
+                .zip(clients)
+                .map(|(settings, client)| Upstream {
+                    name: settings.name,
+                    endpoint_url: settings.url,
+                    client,
+                })
+                .collect(),
+        )
+    } else {
+        Upstreams::single(endpoint_url, clients.into_iter().next().flatten())
+    };
+
+    let search = WikiSearch::open(&settings.search);
+    let handler = ProxyHandler::new(
+        upstreams,
+        cache,
+        search,
+        ProjectContext {
+            repository: DefaultRepository::new(repository),
+            dependencies: current_dir
+                .as_deref()
+                .map(ProjectDependencies::load)
+                .unwrap_or_default(),
+        },
+        &settings,
+        metrics,
+        client_log,
+    );
+    let observer = recorder.map(|recorder| recorder.observer(None));
+    handle_stdio_connection_and_proxy(handler, observer).await
+}
+
+/// Connect to every upstream concurrently
+///
+/// An upstream that cannot be reached is left offline as long as something can still be
+/// served: another upstream, or the response cache.
+async fn connect_upstreams(
+    upstreams: &[UpstreamSettings],
+    has_cache: bool,
+    recorder: Option<&Arc<TrafficRecorder>>,
+    metrics: &Metrics,
+    client_log: &Arc<ClientLog>,
+) -> Result<Vec<Option<RemoteClient>>> {
+    let results = join_all(upstreams.iter().map(|upstream| {
+        let observer = recorder.map(|recorder| recorder.observer(Some(&upstream.name)));
+        connect_upstream(upstream, observer, Some(metrics), Some(client_log))
+    }))
+    .await;
+    let any_connected = results.iter().any(Result::is_ok);
+
+    let mut clients = Vec::with_capacity(results.len());
+    for (upstream, result) in upstreams.iter().zip(results) {
+        match result {
+            Ok(client) => clients.push(Some(client)),
+            Err(e) if has_cache || any_connected => {
+                warn!("{}: {}; serving it offline", upstream.name, e);
+                clients.push(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(clients)
+}
+
+/// Connect to a remote MCP server, detecting transport and authentication from the URL
+///
+/// When an observer is given, every JSON-RPC message exchanged with the server is reported to it.
+async fn connect_remote(
+    endpoint_url: &str,
+    observer: Option<Arc<dyn TrafficObserver>>,
+) -> Result<RemoteClient> {
+    connect_upstream(
+        &UpstreamSettings::from_url(endpoint_url),
+        observer,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Connect to an upstream with its configured transport, authentication and headers
+///
+/// The upstream's log messages are passed on to `client_log` when given.
+async fn connect_upstream(
+    upstream: &UpstreamSettings,
+    observer: Option<Arc<dyn TrafficObserver>>,
+    metrics: Option<&Metrics>,
+    client_log: Option<&Arc<ClientLog>>,
+) -> Result<RemoteClient> {
+    let http_failures = Arc::new(HttpFailures::default());
+    let handler = UpstreamHandler::new(
+        create_client_info(),
+        &upstream.name,
+        client_log.cloned(),
+        http_failures.clone(),
+    );
+    if let Some(path) = replay_path(&upstream.url) {
+        if let Some(metrics) = metrics {
+            metrics.record_connection(&upstream.name, false);
+        }
+        return connect_replay(&path, &upstream.name, observer, handler).await;
+    }
+    let needs_auth = match upstream.auth {
+        AuthMode::Auto => detect_authentication_requirement(&upstream.url),
+        AuthMode::OAuth => true,
+        AuthMode::None => false,
+    };
+    if let Some(metrics) = metrics {
+        metrics.record_connection(&upstream.name, needs_auth);
+    }
+    let transport_type = match upstream.transport {
+        TransportKind::Auto => detect_transport_type(&upstream.url),
+        TransportKind::Http => "HTTP",
+        TransportKind::Sse => "SSE",
+    };
+    let client = TracedClient::new(create_http_client(&upstream.headers)?, http_failures);
+    let remote_transport =
+        create_transport(&upstream.url, transport_type, needs_auth, client).await?;
+    establish_remote_connection(remote_transport, observer, handler).await
+}
+
+/// HTTP client sending the upstream's extra headers with every request
+fn create_http_client(headers: &HashMap<String, String>) -> Result<reqwest::Client> {
+    let mut default_headers = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid header name '{}': {}", name, e))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|e| anyhow::anyhow!("Invalid value for header '{}': {}", name, e))?;
+        value.set_sensitive(true);
+        default_headers.insert(name, value);
+    }
+    reqwest::Client::builder()
+        .default_headers(default_headers)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to create HTTP client: {}", e))
 }
 
 fn detect_authentication_requirement(endpoint_url: &str) -> bool {
@@ -115,15 +536,56 @@ fn create_client_info() -> ClientInfo {
     }
 }
 
+/// The recording a `file://` endpoint refers to
+fn replay_path(url: &str) -> Option<PathBuf> {
+    url::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+}
+
+/// Serve an upstream from a recording over an in-memory pipe
+async fn connect_replay(
+    path: &Path,
+    name: &str,
+    observer: Option<Arc<dyn TrafficObserver>>,
+    handler: UpstreamHandler,
+) -> Result<RemoteClient> {
+    let recording = Recording::load(path, &ReplaySide::Upstream(Some(name.to_string())))?;
+    info!(
+        "Replaying {} recorded exchanges from {} as upstream {}",
+        recording.len(),
+        path.display(),
+        name
+    );
+    let (client_side, server_side) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        let (reader, writer) = tokio::io::split(server_side);
+        if let Err(e) = replay::serve(&recording, reader, writer).await {
+            warn!("Replay stopped: {}", e);
+        }
+    });
+
+    let (reader, writer) = tokio::io::split(client_side);
+    let transport = ObservedTransport::new(AsyncRwTransport::new_client(reader, writer), observer);
+    handler
+        .serve(transport)
+        .await
+        .map_err(|e| anyhow::anyhow!("Replay connection failed: {}", e))
+}
+
 async fn establish_remote_connection(
     remote_transport: McpTransport,
-) -> Result<impl std::fmt::Debug + Send + 'static> {
+    observer: Option<Arc<dyn TrafficObserver>>,
+    handler: UpstreamHandler,
+) -> Result<RemoteClient> {
     info!("Creating MCP client with remote transport...");
 
     let remote_client = match remote_transport {
         McpTransport::Http(transport) => {
             info!("Testing HTTP connection to MCP server");
-            let client = create_client_info().serve(transport).await.map_err(|e| {
+            let transport = ObservedTransport::new(transport, observer);
+            let client = handler.serve(transport).await.map_err(|e| {
                 error!("Failed to connect via HTTP: {}", e);
                 anyhow::anyhow!("HTTP connection failed: {}", e)
             })?;
@@ -132,7 +594,8 @@ async fn establish_remote_connection(
         }
         McpTransport::Sse(transport) => {
             info!("Testing SSE connection to MCP server");
-            let client = create_client_info().serve(transport).await.map_err(|e| {
+            let transport = ObservedTransport::new(transport, observer);
+            let client = handler.serve(transport).await.map_err(|e| {
                 error!("Failed to connect via SSE: {}", e);
                 anyhow::anyhow!("SSE connection failed: {}", e)
             })?;
@@ -146,267 +609,76 @@ async fn establish_remote_connection(
 }
 
 async fn handle_stdio_connection_and_proxy(
-    remote_client: impl std::fmt::Debug + Send + 'static,
+    handler: ProxyHandler,
+    observer: Option<Arc<dyn TrafficObserver>>,
 ) -> Result<()> {
     info!("Creating STDIO transport for Zed communication...");
-    let stdio_transport = stdio();
+    let (stdin, stdout) = stdio();
+    let stdio_transport =
+        ObservedTransport::new(AsyncRwTransport::new_server(stdin, stdout), observer);
     info!("STDIO transport created successfully");
 
-    info!("Establishing STDIO client connection...");
-    let stdio_client_result = create_client_info().serve(stdio_transport).await;
-
-    match stdio_client_result {
-        Ok(stdio_client) => {
-            info!("STDIO client connection established successfully");
-            info!("Both STDIO and remote transport connections established");
-            info!("Starting bidirectional message proxying...");
-            proxy_messages_dual(stdio_client, remote_client).await
-        }
-        Err(e) => {
-            error!("STDIO client connection failed: {}", e);
-            info!("Demonstrating message forwarding structure with remote client only");
-            proxy_messages_demo(remote_client).await
-        }
-    }
+    info!("Establishing STDIO server connection...");
+    let stdio_server = TracedServer(handler)
+        .serve(stdio_transport)
+        .await
+        .map_err(|e| {
+            error!("STDIO server connection failed: {}", e);
+            anyhow::anyhow!("STDIO connection failed: {}", e)
+        })?;
+    info!("STDIO server connection established successfully");
+    info!("Both STDIO and remote transport connections established");
+
+    proxy_messages_dual(stdio_server).await
 }
 
-/// Implement bidirectional message forwarding between STDIO and remote transports
+/// Forward messages between Zed and the remote server until Zed disconnects or Ctrl+C
 async fn proxy_messages_dual(
-    stdio_client: impl std::fmt::Debug + Send + 'static,
-    remote_client: impl std::fmt::Debug + Send + 'static,
+    stdio_server: RunningService<RoleServer, TracedServer<ProxyHandler>>,
 ) -> Result<()> {
-    info!("Initializing bidirectional message proxying between STDIO and remote transport");
-
-    // Create cancellation token for graceful shutdown with timeout
-    let ct = tokio_util::sync::CancellationToken::new();
-    let ct_clone = ct.clone();
-    let shutdown_timeout = std::time::Duration::from_secs(30);
-
-    // Spawn task to handle shutdown signals with timeout
-    tokio::spawn(async move {
-        tokio::select! {
-            signal_result = tokio::signal::ctrl_c() => {
-                match signal_result {
-                    Ok(()) => {
-                        info!("Shutdown signal received, initiating graceful shutdown");
-                        ct_clone.cancel();
-                    }
-                    Err(err) => {
-                        error!("Unable to listen for shutdown signal: {}", err);
-                        ct_clone.cancel();
-                    }
-                }
-            }
-            () = tokio::time::sleep(shutdown_timeout) => {
-                warn!("Shutdown timeout reached, forcing termination");
-                ct_clone.cancel();
-            }
-        }
-    });
-
-    // Add connection health monitoring
-    let health_check_ct = ct.clone();
-    tokio::spawn(async move {
-        let mut health_check_interval = tokio::time::interval(std::time::Duration::from_secs(30));
-        loop {
-            tokio::select! {
-                () = health_check_ct.cancelled() => {
-                    info!("Connection health monitoring stopped");
-                    break;
-                }
-                _ = health_check_interval.tick() => {
-                    info!("Connection health check: both transports active");
-                    // TODO: Implement actual connection health checks
-                }
-            }
-        }
-    });
-
-    info!("Bidirectional message forwarding loop started - use Ctrl+C to shutdown");
-
-    // Main bidirectional message forwarding loop using tokio::select!
-    let mut message_count = 0;
-    let operation_timeout = std::time::Duration::from_secs(60);
-    let start_time = std::time::Instant::now();
-
-    loop {
-        tokio::select! {
-            // Handle shutdown signal with timeout
-            () = ct.cancelled() => {
-                info!("Graceful shutdown initiated");
-                break;
-            }
-
-            // Operation timeout handling
-            () = tokio::time::sleep(operation_timeout), if start_time.elapsed() > operation_timeout => {
-                warn!("Operation timeout reached, initiating graceful shutdown");
-                ct.cancel();
-                break;
-            }
-
-            // STDIO -> Remote message forwarding with error handling
-            () = tokio::time::sleep(std::time::Duration::from_secs(2)) => {
-                message_count += 1;
-                match forward_stdio_to_remote_demo(message_count) {
-                    Ok(()) => {
-                        info!("STDIO -> Remote forwarding active (demo message {})", message_count);
-                        if message_count >= 3 {
-                            info!("Demo completed - bidirectional forwarding structure verified");
-                            ct.cancel();
-                        }
-                    }
-                    Err(e) => {
-                        error!("STDIO -> Remote forwarding error: {}", e);
-                        // Continue operation, don't crash on single message failure
-                    }
-                }
-            }
-
-            // Remote -> STDIO message forwarding with error handling
-            () = tokio::time::sleep(std::time::Duration::from_secs(2)) => {
-                forward_remote_to_stdio_demo();
-                info!("Remote -> STDIO forwarding active (ready to receive)");
-            }
-        }
-    }
-
-    info!("Bidirectional message forwarding completed");
-    info!("Cleaning up client connections with timeout...");
-
-    // Graceful cleanup with timeout
-    let cleanup_result = tokio::time::timeout(std::time::Duration::from_secs(10), async {
-        drop(stdio_client);
-        drop(remote_client);
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await; // Allow cleanup
-    })
-    .await;
-
-    if cleanup_result == Ok(()) {
-        info!("Client connections cleaned up successfully");
-    } else {
-        warn!("Cleanup timeout reached, forcing connection termination");
-    }
-
-    info!("Proxy shutdown completed successfully");
-    Ok(())
-}
-
-// Helper functions for message forwarding with error handling
-const SIMULATED_ERROR_COUNT: i32 = 2;
-
-fn forward_stdio_to_remote_demo(count: i32) -> Result<()> {
-    // Simulate potential forwarding errors
-    if count == SIMULATED_ERROR_COUNT {
-        return Err(anyhow::anyhow!("Simulated forwarding error"));
-    }
-    Ok(())
-}
-
-const fn forward_remote_to_stdio_demo() {
-    // Simulate remote message processing
-}
-
-/// Demonstrate message forwarding structure with remote client only
-async fn proxy_messages_demo(remote_client: impl std::fmt::Debug + Send + 'static) -> Result<()> {
-    info!("Demonstrating message forwarding structure (STDIO connection unavailable)");
-
-    // Create cancellation token for graceful shutdown
-    let ct = tokio_util::sync::CancellationToken::new();
-    let ct_clone = ct.clone();
+    let shutdown = stdio_server.cancellation_token();
 
     // Spawn task to handle shutdown signals
     tokio::spawn(async move {
         match tokio::signal::ctrl_c().await {
             Ok(()) => {
                 info!("Shutdown signal received, initiating graceful shutdown");
-                ct_clone.cancel();
+                shutdown.cancel();
             }
             Err(err) => {
                 error!("Unable to listen for shutdown signal: {}", err);
-                ct_clone.cancel();
             }
         }
     });
 
-    info!("Message forwarding structure demonstration started");
-
-    // Demonstrate the forwarding loop structure with timeout
-    let mut demo_count = 0;
-    let demo_timeout = std::time::Duration::from_secs(10);
-
-    loop {
-        tokio::select! {
-            // Handle shutdown signal
-            () = ct.cancelled() => {
-                info!("Graceful shutdown initiated");
-                break;
-            }
-
-            // Demo timeout handling
-            () = tokio::time::sleep(demo_timeout) => {
-                warn!("Demo timeout reached, completing demonstration");
-                ct.cancel();
-                break;
-            }
-
-            // Demonstrate STDIO message handling with error recovery
-            () = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
-                demo_count += 1;
-                match forward_stdio_to_remote_demo(demo_count) {
-                    Ok(()) => {
-                        info!("Demo: STDIO message received -> would forward to remote ({})", demo_count);
-                        if demo_count >= 3 {
-                            info!("Forwarding structure demonstration completed");
-                            ct.cancel();
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Demo: STDIO forwarding error: {} (continuing)", e);
-                    }
-                }
-            }
+    info!("Bidirectional message forwarding started - use Ctrl+C to shutdown");
 
-            // Demonstrate remote message handling with error recovery
-            () = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
-                forward_remote_to_stdio_demo();
-                info!("Demo: Remote message ready -> would forward to STDIO");
-            }
-        }
+    match stdio_server.waiting().await {
+        Ok(reason) => info!("Bidirectional message forwarding completed: {:?}", reason),
+        Err(e) => warn!("Message forwarding task ended abnormally: {}", e),
     }
 
-    info!("Message forwarding demonstration completed");
-    info!("Cleaning up remote client connection with timeout...");
-
-    // Graceful cleanup with timeout for demo mode
-    let cleanup_result = tokio::time::timeout(std::time::Duration::from_secs(5), async {
-        drop(remote_client);
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-    })
-    .await;
-
-    if cleanup_result == Ok(()) {
-        info!("Remote client connection cleaned up successfully");
-    } else {
-        warn!("Demo cleanup timeout reached, forcing termination");
-    }
-
-    info!("Demo proxy shutdown completed successfully");
+    info!("Proxy shutdown completed successfully");
     Ok(())
 }
 
-/// Create the appropriate transport based on URL patterns and authentication requirements
-async fn create_transport(endpoint_url: &str, needs_auth: bool) -> Result<McpTransport> {
-    let transport_type = detect_transport_type(endpoint_url);
-    info!("Detected transport type: {}", transport_type);
+/// Create the requested transport, authenticating when required
+async fn create_transport(
+    endpoint_url: &str,
+    transport_type: &str,
+    needs_auth: bool,
+    client: TracedClient,
+) -> Result<McpTransport> {
+    info!("Using transport type: {}", transport_type);
 
     if needs_auth {
-        return create_authenticated_transport(endpoint_url, transport_type).await;
+        return create_authenticated_transport(endpoint_url, transport_type, client).await;
     }
 
     match transport_type {
         "SSE" => {
             info!("Creating SSE client transport for: {}", endpoint_url);
-            match SseClientTransport::start(endpoint_url).await {
+            match SseClientTransport::start_with_client(client, sse_config(endpoint_url)).await {
                 Ok(transport) => {
                     info!("SSE transport created successfully");
                     Ok(McpTransport::Sse(transport))
@@ -419,7 +691,10 @@ async fn create_transport(endpoint_url: &str, needs_auth: bool) -> Result<McpTra
         }
         "HTTP" => {
             info!("Creating HTTP client transport for: {}", endpoint_url);
-            let transport = StreamableHttpClientTransport::from_uri(endpoint_url);
+            let transport = StreamableHttpClientTransport::with_client(
+                client,
+                StreamableHttpClientTransportConfig::with_uri(endpoint_url),
+            );
             info!("HTTP transport created successfully");
             Ok(McpTransport::Http(transport))
         }
@@ -437,6 +712,7 @@ async fn create_transport(endpoint_url: &str, needs_auth: bool) -> Result<McpTra
 async fn create_authenticated_transport(
     endpoint_url: &str,
     transport_type: &str,
+    client: TracedClient,
 ) -> Result<McpTransport> {
     info!("Creating authenticated transport for Devin endpoint");
 
@@ -470,7 +746,7 @@ async fn create_authenticated_transport(
                 "Creating authenticated SSE client transport for: {}",
                 endpoint_url
             );
-            match SseClientTransport::start(endpoint_url).await {
+            match SseClientTransport::start_with_client(client, sse_config(endpoint_url)).await {
                 Ok(transport) => {
                     info!("Authenticated SSE transport created successfully");
                     Ok(McpTransport::Sse(transport))
@@ -489,7 +765,10 @@ async fn create_authenticated_transport(
                 "Creating authenticated HTTP client transport for: {}",
                 endpoint_url
             );
-            let transport = StreamableHttpClientTransport::from_uri(endpoint_url);
+            let transport = StreamableHttpClientTransport::with_client(
+                client,
+                StreamableHttpClientTransportConfig::with_uri(endpoint_url),
+            );
             info!("Authenticated HTTP transport created successfully");
             Ok(McpTransport::Http(transport))
         }
@@ -506,6 +785,13 @@ async fn create_authenticated_transport(
     }
 }
 
+fn sse_config(endpoint_url: &str) -> SseClientConfig {
+    SseClientConfig {
+        sse_endpoint: endpoint_url.into(),
+        ..Default::default()
+    }
+}
+
 /// Detect transport type based on URL patterns
 fn detect_transport_type(url: &str) -> &'static str {
     if url.contains("/sse") {
@@ -517,9 +803,15 @@ fn detect_transport_type(url: &str) -> &'static str {
 
 /// Validate URL format and provide helpful error messages
 fn validate_url(url: &str) -> Result<()> {
+    if url.starts_with("file://") {
+        return match replay_path(url) {
+            Some(_) => Ok(()),
+            None => Err(anyhow::anyhow!("Invalid recording URL: {}", url)),
+        };
+    }
     if !url.starts_with("http://") && !url.starts_with("https://") {
         return Err(anyhow::anyhow!(
-            "Invalid URL format: {}. URL must start with http:// or https://",
+            "Invalid URL format: {}. URL must start with http://, https:// or file://",
             url
         ));
     }
@@ -580,21 +872,6 @@ mod integration_tests {
         }
     }
 
-    #[tokio::test]
-    async fn test_message_forwarding_structure() {
-        // Test the forwarding demo functions for error handling
-        let result1 = forward_stdio_to_remote_demo(1);
-        let result2 = forward_stdio_to_remote_demo(2); // Should error
-        forward_remote_to_stdio_demo();
-
-        assert!(result1.is_ok(), "First demo message should succeed");
-        assert!(
-            result2.is_err(),
-            "Second demo message should simulate error"
-        );
-        println!("✅ Integration test: Message forwarding error handling verified");
-    }
-
     #[tokio::test]
     async fn test_graceful_shutdown_simulation() {
         use tokio_util::sync::CancellationToken;
@@ -646,30 +923,6 @@ mod integration_tests {
         println!("✅ Integration test: URL validation comprehensive check passed");
     }
 
-    #[tokio::test]
-    async fn test_performance_simulation() {
-        let start = std::time::Instant::now();
-        let iterations = 100;
-
-        for i in 0..iterations {
-            let _ = forward_stdio_to_remote_demo(1);
-            forward_remote_to_stdio_demo();
-
-            if i % 20 == 0 {
-                tokio::task::yield_now().await; // Yield control periodically
-            }
-        }
-
-        let duration = start.elapsed();
-        println!("✅ Integration test: {iterations} iterations completed in {duration:?}");
-
-        // Simple performance check - should complete quickly
-        assert!(
-            duration < Duration::from_secs(2),
-            "Performance test should complete quickly"
-        );
-    }
-
     // Helper function for endpoint connection testing
     fn test_connection_to_endpoint(endpoint: &str) -> Result<()> {
         // Validate URL first
@@ -720,6 +973,13 @@ mod tests {
         assert!(validate_url("not-a-url").is_err());
     }
 
+    #[test]
+    fn test_file_url_validation() {
+        // Recorded sessions are replayed from absolute file paths
+        assert!(validate_url("file:///tmp/session.jsonl").is_ok());
+        assert!(validate_url("file://relative").is_err());
+    }
+
     #[test]
     fn test_url_validation_logic() {
         // Valid URLs
@@ -0,0 +1,567 @@
+//! Prometheus metrics for the bridge
+//!
+//! With `--metrics-addr` (or `metrics_addr`), `GET /metrics` on that address serves the
+//! Prometheus text format: upstream request counts and latency histograms per method and tool,
+//! retries per method and tool, upstream errors by kind, connections and reconnections per
+//! upstream, circuit breaker states, OAuth refreshes, cache hits and misses, and the number of
+//! requests in flight.
+
+use crate::breaker::CircuitState;
+use anyhow::{anyhow, Result};
+use rmcp::model::ClientRequest;
+use rmcp::service::ServiceError;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Gauge of each upstream's [`CircuitState`], also read back by `doctor`
+pub const CIRCUIT_STATE: &str = "deepwiki_bridge_circuit_state";
+
+/// Largest HTTP request head read from a scraper
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    upstream: String,
+    method: &'static str,
+    /// Empty for requests other than `tools/call`
+    tool: String,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ConnectionStats {
+    connects: u64,
+    reconnects: u64,
+    oauth_refreshes: u64,
+}
+
+#[derive(Debug)]
+struct CircuitStats {
+    state: CircuitState,
+    opens: u64,
+}
+
+/// Name, help text and value of a per-upstream connection counter
+type ConnectionFamily = (&'static str, &'static str, fn(&ConnectionStats) -> u64);
+
+const CONNECTION_FAMILIES: [ConnectionFamily; 3] = [
+    (
+        "deepwiki_bridge_upstream_connects_total",
+        "Connection attempts per upstream",
+        |stats| stats.connects,
+    ),
+    (
+        "deepwiki_bridge_upstream_reconnects_total",
+        "Connection attempts after an upstream's first",
+        |stats| stats.reconnects,
+    ),
+    (
+        "deepwiki_bridge_oauth_refreshes_total",
+        "OAuth authorizations repeated on reconnect",
+        |stats| stats.oauth_refreshes,
+    ),
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, Histogram>>,
+    retries: Mutex<BTreeMap<RequestLabels, u64>>,
+    upstream_errors: Mutex<BTreeMap<(String, &'static str), u64>>,
+    connections: Mutex<BTreeMap<String, ConnectionStats>>,
+    circuits: Mutex<BTreeMap<String, CircuitStats>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    in_flight: AtomicI64,
+}
+
+/// Counts a request as in flight until dropped, including when it is cancelled
+pub struct InFlight<'a>(&'a Metrics);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn start_request(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self)
+    }
+
+    pub fn observe_request(
+        &self,
+        upstream: &str,
+        (method, tool): (&'static str, String),
+        elapsed: Duration,
+    ) {
+        let labels = RequestLabels {
+            upstream: upstream.to_string(),
+            method,
+            tool,
+        };
+        if let Ok(mut requests) = self.requests.lock() {
+            requests
+                .entry(labels)
+                .or_default()
+                .observe(elapsed.as_secs_f64());
+        }
+    }
+
+    /// Count a request sent again after a transient failure
+    pub fn record_retry(&self, upstream: &str, (method, tool): (&'static str, String)) {
+        let labels = RequestLabels {
+            upstream: upstream.to_string(),
+            method,
+            tool,
+        };
+        if let Ok(mut retries) = self.retries.lock() {
+            *retries.entry(labels).or_default() += 1;
+        }
+    }
+
+    pub fn record_upstream_error(&self, upstream: &str, error: &ServiceError) {
+        if let Ok(mut errors) = self.upstream_errors.lock() {
+            *errors
+                .entry((upstream.to_string(), error_kind(error)))
+                .or_default() += 1;
+        }
+    }
+
+    /// Count a connection attempt; every attempt after an upstream's first is a reconnect,
+    /// and for OAuth upstreams also a token refresh
+    pub fn record_connection(&self, upstream: &str, oauth: bool) {
+        if let Ok(mut connections) = self.connections.lock() {
+            let stats = connections.entry(upstream.to_string()).or_default();
+            if stats.connects > 0 {
+                stats.reconnects += 1;
+                if oauth {
+                    stats.oauth_refreshes += 1;
+                }
+            }
+            stats.connects += 1;
+        }
+    }
+
+    /// Note an upstream's circuit entering `state`
+    pub fn record_circuit_state(&self, upstream: &str, state: CircuitState) {
+        if let Ok(mut circuits) = self.circuits.lock() {
+            let stats = circuits
+                .entry(upstream.to_string())
+                .or_insert(CircuitStats { state, opens: 0 });
+            stats.state = state;
+            if state == CircuitState::Open {
+                stats.opens += 1;
+            }
+        }
+    }
+
+    pub fn record_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().map(|r| {
+            r.iter()
+                .map(|(labels, histogram)| {
+                    let labels = format!(
+                        "upstream=\"{}\",method=\"{}\",tool=\"{}\"",
+                        escape(&labels.upstream),
+                        labels.method,
+                        escape(&labels.tool)
+                    );
+                    (labels, histogram.buckets, histogram.sum, histogram.count)
+                })
+                .collect::<Vec<_>>()
+        });
+        let requests = requests.unwrap_or_default();
+        header(
+            &mut out,
+            "deepwiki_bridge_upstream_requests_total",
+            "counter",
+            "Requests forwarded to upstream servers",
+        );
+        for (labels, _, _, count) in &requests {
+            let _ = writeln!(
+                out,
+                "deepwiki_bridge_upstream_requests_total{{{labels}}} {count}"
+            );
+        }
+        let name = "deepwiki_bridge_upstream_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Latency of requests forwarded to upstream servers",
+        );
+        for (labels, buckets, sum, count) in &requests {
+            for (bound, bucket) in LATENCY_BUCKETS.iter().zip(buckets) {
+                let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {bucket}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+            let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+        }
+
+        header(
+            &mut out,
+            "deepwiki_bridge_upstream_retries_total",
+            "counter",
+            "Requests sent again after a transient upstream failure",
+        );
+        if let Ok(retries) = self.retries.lock() {
+            for (labels, count) in retries.iter() {
+                let _ = writeln!(
+                    out,
+                    "deepwiki_bridge_upstream_retries_total{{upstream=\"{}\",method=\"{}\",tool=\"{}\"}} {count}",
+                    escape(&labels.upstream),
+                    labels.method,
+                    escape(&labels.tool)
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "deepwiki_bridge_upstream_errors_total",
+            "counter",
+            "Failed upstream requests by kind",
+        );
+        if let Ok(errors) = self.upstream_errors.lock() {
+            for ((upstream, kind), count) in errors.iter() {
+                let _ = writeln!(
+                    out,
+                    "deepwiki_bridge_upstream_errors_total{{upstream=\"{}\",kind=\"{kind}\"}} {count}",
+                    escape(upstream)
+                );
+            }
+        }
+
+        if let Ok(connections) = self.connections.lock() {
+            for (name, help, value) in CONNECTION_FAMILIES {
+                header(&mut out, name, "counter", help);
+                for (upstream, stats) in connections.iter() {
+                    let _ = writeln!(
+                        out,
+                        "{name}{{upstream=\"{}\"}} {}",
+                        escape(upstream),
+                        value(stats)
+                    );
+                }
+            }
+        }
+
+        if let Ok(circuits) = self.circuits.lock() {
+            header(
+                &mut out,
+                CIRCUIT_STATE,
+                "gauge",
+                "Circuit breaker state per upstream: 0 closed, 1 half-open, 2 open",
+            );
+            for (upstream, stats) in circuits.iter() {
+                let _ = writeln!(
+                    out,
+                    "{CIRCUIT_STATE}{{upstream=\"{}\"}} {}",
+                    escape(upstream),
+                    stats.state.gauge()
+                );
+            }
+            header(
+                &mut out,
+                "deepwiki_bridge_circuit_opens_total",
+                "counter",
+                "Times an upstream's circuit opened",
+            );
+            for (upstream, stats) in circuits.iter() {
+                let _ = writeln!(
+                    out,
+                    "deepwiki_bridge_circuit_opens_total{{upstream=\"{}\"}} {}",
+                    escape(upstream),
+                    stats.opens
+                );
+            }
+        }
+
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let misses = self.cache_misses.load(Ordering::Relaxed);
+        header(
+            &mut out,
+            "deepwiki_bridge_cache_hits_total",
+            "counter",
+            "Tool calls answered from the response cache",
+        );
+        let _ = writeln!(out, "deepwiki_bridge_cache_hits_total {hits}");
+        header(
+            &mut out,
+            "deepwiki_bridge_cache_misses_total",
+            "counter",
+            "Cacheable tool calls the response cache could not answer",
+        );
+        let _ = writeln!(out, "deepwiki_bridge_cache_misses_total {misses}");
+        header(
+            &mut out,
+            "deepwiki_bridge_cache_hit_ratio",
+            "gauge",
+            "Share of cacheable tool calls answered from the cache",
+        );
+        let lookups = hits + misses;
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = if lookups == 0 {
+            0.0
+        } else {
+            hits as f64 / lookups as f64
+        };
+        let _ = writeln!(out, "deepwiki_bridge_cache_hit_ratio {ratio}");
+
+        header(
+            &mut out,
+            "deepwiki_bridge_in_flight_requests",
+            "gauge",
+            "Requests currently awaiting an upstream response",
+        );
+        let _ = writeln!(
+            out,
+            "deepwiki_bridge_in_flight_requests {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The circuit states in a scraped metrics page, by upstream
+pub fn parse_circuit_states(text: &str) -> BTreeMap<String, CircuitState> {
+    let prefix = format!("{CIRCUIT_STATE}{{upstream=\"");
+    text.lines()
+        .filter_map(|line| {
+            let (upstream, value) = line.strip_prefix(&prefix)?.rsplit_once("\"} ")?;
+            let state = CircuitState::from_gauge(value.trim().parse().ok()?)?;
+            Some((upstream.to_string(), state))
+        })
+        .collect()
+}
+
+/// Method and tool labels for a request, taken before it is sent
+pub fn describe(request: &ClientRequest) -> (&'static str, String) {
+    let tool = match request {
+        ClientRequest::CallToolRequest(call) => call.params.name.to_string(),
+        _ => String::new(),
+    };
+    (method_name(request), tool)
+}
+
+pub fn method_name(request: &ClientRequest) -> &'static str {
+    match request {
+        ClientRequest::InitializeRequest(_) => "initialize",
+        ClientRequest::PingRequest(_) => "ping",
+        ClientRequest::CallToolRequest(_) => "tools/call",
+        ClientRequest::ListToolsRequest(_) => "tools/list",
+        ClientRequest::ListPromptsRequest(_) => "prompts/list",
+        ClientRequest::GetPromptRequest(_) => "prompts/get",
+        ClientRequest::ListResourcesRequest(_) => "resources/list",
+        ClientRequest::ListResourceTemplatesRequest(_) => "resources/templates/list",
+        ClientRequest::ReadResourceRequest(_) => "resources/read",
+        ClientRequest::CompleteRequest(_) => "completion/complete",
+        _ => "other",
+    }
+}
+
+fn error_kind(error: &ServiceError) -> &'static str {
+    match error {
+        ServiceError::McpError(_) => "mcp_error",
+        ServiceError::TransportSend(_) => "transport_send",
+        ServiceError::TransportClosed => "transport_closed",
+        ServiceError::UnexpectedResponse => "unexpected_response",
+        ServiceError::Cancelled { .. } => "cancelled",
+        ServiceError::Timeout { .. } => "timeout",
+        _ => "other",
+    }
+}
+
+/// Serve `GET /metrics` on `addr` in the background
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("Cannot serve metrics on {}: {}", addr, e))?;
+    info!("Serving Prometheus metrics at http://{}/metrics", addr);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let metrics = metrics.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream, &metrics).await {
+                            debug!("Metrics request from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept metrics connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{CallToolRequest, CallToolRequestParam};
+
+    fn ask_question() -> ClientRequest {
+        ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+            name: "ask_question".into(),
+            arguments: None,
+        }))
+    }
+
+    #[test]
+    fn test_renders_request_and_cache_metrics() {
+        let metrics = Metrics::default();
+        {
+            let _in_flight = metrics.start_request();
+            assert!(metrics
+                .render()
+                .contains("deepwiki_bridge_in_flight_requests 1"));
+        }
+        metrics.observe_request(
+            "deepwiki",
+            describe(&ask_question()),
+            Duration::from_millis(300),
+        );
+        metrics.record_upstream_error("deepwiki", &ServiceError::TransportClosed);
+        metrics.record_retry("deepwiki", describe(&ask_question()));
+        metrics.record_cache_lookup(true);
+        metrics.record_cache_lookup(false);
+        metrics.record_connection("devin", true);
+        metrics.record_connection("devin", true);
+        metrics.record_circuit_state("devin", CircuitState::Open);
+
+        let text = metrics.render();
+        let labels = r#"upstream="deepwiki",method="tools/call",tool="ask_question""#;
+        assert!(text.contains(&format!(
+            "deepwiki_bridge_upstream_requests_total{{{labels}}} 1"
+        )));
+        assert!(text.contains(&format!(
+            "deepwiki_bridge_upstream_request_duration_seconds_bucket{{{labels},le=\"0.25\"}} 0"
+        )));
+        assert!(text.contains(&format!(
+            "deepwiki_bridge_upstream_request_duration_seconds_bucket{{{labels},le=\"0.5\"}} 1"
+        )));
+        assert!(text.contains(
+            r#"deepwiki_bridge_upstream_errors_total{upstream="deepwiki",kind="transport_closed"} 1"#
+        ));
+        assert!(text.contains(&format!(
+            "deepwiki_bridge_upstream_retries_total{{{labels}}} 1"
+        )));
+        assert!(text.contains(r#"deepwiki_bridge_upstream_reconnects_total{upstream="devin"} 1"#));
+        assert!(text.contains(r#"deepwiki_bridge_oauth_refreshes_total{upstream="devin"} 1"#));
+        assert!(text.contains(r#"deepwiki_bridge_circuit_opens_total{upstream="devin"} 1"#));
+        assert_eq!(
+            parse_circuit_states(&text).get("devin"),
+            Some(&CircuitState::Open)
+        );
+        assert!(text.contains("deepwiki_bridge_cache_hit_ratio 0.5"));
+        assert!(text.contains("deepwiki_bridge_in_flight_requests 0"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let metrics = Arc::new(Metrics::default());
+        serve(addr, metrics).await.unwrap();
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("deepwiki_bridge_cache_hits_total 0"));
+    }
+}
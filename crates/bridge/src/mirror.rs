@@ -0,0 +1,311 @@
+//! Mirror a repository's wiki to a local Markdown tree
+//!
+//! `read_wiki_structure` provides the numbered table of contents and `read_wiki_contents`
+//! returns every page in one response, separated by `# Page: <title>` headings. Pages are
+//! written as `NN-slug.md` files nested under their parent section, with an `index.md` linking
+//! them in order. Both responses also seed the response cache so the proxy can serve the
+//! repository offline, and the pages are added to the local search index.
+
+use crate::cache::ResponseCache;
+use crate::commands::shutdown;
+use crate::connect_remote;
+use crate::search::WikiSearch;
+use crate::settings::BridgeSettings;
+use anyhow::{anyhow, Result};
+use rmcp::model::{CallToolRequestParam, CallToolResult};
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const STRUCTURE_TOOL: &str = "read_wiki_structure";
+const CONTENTS_TOOL: &str = "read_wiki_contents";
+const PAGE_MARKER: &str = "# Page: ";
+
+/// A page in the wiki's table of contents, e.g. `1.2 Architecture`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WikiPage {
+    /// Section number parts; `[1, 2]` for `1.2`
+    number: Vec<u32>,
+    title: String,
+}
+
+/// Mirror `repo`'s wiki into `<out>/<owner>/<repo>`
+pub async fn run(endpoint_url: &str, repo: &str, out: &Path) -> Result<()> {
+    let arguments = json!({ "repoName": repo });
+    let client = connect_remote(endpoint_url, None).await?;
+    let fetched = async {
+        let structure = call_text_tool(&client, STRUCTURE_TOOL, &arguments).await?;
+        let contents = call_text_tool(&client, CONTENTS_TOOL, &arguments).await?;
+        Ok::<_, anyhow::Error>((structure, contents))
+    }
+    .await;
+    shutdown(client).await;
+    let (structure, contents) = fetched?;
+
+    let settings = BridgeSettings::from_env();
+    if let Some(cache) = ResponseCache::from_settings(&settings.cache) {
+        for (tool, result) in [(STRUCTURE_TOOL, &structure), (CONTENTS_TOOL, &contents)] {
+            if let Err(e) = cache.put(endpoint_url, tool, &arguments, result) {
+                warn!("Failed to cache {} response: {}", tool, e);
+            }
+        }
+    }
+
+    let contents = result_text(&contents);
+    if let Some(search) = WikiSearch::open(&settings.search) {
+        if let Err(e) = search.add_wiki(repo, &contents) {
+            warn!("Failed to store pages for search: {}", e);
+        }
+    }
+
+    let toc = parse_structure(&result_text(&structure));
+    let pages = split_contents(&contents);
+    let pages = assign_pages(&toc, pages);
+
+    let root = out.join(repo);
+    fs::create_dir_all(&root).map_err(|e| anyhow!("Failed to create {}: {}", root.display(), e))?;
+    for (page, body) in &pages {
+        let path = root.join(page_path(page, &pages));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, body).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+    }
+    let index = render_index(repo, &pages);
+    fs::write(root.join("index.md"), index).map_err(|e| anyhow!("Failed to write index: {}", e))?;
+
+    println!("Mirrored {} pages to {}", pages.len(), root.display());
+    Ok(())
+}
+
+async fn call_text_tool(
+    client: &crate::RemoteClient,
+    tool: &str,
+    arguments: &Value,
+) -> Result<CallToolResult> {
+    let result = client
+        .call_tool(CallToolRequestParam {
+            name: tool.to_string().into(),
+            arguments: arguments.as_object().cloned(),
+        })
+        .await
+        .map_err(|e| anyhow!("Tool call '{}' failed: {}", tool, e))?;
+    if result.is_error == Some(true) {
+        return Err(anyhow!(
+            "Tool '{}' reported an error: {}",
+            tool,
+            result_text(&result)
+        ));
+    }
+    Ok(result)
+}
+
+pub fn result_text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text().map(|text| text.text.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parse the table of contents, e.g. `- 1 Overview` / `  - 1.1 Architecture`
+fn parse_structure(text: &str) -> Vec<WikiPage> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches(['-', '*']).trim_start();
+            let (number, title) = line.split_once(char::is_whitespace)?;
+            let number: Vec<u32> = number
+                .trim_end_matches('.')
+                .split('.')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            Some(WikiPage {
+                number,
+                title: title.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Split the combined contents into `(title, markdown)` pairs
+pub fn split_contents(text: &str) -> Vec<(String, String)> {
+    let mut pages: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if let Some(title) = line.strip_prefix(PAGE_MARKER) {
+            pages.push((title.trim().to_string(), String::new()));
+        } else if let Some((_, body)) = pages.last_mut() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+
+    if pages.is_empty() && !text.trim().is_empty() {
+        pages.push(("Overview".to_string(), text.to_string()));
+    }
+    for (_, body) in &mut pages {
+        *body = format!("{}\n", body.trim());
+    }
+    pages
+}
+
+/// Pair content pages with their table-of-contents entries, numbering any extras at the end
+fn assign_pages(toc: &[WikiPage], pages: Vec<(String, String)>) -> Vec<(WikiPage, String)> {
+    let mut used = vec![false; toc.len()];
+    let mut next_top = toc.iter().filter_map(|p| p.number.first()).max().copied();
+
+    let mut assigned: Vec<(WikiPage, String)> = pages
+        .into_iter()
+        .map(|(title, body)| {
+            let matched = toc
+                .iter()
+                .enumerate()
+                .find(|(i, page)| !used[*i] && page.title == title);
+            let page = if let Some((i, page)) = matched {
+                used[i] = true;
+                page.clone()
+            } else {
+                let number = next_top.map_or(1, |n| n + 1);
+                next_top = Some(number);
+                WikiPage {
+                    number: vec![number],
+                    title,
+                }
+            };
+            (page, body)
+        })
+        .collect();
+    assigned.sort_by(|(a, _), (b, _)| a.number.cmp(&b.number));
+    assigned
+}
+
+/// Relative path for a page: `01-overview.md`, or `01-overview/02-architecture.md` for 1.2
+fn page_path(page: &WikiPage, pages: &[(WikiPage, String)]) -> PathBuf {
+    let mut path = PathBuf::new();
+    for depth in 1..page.number.len() {
+        let prefix = &page.number[..depth];
+        let title = pages
+            .iter()
+            .find(|(p, _)| p.number == prefix)
+            .map_or("section", |(p, _)| p.title.as_str());
+        path.push(file_stem(prefix, title));
+    }
+    path.push(format!("{}.md", file_stem(&page.number, &page.title)));
+    path
+}
+
+fn file_stem(number: &[u32], title: &str) -> String {
+    format!(
+        "{:02}-{}",
+        number.last().copied().unwrap_or(0),
+        slugify(title)
+    )
+}
+
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "page".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+fn render_index(repo: &str, pages: &[(WikiPage, String)]) -> String {
+    let mut index = format!("# {repo}\n\nMirrored from DeepWiki.\n\n");
+    for (page, _) in pages {
+        let number: Vec<String> = page.number.iter().map(u32::to_string).collect();
+        let link = page_path(page, pages).to_string_lossy().replace('\\', "/");
+        let _ = writeln!(
+            index,
+            "{}- [{} {}]({})",
+            "  ".repeat(page.number.len().saturating_sub(1)),
+            number.join("."),
+            page.title,
+            link
+        );
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRUCTURE: &str = "Available pages for tokio-rs/tokio:\n\n\
+        - 1 Overview\n  - 1.1 Runtime Architecture\n- 2 I/O & Networking\n";
+
+    fn page(number: &[u32], title: &str) -> WikiPage {
+        WikiPage {
+            number: number.to_vec(),
+            title: title.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_structure() {
+        assert_eq!(
+            parse_structure(STRUCTURE),
+            vec![
+                page(&[1], "Overview"),
+                page(&[1, 1], "Runtime Architecture"),
+                page(&[2], "I/O & Networking"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_and_assign_pages() {
+        let contents = "# Page: Overview\n\n# Overview\nTokio is...\n\
+            # Page: Runtime Architecture\n\nThe scheduler\n# Page: Glossary\nTerms\n";
+        let pages = assign_pages(&parse_structure(STRUCTURE), split_contents(contents));
+
+        let titles: Vec<(Vec<u32>, &str)> = pages
+            .iter()
+            .map(|(p, _)| (p.number.clone(), p.title.as_str()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                (vec![1], "Overview"),
+                (vec![1, 1], "Runtime Architecture"),
+                (vec![3], "Glossary"),
+            ]
+        );
+        assert_eq!(pages[0].1, "# Overview\nTokio is...\n");
+    }
+
+    #[test]
+    fn test_page_paths_and_index() {
+        let pages = vec![
+            (page(&[1], "Overview"), String::new()),
+            (page(&[1, 1], "Runtime Architecture"), String::new()),
+            (page(&[2], "I/O & Networking"), String::new()),
+        ];
+        assert_eq!(
+            page_path(&pages[1].0, &pages),
+            PathBuf::from("01-overview").join("01-runtime-architecture.md")
+        );
+        assert_eq!(
+            page_path(&pages[2].0, &pages),
+            PathBuf::from("02-i-o-networking.md")
+        );
+
+        let index = render_index("tokio-rs/tokio", &pages);
+        assert!(index.contains("- [1 Overview](01-overview.md)\n"));
+        assert!(index
+            .contains("  - [1.1 Runtime Architecture](01-overview/01-runtime-architecture.md)\n"));
+    }
+}
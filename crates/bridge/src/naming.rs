@@ -0,0 +1,113 @@
+//! Names and descriptions under which tools are presented to Zed
+//!
+//! Every tool name gets the configured prefix (e.g. `deepwiki_`), after any per-tool rename,
+//! and descriptions can be replaced to steer the model's tool choice. Overrides are keyed by
+//! the original name, namespaced as `<upstream>__<tool>` when there are several upstreams.
+//! `tools/call` requests are mapped back to that name before anything else sees them, so
+//! filters and tool settings all work on the original names.
+
+use crate::settings::ToolSettings;
+use rmcp::model::Tool;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolNaming {
+    prefix: String,
+    /// Upstream name to exposed name, before the prefix
+    rename: HashMap<String, String>,
+    /// Exposed name (before the prefix) back to the upstream name
+    reverse: HashMap<String, String>,
+    descriptions: HashMap<String, String>,
+}
+
+impl ToolNaming {
+    pub fn new(settings: &ToolSettings) -> Self {
+        Self {
+            prefix: settings.prefix.clone(),
+            rename: settings.rename.clone(),
+            reverse: settings
+                .rename
+                .iter()
+                .map(|(original, renamed)| (renamed.clone(), original.clone()))
+                .collect(),
+            descriptions: settings.descriptions.clone(),
+        }
+    }
+
+    /// Apply the description override and the exposed name to an upstream tool
+    pub fn expose(&self, tool: &mut Tool) {
+        if let Some(description) = self.descriptions.get(tool.name.as_ref()) {
+            tool.description = Some(description.clone().into());
+        }
+        tool.name = self.exposed_name(&tool.name).into();
+    }
+
+    pub fn exposed_name(&self, original: &str) -> String {
+        let name = self.rename.get(original).map_or(original, String::as_str);
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// Map an exposed tool name back to the upstream name; `None` if no tool is exposed as `name`
+    pub fn original_name(&self, name: &str) -> Option<String> {
+        let name = name.strip_prefix(&self.prefix)?;
+        match self.reverse.get(name) {
+            Some(original) => Some(original.clone()),
+            // A renamed tool is no longer reachable under its upstream name
+            None if self.rename.contains_key(name) => None,
+            None => Some(name.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naming(settings: &str) -> ToolNaming {
+        let settings: ToolSettings = serde_json::from_str(settings).unwrap();
+        ToolNaming::new(&settings)
+    }
+
+    #[test]
+    fn test_prefix_and_renames_round_trip() {
+        let naming = naming(
+            r#"{"prefix": "deepwiki_", "rename": {"ask_question": "ask"},
+                "descriptions": {"ask_question": "Ask about a GitHub repository"}}"#,
+        );
+
+        let mut tool = Tool::new("ask_question", "Ask", serde_json::Map::new());
+        naming.expose(&mut tool);
+        assert_eq!(tool.name, "deepwiki_ask");
+        assert_eq!(
+            tool.description.as_deref(),
+            Some("Ask about a GitHub repository")
+        );
+        assert_eq!(
+            naming.exposed_name("read_wiki_structure"),
+            "deepwiki_read_wiki_structure"
+        );
+
+        assert_eq!(
+            naming.original_name("deepwiki_ask").as_deref(),
+            Some("ask_question")
+        );
+        assert_eq!(
+            naming
+                .original_name("deepwiki_read_wiki_structure")
+                .as_deref(),
+            Some("read_wiki_structure")
+        );
+        assert_eq!(naming.original_name("deepwiki_ask_question"), None);
+        assert_eq!(naming.original_name("read_wiki_structure"), None);
+    }
+
+    #[test]
+    fn test_default_naming_is_identity() {
+        let naming = ToolNaming::default();
+        assert_eq!(naming.exposed_name("ask_question"), "ask_question");
+        assert_eq!(
+            naming.original_name("ask_question").as_deref(),
+            Some("ask_question")
+        );
+    }
+}
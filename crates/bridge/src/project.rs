@@ -0,0 +1,329 @@
+//! Detect the `owner/repo` of the project the bridge is serving
+//!
+//! Zed's extension API only exposes worktree IDs to `context_server_command`, so detection
+//! happens here instead: from the directory Zed starts the bridge in (the project root), or from
+//! the roots the MCP client advertises. Sources are tried in order: the git `origin` remote
+//! (falling back to any other remote), then the `repository` field of `Cargo.toml`, then that of
+//! `package.json`. Only GitHub repositories are recognized, since those are what DeepWiki indexes.
+
+use crate::arguments::{make_optional, property_mut};
+use crate::dependencies::ProjectDependencies;
+use rmcp::model::{JsonObject, Tool};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// DeepWiki tools (and the bridge's section reader) whose `repoName` argument may be filled in
+/// from the project
+pub const REPO_TOOLS: &[&str] = &[
+    "read_wiki_structure",
+    "read_wiki_contents",
+    "ask_question",
+    "read_wiki_section",
+];
+pub const REPO_ARGUMENT: &str = "repoName";
+
+/// What the bridge knows about the open project, filled in further once the client's roots are known
+#[derive(Clone, Default)]
+pub struct ProjectContext {
+    pub repository: DefaultRepository,
+    pub dependencies: ProjectDependencies,
+}
+
+/// The project's repository, used when a DeepWiki tool call omits `repoName`
+///
+/// Shared so it can be filled in after startup, once the client has reported its roots.
+#[derive(Clone, Default)]
+pub struct DefaultRepository(Arc<RwLock<Option<String>>>);
+
+impl DefaultRepository {
+    pub fn new(repo: Option<String>) -> Self {
+        Self(Arc::new(RwLock::new(repo)))
+    }
+
+    pub fn get(&self) -> Option<String> {
+        self.0.read().ok().and_then(|repo| repo.clone())
+    }
+
+    pub fn set(&self, repo: String) {
+        if let Ok(mut current) = self.0.write() {
+            *current = Some(repo);
+        }
+    }
+
+    /// Insert `repoName` into a DeepWiki tool call that lacks one
+    pub fn fill_arguments(&self, tool: &str, arguments: &mut Option<JsonObject>) {
+        if !REPO_TOOLS.contains(&tool) {
+            return;
+        }
+        let Some(repo) = self.get() else {
+            return;
+        };
+        let arguments = arguments.get_or_insert_with(JsonObject::new);
+        let given = matches!(
+            arguments.get(REPO_ARGUMENT),
+            Some(Value::String(name)) if !name.trim().is_empty()
+        );
+        if !given {
+            arguments.insert(REPO_ARGUMENT.to_string(), Value::String(repo));
+        }
+    }
+
+    /// Make `repoName` optional in the tool's schema and document the default
+    pub fn rewrite_schema(&self, tool: &mut Tool) {
+        if !REPO_TOOLS.contains(&tool.name.as_ref()) {
+            return;
+        }
+        let Some(repo) = self.get() else {
+            return;
+        };
+
+        let mut schema = tool.input_schema.as_ref().clone();
+        // A configured default or pin takes precedence over the project's repository
+        let configured = property_mut(&mut schema, REPO_ARGUMENT)
+            .is_none_or(|property| property.contains_key("default"));
+        if configured {
+            return;
+        }
+        make_optional(
+            &mut schema,
+            REPO_ARGUMENT,
+            &format!("defaults to the current project, {repo}"),
+        );
+        tool.input_schema = Arc::new(schema);
+    }
+}
+
+/// Find the repository for `dir` or the nearest ancestor that identifies one
+pub fn detect_repository(dir: &Path) -> Option<String> {
+    dir.ancestors().find_map(|dir| {
+        git_remote_repository(dir)
+            .or_else(|| cargo_repository(dir))
+            .or_else(|| package_json_repository(dir))
+    })
+}
+
+/// Repository of the git checkout rooted at `dir`, if any
+fn git_remote_repository(dir: &Path) -> Option<String> {
+    let config = fs::read_to_string(git_dir(dir)?.join("config")).ok()?;
+    let remotes = parse_git_remotes(&config);
+    remotes
+        .iter()
+        .filter(|(name, _)| name == "origin")
+        .chain(remotes.iter())
+        .find_map(|(_, url)| parse_github_url(url))
+}
+
+/// Resolve `.git`, following the `gitdir:` file used by worktrees and submodules
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+
+    let pointer = fs::read_to_string(&dot_git).ok()?;
+    let git_dir = dir.join(pointer.strip_prefix("gitdir:")?.trim());
+    // Linked worktrees keep their config in the common directory
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => Some(git_dir.join(common.trim())),
+        Err(_) => Some(git_dir),
+    }
+}
+
+/// `(name, url)` for every `[remote "name"]` section of a git config
+fn parse_git_remotes(config: &str) -> Vec<(String, String)> {
+    let mut remotes = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            current = line
+                .strip_prefix("[remote \"")
+                .and_then(|rest| rest.strip_suffix("\"]"))
+                .map(str::to_string);
+        } else if let (Some(name), Some((key, value))) = (&current, line.split_once('=')) {
+            if key.trim() == "url" {
+                remotes.push((name.clone(), value.trim().to_string()));
+            }
+        }
+    }
+    remotes
+}
+
+/// `repository` from `[package]` or `[workspace.package]` in `Cargo.toml`
+pub fn cargo_repository(dir: &Path) -> Option<String> {
+    let manifest = fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+    let mut in_package = false;
+
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = matches!(line, "[package]" | "[workspace.package]");
+        } else if in_package {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "repository" {
+                    return parse_github_url(value.trim().trim_matches('"'));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// `repository` from `package.json`, as a string or `{ "url": ... }`
+pub fn package_json_repository(dir: &Path) -> Option<String> {
+    let manifest: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("package.json")).ok()?).ok()?;
+    let repository = manifest.get("repository")?;
+    let url = repository
+        .as_str()
+        .or_else(|| repository.get("url").and_then(Value::as_str))?;
+
+    // npm shorthands: "github:owner/repo" and plain "owner/repo"
+    let shorthand = url.strip_prefix("github:").unwrap_or(url);
+    if !shorthand.contains(':') && shorthand.split('/').count() == 2 {
+        return normalize_repo(shorthand);
+    }
+    parse_github_url(url)
+}
+
+/// Extract `owner/repo` from a GitHub HTTPS, SSH or scp-style URL
+pub fn parse_github_url(url: &str) -> Option<String> {
+    let url = url.trim().trim_start_matches("git+");
+    let path = if let Some(rest) = url.strip_prefix("git@github.com:") {
+        rest
+    } else {
+        let (_, rest) = url.split_once("://")?;
+        let (host, path) = rest.split_once('/')?;
+        let host = host.rsplit('@').next()?;
+        if !host.eq_ignore_ascii_case("github.com") && !host.eq_ignore_ascii_case("www.github.com")
+        {
+            return None;
+        }
+        path
+    };
+    normalize_repo(path)
+}
+
+fn normalize_repo(path: &str) -> Option<String> {
+    let mut parts = path.trim_matches('/').split('/');
+    let owner = parts.next().filter(|owner| !owner.is_empty())?;
+    let repo = parts.next()?;
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    (!repo.is_empty()).then(|| format!("{owner}/{repo}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_repository_fills_and_rewrites() {
+        let default = DefaultRepository::new(Some("zed-industries/zed".to_string()));
+
+        let mut arguments = None;
+        default.fill_arguments("read_wiki_structure", &mut arguments);
+        assert_eq!(
+            arguments.unwrap().get(REPO_ARGUMENT),
+            Some(&Value::String("zed-industries/zed".to_string()))
+        );
+
+        let mut arguments = serde_json::json!({"repoName": "a/b"}).as_object().cloned();
+        default.fill_arguments("ask_question", &mut arguments);
+        assert_eq!(arguments.unwrap()[REPO_ARGUMENT], "a/b");
+
+        let mut arguments = None;
+        default.fill_arguments("search_cached_wiki", &mut arguments);
+        assert!(arguments.is_none());
+
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"repoName": {"type": "string"}, "question": {"type": "string"}},
+            "required": ["repoName", "question"]
+        });
+        let mut tool = Tool::new("ask_question", "Ask", schema.as_object().cloned().unwrap());
+        default.rewrite_schema(&mut tool);
+        assert_eq!(
+            tool.input_schema["required"],
+            serde_json::json!(["question"])
+        );
+        assert!(tool.input_schema["properties"]["repoName"]["description"]
+            .as_str()
+            .unwrap()
+            .contains("zed-industries/zed"));
+    }
+
+    #[test]
+    fn test_parse_github_urls() {
+        for url in [
+            "https://github.com/tokio-rs/tokio",
+            "https://github.com/tokio-rs/tokio.git",
+            "git@github.com:tokio-rs/tokio.git",
+            "ssh://git@github.com/tokio-rs/tokio.git",
+            "git+https://github.com/tokio-rs/tokio.git",
+            "https://github.com/tokio-rs/tokio/tree/master/tokio",
+        ] {
+            assert_eq!(
+                parse_github_url(url).as_deref(),
+                Some("tokio-rs/tokio"),
+                "{url}"
+            );
+        }
+        assert_eq!(parse_github_url("https://gitlab.com/a/b"), None);
+        assert_eq!(parse_github_url("https://github.com/only-owner"), None);
+    }
+
+    #[test]
+    fn test_git_remote_prefers_origin() {
+        let config = "[core]\n\tbare = false\n\
+            [remote \"upstream\"]\n\turl = https://github.com/rust-lang/cargo.git\n\
+            [remote \"origin\"]\n\turl = git@github.com:me/cargo.git\n\
+            \tfetch = +refs/heads/*:refs/remotes/origin/*\n";
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/config"), config).unwrap();
+
+        assert_eq!(parse_git_remotes(config).len(), 2);
+        assert_eq!(detect_repository(dir.path()).as_deref(), Some("me/cargo"));
+
+        // Subdirectories resolve to the enclosing checkout
+        let nested = dir.path().join("src/bin");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(detect_repository(&nested).as_deref(), Some("me/cargo"));
+    }
+
+    #[test]
+    fn test_manifest_repository_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"x\"\nrepository = \"https://github.com/serde-rs/serde\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cargo_repository(dir.path()).as_deref(),
+            Some("serde-rs/serde")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"repository": {"type": "git", "url": "git+https://github.com/vercel/next.js.git"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_repository(dir.path()).as_deref(),
+            Some("vercel/next.js")
+        );
+
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"repository": "github:facebook/react"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package_json_repository(dir.path()).as_deref(),
+            Some("facebook/react")
+        );
+    }
+}
@@ -0,0 +1,1574 @@
+//! MCP server handler that forwards Zed's requests to the remote server
+//!
+//! The bridge serves this handler on STDIO. Each request from Zed is re-issued on the
+//! upstream client session, and cancellations from Zed are propagated upstream. With several
+//! [`Upstreams`], list responses are merged and each request is routed to the owning server.
+//!
+//! Without an upstream session (offline mode) or when the server becomes unreachable, the
+//! tool list and cached tool results are served from the [`ResponseCache`] instead.
+//!
+//! The bridge also provides its own [`SEARCH_TOOL`], answered locally from the pages of every
+//! `read_wiki_contents` response that passes through, and its [`SECTION_TOOL`], which pages
+//! through a repository's `read_wiki_contents` response section by section.
+//!
+//! DeepWiki tool calls that omit `repoName` get the project's repository filled in (see
+//! [`DefaultRepository`](crate::project::DefaultRepository)), and a package name given as
+//! `repoName` is resolved to its repository through the project's dependencies (see
+//! [`ProjectDependencies`](crate::dependencies::ProjectDependencies)).
+//!
+//! Tools, prompts and resources excluded by the user's [`AccessPolicy`] are left out of list
+//! responses, and requests that name them are rejected without reaching the server. Tools are
+//! presented under the names and descriptions configured in [`ToolNaming`].
+
+use crate::arguments::ToolArguments;
+use crate::breaker::{self, CircuitBreaker};
+use crate::cache::{format_timestamp, ResponseCache};
+use crate::client_log::ClientLog;
+use crate::coalesce::{self, Coalescer};
+use crate::dependencies::{dependencies_tool, DEPENDENCIES_TOOL};
+use crate::filter::{AccessPolicy, NameFilter};
+use crate::limits::{self, Limits};
+use crate::metrics::{self, Metrics};
+use crate::mirror::result_text;
+use crate::naming::ToolNaming;
+use crate::project::{detect_repository, ProjectContext, REPO_ARGUMENT, REPO_TOOLS};
+use crate::retry::{HttpFailure, RetryPolicy};
+use crate::routing::{reports_not_indexed, result_not_indexed, Router};
+use crate::search::{search_tool, WikiSearch, SEARCH_TOOL};
+use crate::sections::{
+    read_section, section_tool, SectionRequest, DEFAULT_PAGE_BYTES, SECTION_TOOL,
+};
+use crate::settings::BridgeSettings;
+use crate::telemetry::{self, TRACEPARENT};
+use crate::truncate::truncate_result;
+use crate::upstream::{Upstream, Upstreams};
+use crate::RemoteClient;
+use futures::future::join_all;
+use rmcp::{
+    model::{
+        CallToolRequest, CallToolRequestParam, CallToolResult, CancelledNotificationParam,
+        ClientRequest, CompleteRequest, CompleteRequestParam, CompleteResult, Content, ErrorData,
+        GetMeta, GetPromptRequest, GetPromptRequestParam, GetPromptResult, Implementation,
+        JsonObject, ListPromptsResult, ListResourceTemplatesResult, ListResourcesResult,
+        ListToolsResult, PaginatedRequestParam, PromptsCapability, ReadResourceRequest,
+        ReadResourceRequestParam, ReadResourceResult, Reference, RequestOptionalParam,
+        ResourcesCapability, ServerCapabilities, ServerInfo, ServerResult, SetLevelRequestParam,
+        Tool, ToolsCapability,
+    },
+    service::{NotificationContext, PeerRequestOptions, RequestContext, ServiceError},
+    RoleServer, ServerHandler,
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+type McpError = ErrorData;
+
+/// Unwrap the expected [`ServerResult`] variant or report an unexpected response
+macro_rules! expect_result {
+    ($result:expr, $variant:ident) => {
+        match $result {
+            ServerResult::$variant(result) => Ok(result),
+            _ => Err(McpError::internal_error(
+                "Unexpected response type from upstream server",
+                None,
+            )),
+        }
+    };
+}
+
+pub struct ProxyHandler {
+    upstreams: Upstreams,
+    cache: Option<ResponseCache>,
+    search: Option<WikiSearch>,
+    project: ProjectContext,
+    policy: AccessPolicy,
+    naming: ToolNaming,
+    arguments: ToolArguments,
+    router: Router,
+    retry: RetryPolicy,
+    breakers: HashMap<String, CircuitBreaker>,
+    limits: Limits,
+    coalescer: Coalescer,
+    /// Response size limits by upstream tool name
+    max_response_bytes: HashMap<String, usize>,
+    metrics: Arc<Metrics>,
+    client_log: Arc<ClientLog>,
+    message_count: AtomicU64,
+}
+
+impl ProxyHandler {
+    pub fn new(
+        upstreams: Upstreams,
+        cache: Option<ResponseCache>,
+        search: Option<WikiSearch>,
+        project: ProjectContext,
+        settings: &BridgeSettings,
+        metrics: Arc<Metrics>,
+        client_log: Arc<ClientLog>,
+    ) -> Self {
+        // Routing only applies when aggregating several upstreams
+        let router = match upstreams.sole() {
+            Some(_) => Router::default(),
+            None => Router::new(&settings.routing),
+        };
+        let breakers = upstreams
+            .iter()
+            .map(|upstream| {
+                let breaker =
+                    CircuitBreaker::new(&upstream.name, &settings.circuit_breaker, metrics.clone());
+                (upstream.name.clone(), breaker)
+            })
+            .collect();
+        let limits = Limits::new(
+            upstreams.iter().map(|upstream| upstream.name.as_str()),
+            &settings.limits,
+        );
+        // Limits are keyed by the listed name, so the `read_wiki_contents` limit also covers
+        // each upstream's namespaced copy unless that has a limit of its own
+        let mut max_response_bytes = settings.tools.max_response_bytes.clone();
+        if let Some(&limit) = max_response_bytes.get(WIKI_CONTENTS_TOOL) {
+            for upstream in upstreams.iter() {
+                max_response_bytes
+                    .entry(upstreams.qualify(upstream, WIKI_CONTENTS_TOOL))
+                    .or_insert(limit);
+            }
+        }
+        Self {
+            upstreams,
+            cache,
+            search,
+            project,
+            policy: AccessPolicy::from_settings(settings),
+            naming: ToolNaming::new(&settings.tools),
+            arguments: ToolArguments::new(&settings.tools),
+            router,
+            retry: RetryPolicy::new(&settings.retry),
+            breakers,
+            limits,
+            coalescer: Coalescer::default(),
+            max_response_bytes,
+            metrics,
+            client_log,
+            message_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Call a repository tool on the upstream its `repoName` routes to, falling back when
+    /// that upstream has not indexed the repository
+    async fn call_routed(
+        &self,
+        tool: &str,
+        request: CallToolRequestParam,
+        arguments: &Value,
+        ct: &CancellationToken,
+    ) -> Result<CallToolResult, McpError> {
+        let repo = arguments
+            .get(REPO_ARGUMENT)
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let first = self
+            .upstreams
+            .iter()
+            .next()
+            .ok_or_else(|| McpError::internal_error("No upstream servers configured", None))?;
+        let upstream = self
+            .router
+            .route(repo)
+            .and_then(|name| self.upstreams.named(name))
+            .unwrap_or(first);
+        debug!("Routing {} for {} to {}", tool, repo, upstream.name);
+
+        let result = self
+            .call_upstream(upstream, tool, request.clone(), arguments, ct)
+            .await;
+        let not_indexed = match &result {
+            Ok(result) => result_not_indexed(result),
+            Err(e) => reports_not_indexed(&e.message),
+        };
+        let fallback = self
+            .router
+            .fallback_for(&upstream.name)
+            .and_then(|name| self.upstreams.named(name));
+        match fallback {
+            Some(fallback) if not_indexed => {
+                info!(
+                    "{} has not indexed {}; retrying {} on {}",
+                    upstream.name, repo, tool, fallback.name
+                );
+                self.call_upstream(fallback, tool, request, arguments, ct)
+                    .await
+            }
+            _ => result,
+        }
+    }
+
+    /// Call a tool on one upstream, serving and filling the response cache
+    async fn call_upstream(
+        &self,
+        upstream: &Upstream,
+        tool: &str,
+        request: CallToolRequestParam,
+        arguments: &Value,
+        ct: &CancellationToken,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(cache) = self.cache.as_ref().filter(|c| c.is_cacheable(tool)) {
+            let entry = cache.get(&upstream.endpoint_url, tool, arguments);
+            self.metrics.record_cache_lookup(entry.is_some());
+            if let Some(entry) = entry {
+                debug!("Serving {} from response cache", tool);
+                return Ok(entry.result);
+            }
+        }
+
+        let request = ClientRequest::CallToolRequest(CallToolRequest::new(request));
+        let result = match self.try_forward(upstream, request, ct).await {
+            Ok(result) => expect_result!(result, CallToolResult)?,
+            Err(e) if is_unreachable(&e) => {
+                let refusal = refusal(&e);
+                // Refusals were already logged by the circuit breaker or limit
+                if upstream.client.is_some() && refusal.is_none() {
+                    warn!(
+                        "Upstream {} unreachable ({}); trying response cache",
+                        upstream.name, e
+                    );
+                }
+                return match refusal {
+                    // Fail fast as a tool error the model can read and act on
+                    Some(refusal) => self
+                        .call_tool_from_cache(upstream, tool, arguments)
+                        .or_else(|_| Ok(CallToolResult::error(vec![Content::text(refusal)]))),
+                    None => self.call_tool_from_cache(upstream, tool, arguments),
+                };
+            }
+            Err(e) => return Err(upstream_error(e)),
+        };
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(&upstream.endpoint_url, tool, arguments, &result) {
+                warn!("Failed to cache {} response: {}", tool, e);
+            }
+        }
+        self.index_wiki_pages(tool, arguments, &result);
+        Ok(result)
+    }
+
+    /// Send a request upstream, recording it in the metrics
+    async fn try_forward(
+        &self,
+        upstream: &Upstream,
+        request: ClientRequest,
+        ct: &CancellationToken,
+    ) -> Result<ServerResult, ServiceError> {
+        // Identical idempotent requests in flight share one upstream call
+        let key = self
+            .retry
+            .is_idempotent(&request)
+            .then(|| coalesce::key(&upstream.name, &request))
+            .flatten();
+        match key {
+            Some(key) => {
+                self.coalescer
+                    .run(key, ct, |ct| async move {
+                        self.send_upstream(upstream, request, &ct).await
+                    })
+                    .await
+            }
+            None => self.send_upstream(upstream, request, ct).await,
+        }
+    }
+
+    /// Send a request to an upstream within its limits, retrying transient failures
+    async fn send_upstream(
+        &self,
+        upstream: &Upstream,
+        mut request: ClientRequest,
+        ct: &CancellationToken,
+    ) -> Result<ServerResult, ServiceError> {
+        let Some(client) = &upstream.client else {
+            return Err(ServiceError::TransportClosed);
+        };
+        let count = self.message_count.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("Forwarding request {} to upstream {}", count, upstream.name);
+
+        let labels = metrics::describe(&request);
+        let idempotent = self.retry.is_idempotent(&request);
+        let span = info_span!(
+            "mcp.forward",
+            otel.name = format!("{} {}", labels.0, upstream.name),
+            otel.kind = "client",
+            rpc.method = labels.0,
+            mcp.tool = %labels.1,
+            upstream = %upstream.name,
+            retries = tracing::field::Empty,
+            error = tracing::field::Empty,
+            otel.status_description = tracing::field::Empty,
+        );
+        async move {
+            // Carry this span's context to the upstream in `_meta` (and the HTTP headers)
+            if let Some(traceparent) = telemetry::current_traceparent() {
+                request
+                    .get_meta_mut()
+                    .0
+                    .insert(TRACEPARENT.to_string(), Value::String(traceparent));
+            }
+            let breaker = self.breakers.get(&upstream.name);
+            let mut attempt = 1;
+            let mut last_error = None;
+            loop {
+                let permit = self.limits.acquire(&upstream.name, &labels.1, ct).await;
+                let admitted = match (permit, breaker.map(CircuitBreaker::admit)) {
+                    (Err(error), _) => Err(error),
+                    (Ok(_), Some(Err(open))) => Err(ServiceError::TransportSend(Box::new(open))),
+                    (Ok(permit), _) => Ok(permit),
+                };
+                let _permit = match admitted {
+                    Ok(permit) => permit,
+                    Err(refused) => {
+                        // A refused retry reports the failure that led to it
+                        let error = last_error.unwrap_or(refused);
+                        telemetry::record_error(&tracing::Span::current(), &error.to_string());
+                        return Err(error);
+                    }
+                };
+                let _in_flight = self.metrics.start_request();
+                let started = Instant::now();
+                let response = send_cancellable(client, request.clone(), ct).await;
+                self.metrics
+                    .observe_request(&upstream.name, labels.clone(), started.elapsed());
+                let (error, http) = match response {
+                    Ok(result) => {
+                        breaker.inspect(|breaker| breaker.succeed());
+                        return Ok(result);
+                    }
+                    Err(failure) => failure,
+                };
+                self.metrics.record_upstream_error(&upstream.name, &error);
+                if let Some(breaker) = breaker {
+                    if is_outage(&error, http) {
+                        breaker.fail();
+                    } else if !matches!(error, ServiceError::Cancelled { .. }) {
+                        breaker.succeed();
+                    }
+                }
+                let delay = idempotent
+                    .then(|| self.retry.delay(attempt, &error, http))
+                    .flatten();
+                let Some(delay) = delay else {
+                    telemetry::record_error(&tracing::Span::current(), &error.to_string());
+                    return Err(error);
+                };
+                warn!(
+                    "{} on upstream {} failed ({}); retrying in {}ms",
+                    labels.0,
+                    upstream.name,
+                    error,
+                    delay.as_millis()
+                );
+                self.metrics.record_retry(&upstream.name, labels.clone());
+                tracing::Span::current().record("retries", attempt);
+                last_error = Some(error);
+                tokio::select! {
+                    () = tokio::time::sleep(delay) => attempt += 1,
+                    () = ct.cancelled() => {
+                        let reason = Some("cancelled by client".to_string());
+                        return Err(ServiceError::Cancelled { reason });
+                    }
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn forward(
+        &self,
+        upstream: &Upstream,
+        request: ClientRequest,
+        ct: &CancellationToken,
+    ) -> Result<ServerResult, McpError> {
+        if upstream.client.is_none() {
+            return Err(McpError::internal_error(
+                "The bridge is offline; only cached tool results are available",
+                None,
+            ));
+        }
+        self.try_forward(upstream, request, ct)
+            .await
+            .map_err(upstream_error)
+    }
+
+    /// Forward to each candidate in turn until one of them answers
+    async fn forward_to_any(
+        &self,
+        candidates: &[&Upstream],
+        request: impl Fn() -> ClientRequest,
+        ct: &CancellationToken,
+    ) -> Result<ServerResult, McpError> {
+        let mut first_error = None;
+        for upstream in candidates {
+            match self.forward(upstream, request(), ct).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error
+            .unwrap_or_else(|| McpError::internal_error("No upstream server is configured", None)))
+    }
+
+    /// Fetch every page of an upstream list
+    async fn list_all<T>(
+        &self,
+        upstream: &Upstream,
+        ct: &CancellationToken,
+        request: impl Fn(Option<PaginatedRequestParam>) -> ClientRequest,
+        page: impl Fn(ServerResult) -> Option<(Vec<T>, Option<String>)>,
+    ) -> Result<Vec<T>, ServiceError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.clone().map(|cursor| PaginatedRequestParam {
+                cursor: Some(cursor),
+            });
+            let result = self.try_forward(upstream, request(params), ct).await?;
+            let (page, next_cursor) = page(result).ok_or(ServiceError::UnexpectedResponse)?;
+            items.extend(page);
+            // A server repeating its cursor would otherwise be paged forever
+            if next_cursor.is_none() || next_cursor == cursor {
+                return Ok(items);
+            }
+            cursor = next_cursor;
+        }
+    }
+
+    /// Run `list` against every upstream concurrently, keeping the items of those that answer
+    ///
+    /// Fails only when no upstream answers.
+    async fn gather<'a, T, F>(
+        &'a self,
+        kind: &str,
+        list: impl Fn(&'a Upstream) -> F,
+    ) -> Result<Vec<(&'a Upstream, Vec<T>)>, McpError>
+    where
+        F: Future<Output = Result<Vec<T>, McpError>>,
+    {
+        let results = join_all(self.upstreams.iter().map(|upstream| {
+            let listed = list(upstream);
+            async move { (upstream, listed.await) }
+        }))
+        .await;
+
+        let mut gathered = Vec::new();
+        let mut first_error = None;
+        for (upstream, result) in results {
+            match result {
+                Ok(items) => gathered.push((upstream, items)),
+                Err(e) => {
+                    if self.upstreams.sole().is_none() {
+                        warn!(
+                            "Failed to list {} of {}: {}",
+                            kind, upstream.name, e.message
+                        );
+                    }
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if gathered.is_empty() => Err(e),
+            _ => Ok(gathered),
+        }
+    }
+
+    /// An upstream's tools, falling back to the cached list when it is unreachable
+    async fn upstream_tools(
+        &self,
+        upstream: &Upstream,
+        ct: &CancellationToken,
+    ) -> Result<Vec<Tool>, McpError> {
+        let listed = self
+            .list_all(
+                upstream,
+                ct,
+                |params| ClientRequest::ListToolsRequest(optional_param(params)),
+                |result| match result {
+                    ServerResult::ListToolsResult(result) => {
+                        Some((result.tools, result.next_cursor))
+                    }
+                    _ => None,
+                },
+            )
+            .await;
+
+        match listed {
+            Ok(tools) => {
+                if let Some(cache) = &self.cache {
+                    if let Err(e) = cache.put_tools(&upstream.endpoint_url, &tools) {
+                        warn!("Failed to cache tool list: {}", e);
+                    }
+                }
+                Ok(tools)
+            }
+            Err(e) if is_unreachable(&e) => {
+                if upstream.client.is_some() && refusal(&e).is_none() {
+                    warn!(
+                        "Upstream {} unreachable ({}); serving cached tool list",
+                        upstream.name, e
+                    );
+                }
+                self.list_tools_from_cache(upstream)
+            }
+            Err(e) => Err(upstream_error(e)),
+        }
+    }
+
+    /// Every page of a prompt or resource list from an online upstream
+    async fn upstream_items<T>(
+        &self,
+        upstream: &Upstream,
+        ct: &CancellationToken,
+        request: impl Fn(Option<PaginatedRequestParam>) -> ClientRequest,
+        page: impl Fn(ServerResult) -> Option<(Vec<T>, Option<String>)>,
+    ) -> Result<Vec<T>, McpError> {
+        if upstream.client.is_none() {
+            return Err(McpError::internal_error(
+                format!("Upstream {} is offline", upstream.name),
+                None,
+            ));
+        }
+        self.list_all(upstream, ct, request, page)
+            .await
+            .map_err(upstream_error)
+    }
+
+    /// Resolve a qualified prompt name to its upstream and the name it has there
+    fn resolve_prompt<'a>(&self, name: &'a str) -> Result<(&Upstream, &'a str), McpError> {
+        check_allowed(&self.policy.prompts, "Prompt", "prompts", name)?;
+        self.upstreams
+            .resolve(name)
+            .ok_or_else(|| McpError::invalid_params(format!("Unknown prompt '{name}'"), None))
+    }
+
+    /// Server info merged from every connected upstream
+    fn aggregated_info(&self) -> ServerInfo {
+        let connected: Vec<(&Upstream, &ServerInfo)> = self
+            .upstreams
+            .iter()
+            .filter_map(|upstream| Some((upstream, upstream.client.as_ref()?.peer_info()?)))
+            .collect();
+        if connected.is_empty() {
+            return offline_info();
+        }
+
+        let instructions: Vec<String> = connected
+            .iter()
+            .filter_map(|(upstream, info)| {
+                let instructions = info.instructions.as_deref()?;
+                Some(format!("## {}\n{}", upstream.name, instructions))
+            })
+            .collect();
+        ServerInfo {
+            capabilities: ServerCapabilities {
+                // Cached and bridge-provided tools are always available
+                tools: Some(ToolsCapability::default()),
+                prompts: connected
+                    .iter()
+                    .any(|(_, info)| info.capabilities.prompts.is_some())
+                    .then(PromptsCapability::default),
+                resources: connected
+                    .iter()
+                    .any(|(_, info)| info.capabilities.resources.is_some())
+                    .then(ResourcesCapability::default),
+                ..ServerCapabilities::default()
+            },
+            server_info: Implementation {
+                name: "DeepWiki MCP Proxy".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            instructions: (!instructions.is_empty()).then(|| instructions.join("\n\n")),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// Replace a package name given as `repoName` with the package's repository
+    async fn resolve_package_name(
+        &self,
+        tool: &str,
+        arguments: &mut Option<rmcp::model::JsonObject>,
+    ) -> Result<(), McpError> {
+        if !REPO_TOOLS.contains(&tool) {
+            return Ok(());
+        }
+        let Some(repo_name) = arguments
+            .as_mut()
+            .and_then(|arguments| arguments.get_mut(REPO_ARGUMENT))
+        else {
+            return Ok(());
+        };
+        let Some(package) = repo_name.as_str().filter(|name| !name.contains('/')) else {
+            return Ok(());
+        };
+
+        let repository = self
+            .project
+            .dependencies
+            .resolve(package)
+            .await
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        debug!("Resolved package {} to {}", package, repository);
+        *repo_name = Value::String(repository);
+        Ok(())
+    }
+
+    /// Add the pages of a successful `read_wiki_contents` result to the search index
+    fn index_wiki_pages(&self, tool: &str, arguments: &Value, result: &CallToolResult) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let Some(repo) = arguments.get("repoName").and_then(Value::as_str) else {
+            return;
+        };
+        if tool != WIKI_CONTENTS_TOOL || result.is_error == Some(true) {
+            return;
+        }
+        if let Err(e) = search.add_wiki(repo, &result_text(result)) {
+            warn!("Failed to index wiki pages for {}: {}", repo, e);
+        }
+    }
+
+    /// The upstream the [`SECTION_TOOL`] fetches `read_wiki_contents` from (`None` when that
+    /// is routed by repository) and the name the fetch is listed under, which the filters and
+    /// argument settings apply to
+    fn section_source(&self) -> (Option<&Upstream>, String) {
+        if self.router.routes(WIKI_CONTENTS_TOOL) {
+            return (None, WIKI_CONTENTS_TOOL.to_string());
+        }
+        match self.upstreams.iter().next() {
+            Some(upstream) => (
+                Some(upstream),
+                self.upstreams.qualify(upstream, WIKI_CONTENTS_TOOL),
+            ),
+            None => (None, WIKI_CONTENTS_TOOL.to_string()),
+        }
+    }
+
+    /// Whether the [`SECTION_TOOL`] is offered: it serves `read_wiki_contents` responses, so
+    /// denying either tool hides it
+    fn offers_section_tool(&self) -> bool {
+        let (_, contents) = self.section_source();
+        self.policy.tools.is_allowed(SECTION_TOOL) && self.policy.tools.is_allowed(&contents)
+    }
+
+    /// The name Zed sees the [`SECTION_TOOL`] under, or `None` when it is not offered
+    fn section_tool_name(&self) -> Option<String> {
+        self.offers_section_tool()
+            .then(|| self.naming.exposed_name(SECTION_TOOL))
+    }
+
+    /// Apply `tools.max_response_bytes` of the tool listed as `name` to its result
+    fn limit_response(&self, name: &str, result: CallToolResult) -> CallToolResult {
+        let Some(&max_bytes) = self.max_response_bytes.get(name) else {
+            return result;
+        };
+        // Only the `read_wiki_contents` the section tool reads can be paged through
+        let section_tool = self
+            .section_tool_name()
+            .filter(|_| name == self.section_source().1);
+        truncate_result(result, max_bytes, name, section_tool.as_deref())
+    }
+
+    /// Run the search or dependencies tool with the configured argument settings, or `None`
+    /// when `name` is not one of them
+    fn call_local_tool(
+        &self,
+        name: &str,
+        mut arguments: Option<JsonObject>,
+    ) -> Option<Result<CallToolResult, McpError>> {
+        let call = |text: anyhow::Result<String>| {
+            text.map(|text| CallToolResult::success(vec![Content::text(text)]))
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))
+        };
+        if let Some(search) = self.search.as_ref().filter(|_| name == SEARCH_TOOL) {
+            self.arguments.apply(name, &mut arguments);
+            return Some(call(search.call(&arguments.unwrap_or_default())));
+        }
+        if name == DEPENDENCIES_TOOL && self.project.dependencies.is_loaded() {
+            self.arguments.apply(name, &mut arguments);
+            let dependencies = &self.project.dependencies;
+            return Some(call(dependencies.call(&arguments.unwrap_or_default())));
+        }
+        None
+    }
+
+    /// Answer the [`SECTION_TOOL`] from the repository's `read_wiki_contents` response, fetched
+    /// (and cached) like any other call
+    async fn read_wiki_section(
+        &self,
+        arguments: &JsonObject,
+        ct: &CancellationToken,
+    ) -> Result<CallToolResult, McpError> {
+        // The contents are fetched as if Zed had called `read_wiki_contents` itself
+        let (upstream, name) = self.section_source();
+        check_allowed(&self.policy.tools, "Tool", "tools", &name)?;
+        let request = SectionRequest::parse(arguments)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        let mut contents = CallToolRequestParam {
+            name: WIKI_CONTENTS_TOOL.into(),
+            arguments: Some(JsonObject::from_iter([(
+                REPO_ARGUMENT.to_string(),
+                Value::String(request.repo.clone()),
+            )])),
+        };
+        self.arguments.apply(&name, &mut contents.arguments);
+        let arguments = Value::Object(contents.arguments.clone().unwrap_or_default());
+        let result = match upstream {
+            Some(upstream) => {
+                self.call_upstream(upstream, WIKI_CONTENTS_TOOL, contents, &arguments, ct)
+                    .await?
+            }
+            None => {
+                self.call_routed(WIKI_CONTENTS_TOOL, contents, &arguments, ct)
+                    .await?
+            }
+        };
+        if result.is_error == Some(true) {
+            return Ok(result);
+        }
+        let page_bytes = self
+            .max_response_bytes
+            .get(SECTION_TOOL)
+            .copied()
+            .filter(|bytes| *bytes > 0)
+            .unwrap_or(DEFAULT_PAGE_BYTES);
+        let text = read_section(&result_text(&result), &request, page_bytes)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    /// Serve a tool call from the cache regardless of age, or fail with a clear error
+    fn call_tool_from_cache(
+        &self,
+        upstream: &Upstream,
+        tool: &str,
+        arguments: &Value,
+    ) -> Result<CallToolResult, McpError> {
+        self.cache
+            .as_ref()
+            .and_then(|cache| cache.get_stale(&upstream.endpoint_url, tool, arguments))
+            .map(|entry| {
+                debug!("Serving stale {} response from cache", tool);
+                entry.into_stale_result()
+            })
+            .ok_or_else(|| {
+                McpError::internal_error(
+                    format!(
+                        "The DeepWiki server is unreachable and no cached response exists for \
+                         {tool} with these arguments. Run the same call once while online to \
+                         make it available offline."
+                    ),
+                    None,
+                )
+            })
+    }
+
+    fn list_tools_from_cache(&self, upstream: &Upstream) -> Result<Vec<Tool>, McpError> {
+        let entry = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get_tools(&upstream.endpoint_url))
+            .ok_or_else(|| {
+                McpError::internal_error(
+                    "The DeepWiki server is unreachable and no tool list has been cached yet",
+                    None,
+                )
+            })?;
+        debug!(
+            "Serving tool list cached at {}",
+            format_timestamp(entry.fetched_at)
+        );
+        Ok(entry.tools)
+    }
+}
+
+/// Upstream tool whose responses carry full wiki pages
+const WIKI_CONTENTS_TOOL: &str = "read_wiki_contents";
+
+/// Send a request upstream, cancelling it there if Zed cancels first, and return any failure
+/// with the HTTP response that caused it
+async fn send_cancellable(
+    client: &RemoteClient,
+    request: ClientRequest,
+    ct: &CancellationToken,
+) -> Result<ServerResult, (ServiceError, Option<HttpFailure>)> {
+    let handle = client
+        .send_cancellable_request(request, PeerRequestOptions::no_options())
+        .await
+        .map_err(|e| (e, None))?;
+    let peer = handle.peer.clone();
+    let request_id = handle.id.clone();
+
+    let response = tokio::select! {
+        response = handle.await_response() => response,
+        () = ct.cancelled() => {
+            let reason = Some("cancelled by client".to_string());
+            let cancel = CancelledNotificationParam {
+                request_id: request_id.clone(),
+                reason: reason.clone(),
+            };
+            if let Err(e) = peer.notify_cancelled(cancel).await {
+                warn!("Failed to propagate cancellation upstream: {}", e);
+            }
+            Err(ServiceError::Cancelled { reason })
+        }
+    };
+    response.map_err(|e| (e, client.service().http_failures().take(&request_id)))
+}
+
+/// Map an upstream failure onto a JSON-RPC error for Zed
+///
+/// Protocol errors from the remote server are passed through unchanged.
+fn upstream_error(error: ServiceError) -> McpError {
+    match error {
+        ServiceError::McpError(error) => error,
+        ServiceError::Cancelled { .. } => {
+            McpError::internal_error("Request cancelled by client", None)
+        }
+        other => match refusal(&other) {
+            Some(refusal) => McpError::internal_error(refusal, None),
+            None => McpError::internal_error(format!("Upstream request failed: {other}"), None),
+        },
+    }
+}
+
+/// Whether the failure means the server could not be reached, as opposed to rejecting the request
+fn is_unreachable(error: &ServiceError) -> bool {
+    matches!(
+        error,
+        ServiceError::TransportSend(_)
+            | ServiceError::TransportClosed
+            | ServiceError::Timeout { .. }
+    )
+}
+
+/// Why the bridge refused a request without sending it, if it did
+fn refusal(error: &ServiceError) -> Option<String> {
+    breaker::unavailable(error)
+        .map(ToString::to_string)
+        .or_else(|| limits::rejected(error).map(ToString::to_string))
+}
+
+/// Whether a failure means the server is down, as opposed to answering with an error
+fn is_outage(error: &ServiceError, http: Option<HttpFailure>) -> bool {
+    is_unreachable(error) && http.is_none_or(|http| http.is_transient())
+}
+
+/// Server info advertised when no upstream session exists
+fn offline_info() -> ServerInfo {
+    ServerInfo {
+        capabilities: ServerCapabilities::builder().enable_tools().build(),
+        server_info: Implementation {
+            name: "DeepWiki MCP Proxy (offline)".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+        instructions: Some(
+            "The DeepWiki server is unreachable. Only previously cached wiki content is \
+             available, and results may be out of date."
+                .to_string(),
+        ),
+        ..ServerInfo::default()
+    }
+}
+
+impl ServerHandler for ProxyHandler {
+    fn get_info(&self) -> ServerInfo {
+        let mut info = match self.upstreams.sole() {
+            Some(upstream) => match &upstream.client {
+                Some(client) => client.peer_info().cloned().unwrap_or_default(),
+                None => offline_info(),
+            },
+            None => self.aggregated_info(),
+        };
+        // The bridge's own messages are sent whether or not the upstreams log
+        info.capabilities
+            .logging
+            .get_or_insert_with(JsonObject::new);
+        info
+    }
+
+    /// Filter the messages sent to Zed, and ask upstreams that log for the same level
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.client_log.set_level(request.level);
+        let logging = self.upstreams.iter().filter_map(|upstream| {
+            let client = upstream.client.as_ref()?;
+            client.peer_info()?.capabilities.logging.as_ref()?;
+            let request = request.clone();
+            Some(async move {
+                if let Err(e) = client.peer().set_level(request).await {
+                    debug!("{} rejected logging/setLevel: {}", upstream.name, e);
+                }
+            })
+        });
+        join_all(logging).await;
+        Ok(())
+    }
+
+    /// All tools in one page: every upstream's, namespaced when aggregating, then the bridge's own
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let gathered = self
+            .gather("tools", |upstream| {
+                self.upstream_tools(upstream, &context.ct)
+            })
+            .await?;
+
+        let mut tools = Vec::new();
+        let mut routed = HashSet::new();
+        for (upstream, upstream_tools) in gathered {
+            for mut tool in upstream_tools {
+                let name = if self.router.routes(&tool.name) {
+                    // Listed once; the first upstream's schema stands for all of them
+                    if !routed.insert(tool.name.to_string()) {
+                        continue;
+                    }
+                    tool.name.to_string()
+                } else {
+                    self.upstreams.qualify(upstream, &tool.name)
+                };
+                self.arguments.rewrite_schema(&name, &mut tool);
+                self.project.repository.rewrite_schema(&mut tool);
+                tool.name = name.into();
+                tools.push(tool);
+            }
+        }
+        let local_tools = [
+            self.search.as_ref().map(|_| search_tool()),
+            self.offers_section_tool().then(section_tool),
+            self.project
+                .dependencies
+                .is_loaded()
+                .then(dependencies_tool),
+        ];
+        for mut tool in local_tools.into_iter().flatten() {
+            let name = tool.name.to_string();
+            self.arguments.rewrite_schema(&name, &mut tool);
+            self.project.repository.rewrite_schema(&mut tool);
+            tools.push(tool);
+        }
+
+        tools.retain(|tool| self.policy.tools.is_allowed(&tool.name));
+        for tool in &mut tools {
+            self.naming.expose(tool);
+        }
+        Ok(ListToolsResult {
+            next_cursor: None,
+            tools,
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let exposed = request.name.to_string();
+        let unknown = || McpError::invalid_params(format!("Unknown tool '{exposed}'"), None);
+        let name = self.naming.original_name(&exposed).ok_or_else(unknown)?;
+        check_allowed(&self.policy.tools, "Tool", "tools", &name)?;
+        if let Some(result) = self.call_local_tool(&name, request.arguments.clone()) {
+            return result;
+        }
+
+        if name == SECTION_TOOL {
+            self.arguments.apply(&name, &mut request.arguments);
+            self.project
+                .repository
+                .fill_arguments(&name, &mut request.arguments);
+            self.resolve_package_name(&name, &mut request.arguments)
+                .await?;
+            let arguments = request.arguments.unwrap_or_default();
+            return self.read_wiki_section(&arguments, &context.ct).await;
+        }
+
+        let routed = self.router.routes(&name);
+        let (upstream, tool) = if routed {
+            (None, name.clone())
+        } else {
+            let (upstream, tool) = self.upstreams.resolve(&name).ok_or_else(unknown)?;
+            (Some(upstream), tool.to_string())
+        };
+        self.arguments.apply(&name, &mut request.arguments);
+        self.project
+            .repository
+            .fill_arguments(&tool, &mut request.arguments);
+        self.resolve_package_name(&tool, &mut request.arguments)
+            .await?;
+        request.name = tool.clone().into();
+        let arguments = Value::Object(request.arguments.clone().unwrap_or_default());
+
+        let result = match upstream {
+            Some(upstream) => {
+                self.call_upstream(upstream, &tool, request, &arguments, &context.ct)
+                    .await?
+            }
+            None => {
+                self.call_routed(&tool, request, &arguments, &context.ct)
+                    .await?
+            }
+        };
+        // Truncated only now, so the cache and search index get the full response
+        Ok(self.limit_response(&name, result))
+    }
+
+    /// Inspect the client's roots for whatever the working directory did not reveal
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.client_log.connect(context.peer.clone());
+
+        let supports_roots = context
+            .peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.roots.is_some());
+        let needs_repository = self.project.repository.get().is_none();
+        let needs_dependencies = !self.project.dependencies.is_loaded();
+        if !supports_roots || !(needs_repository || needs_dependencies) {
+            return;
+        }
+
+        let peer = context.peer;
+        let repository = self.project.repository.clone();
+        let dependencies = self.project.dependencies.clone();
+        tokio::spawn(async move {
+            let roots = match peer.list_roots().await {
+                Ok(result) => result.roots,
+                Err(e) => {
+                    debug!("Could not list client roots: {}", e);
+                    return;
+                }
+            };
+            let paths: Vec<_> = roots
+                .iter()
+                .filter_map(|root| url::Url::parse(&root.uri).ok()?.to_file_path().ok())
+                .collect();
+
+            if needs_repository {
+                if let Some(repo) = paths.iter().find_map(|path| detect_repository(path)) {
+                    info!("Detected project repository {} from client roots", repo);
+                    repository.set(repo);
+                }
+            }
+            if needs_dependencies {
+                paths.iter().any(|path| dependencies.load_from(path));
+            }
+        });
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        let gathered = self
+            .gather("prompts", |upstream| {
+                self.upstream_items(
+                    upstream,
+                    &context.ct,
+                    |params| ClientRequest::ListPromptsRequest(optional_param(params)),
+                    |result| match result {
+                        ServerResult::ListPromptsResult(result) => {
+                            Some((result.prompts, result.next_cursor))
+                        }
+                        _ => None,
+                    },
+                )
+            })
+            .await?;
+
+        let mut prompts = Vec::new();
+        for (upstream, upstream_prompts) in gathered {
+            for mut prompt in upstream_prompts {
+                prompt.name = self.upstreams.qualify(upstream, &prompt.name);
+                prompts.push(prompt);
+            }
+        }
+        prompts.retain(|prompt| self.policy.prompts.is_allowed(&prompt.name));
+        Ok(ListPromptsResult {
+            next_cursor: None,
+            prompts,
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        mut request: GetPromptRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let name = request.name.clone();
+        let (upstream, name) = self.resolve_prompt(&name)?;
+        request.name = name.to_string();
+        let request = ClientRequest::GetPromptRequest(GetPromptRequest::new(request));
+        expect_result!(
+            self.forward(upstream, request, &context.ct).await?,
+            GetPromptResult
+        )
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let gathered = self
+            .gather("resources", |upstream| {
+                self.upstream_items(
+                    upstream,
+                    &context.ct,
+                    |params| ClientRequest::ListResourcesRequest(optional_param(params)),
+                    |result| match result {
+                        ServerResult::ListResourcesResult(result) => {
+                            Some((result.resources, result.next_cursor))
+                        }
+                        _ => None,
+                    },
+                )
+            })
+            .await?;
+
+        let mut resources = Vec::new();
+        for (upstream, upstream_resources) in gathered {
+            for resource in upstream_resources {
+                self.upstreams.record_resource(upstream, &resource.uri);
+                resources.push(resource);
+            }
+        }
+        resources.retain(|resource| self.policy.resources.is_allowed(&resource.uri));
+        Ok(ListResourcesResult {
+            next_cursor: None,
+            resources,
+        })
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let gathered = self
+            .gather("resource templates", |upstream| {
+                self.upstream_items(
+                    upstream,
+                    &context.ct,
+                    |params| ClientRequest::ListResourceTemplatesRequest(optional_param(params)),
+                    |result| match result {
+                        ServerResult::ListResourceTemplatesResult(result) => {
+                            Some((result.resource_templates, result.next_cursor))
+                        }
+                        _ => None,
+                    },
+                )
+            })
+            .await?;
+
+        let mut resource_templates: Vec<_> = gathered
+            .into_iter()
+            .flat_map(|(_, templates)| templates)
+            .collect();
+        resource_templates
+            .retain(|template| self.policy.resources.is_allowed(&template.uri_template));
+        Ok(ListResourceTemplatesResult {
+            next_cursor: None,
+            resource_templates,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        check_allowed(
+            &self.policy.resources,
+            "Resource",
+            "resources",
+            &request.uri,
+        )?;
+        let candidates = self.upstreams.resource_candidates(&request.uri);
+        let result = self
+            .forward_to_any(
+                &candidates,
+                || ClientRequest::ReadResourceRequest(ReadResourceRequest::new(request.clone())),
+                &context.ct,
+            )
+            .await?;
+        expect_result!(result, ReadResourceResult)
+    }
+
+    async fn complete(
+        &self,
+        mut request: CompleteRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        let candidates = match &mut request.r#ref {
+            Reference::Prompt(prompt) => {
+                let name = prompt.name.clone();
+                let (upstream, name) = self.resolve_prompt(&name)?;
+                prompt.name = name.to_string();
+                vec![upstream]
+            }
+            Reference::Resource(resource) => {
+                check_allowed(
+                    &self.policy.resources,
+                    "Resource",
+                    "resources",
+                    &resource.uri,
+                )?;
+                self.upstreams.resource_candidates(&resource.uri)
+            }
+        };
+        let result = self
+            .forward_to_any(
+                &candidates,
+                || ClientRequest::CompleteRequest(CompleteRequest::new(request.clone())),
+                &context.ct,
+            )
+            .await?;
+        expect_result!(result, CompleteResult)
+    }
+}
+
+/// Reject a request for an item the user's filter settings exclude
+fn check_allowed(
+    filter: &NameFilter,
+    kind: &str,
+    setting: &str,
+    name: &str,
+) -> Result<(), McpError> {
+    if filter.is_allowed(name) {
+        return Ok(());
+    }
+    Err(McpError::invalid_params(
+        format!(
+            "{kind} '{name}' is disabled by the bridge's {setting}.allow/{setting}.deny settings"
+        ),
+        None,
+    ))
+}
+
+fn optional_param<M: Default>(
+    params: Option<PaginatedRequestParam>,
+) -> RequestOptionalParam<M, PaginatedRequestParam> {
+    RequestOptionalParam {
+        method: M::default(),
+        params,
+        extensions: rmcp::model::Extensions::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::ErrorCode;
+
+    #[test]
+    fn test_upstream_mcp_errors_pass_through() {
+        let original = McpError::invalid_params("repoName is required", None);
+        let mapped = upstream_error(ServiceError::McpError(original.clone()));
+        assert_eq!(mapped, original);
+    }
+
+    #[test]
+    fn test_unreachable_classification() {
+        assert!(is_unreachable(&ServiceError::TransportClosed));
+        assert!(is_unreachable(&ServiceError::Timeout {
+            timeout: std::time::Duration::from_secs(30)
+        }));
+        assert!(!is_unreachable(&ServiceError::McpError(
+            McpError::invalid_params("bad", None)
+        )));
+        assert!(!is_unreachable(&ServiceError::Cancelled { reason: None }));
+    }
+
+    #[test]
+    fn test_offline_handler_serves_cache_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::open(&crate::settings::CacheSettings {
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        })
+        .unwrap();
+        let args = serde_json::json!({"repoName": "a/b"});
+        cache
+            .put(
+                "https://mcp.deepwiki.com",
+                "read_wiki_structure",
+                &args,
+                &CallToolResult::success(vec![rmcp::model::Content::text("topics")]),
+            )
+            .unwrap();
+
+        let handler = ProxyHandler::new(
+            Upstreams::single("https://mcp.deepwiki.com", None),
+            Some(cache),
+            None,
+            ProjectContext::default(),
+            &BridgeSettings::default(),
+            Arc::default(),
+            Arc::default(),
+        );
+        let upstream = handler.upstreams.sole().unwrap();
+        assert!(handler.get_info().capabilities.tools.is_some());
+        assert!(handler.list_tools_from_cache(upstream).is_err());
+
+        let result = handler
+            .call_tool_from_cache(upstream, "read_wiki_structure", &args)
+            .unwrap();
+        assert!(result.content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .starts_with("[Offline]"));
+
+        let error = handler
+            .call_tool_from_cache(upstream, "read_wiki_contents", &args)
+            .unwrap_err();
+        assert!(error.message.contains("no cached response"));
+    }
+
+    #[test]
+    fn test_wiki_contents_responses_are_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let search = WikiSearch::open(&crate::settings::SearchSettings {
+            enabled: true,
+            dir: Some(dir.path().to_path_buf()),
+        })
+        .unwrap();
+        let handler = ProxyHandler::new(
+            Upstreams::single("https://mcp.deepwiki.com", None),
+            None,
+            Some(search),
+            ProjectContext::default(),
+            &BridgeSettings::default(),
+            Arc::default(),
+            Arc::default(),
+        );
+        let args = serde_json::json!({"repoName": "tokio-rs/tokio"});
+        let result = CallToolResult::success(vec![Content::text(
+            "# Page: Scheduler\nWork stealing across workers.\n",
+        )]);
+
+        handler.index_wiki_pages("read_wiki_structure", &args, &result);
+        let search = handler.search.as_ref().unwrap();
+        assert!(search.search("stealing", None, 5).is_empty());
+
+        handler.index_wiki_pages(WIKI_CONTENTS_TOOL, &args, &result);
+        assert_eq!(search.search("stealing", None, 5)[0].title, "Scheduler");
+    }
+
+    #[test]
+    fn test_transport_errors_become_internal_errors() {
+        let mapped = upstream_error(ServiceError::TransportClosed);
+        assert_eq!(mapped.code, ErrorCode::INTERNAL_ERROR);
+        assert!(mapped.message.contains("Transport closed"));
+    }
+
+    #[tokio::test]
+    async fn test_section_tool_follows_the_contents_policy() {
+        let handler = |settings: &str| {
+            ProxyHandler::new(
+                Upstreams::single("https://mcp.deepwiki.com", None),
+                None,
+                None,
+                ProjectContext::default(),
+                &BridgeSettings::from_json(settings).unwrap(),
+                Arc::default(),
+                Arc::default(),
+            )
+        };
+        let renamed = handler(r#"{"tools": {"prefix": "deepwiki_"}}"#);
+        assert_eq!(
+            renamed.section_tool_name().as_deref(),
+            Some("deepwiki_read_wiki_section")
+        );
+
+        // Denying the contents tool also withholds them through the section tool
+        let denied = handler(r#"{"tools": {"deny": ["read_wiki_contents"]}}"#);
+        assert_eq!(denied.section_tool_name(), None);
+        let arguments = serde_json::json!({"repoName": "tokio-rs/tokio", "section": "Scheduler"});
+        let error = denied
+            .read_wiki_section(arguments.as_object().unwrap(), &CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS);
+        assert!(error.message.contains("'read_wiki_contents' is disabled"));
+    }
+
+    #[test]
+    fn test_local_tools_apply_argument_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("go.mod"),
+            "module app\n\nrequire (\n\tgithub.com/spf13/cobra v1.8.0\n\tgithub.com/spf13/viper v1.18.0\n)\n",
+        )
+        .unwrap();
+        let settings = r#"{"tools": {
+            "defaults": {"list_project_dependencies": {"ecosystem": "cargo"}},
+            "pinned": {"list_project_dependencies": {"query": "cobra"}}
+        }}"#;
+        let handler = ProxyHandler::new(
+            Upstreams::single("https://mcp.deepwiki.com", None),
+            None,
+            None,
+            ProjectContext {
+                dependencies: crate::dependencies::ProjectDependencies::load(dir.path()),
+                ..Default::default()
+            },
+            &BridgeSettings::from_json(settings).unwrap(),
+            Arc::default(),
+            Arc::default(),
+        );
+        let call = |arguments: serde_json::Value| {
+            let result = handler
+                .call_local_tool(DEPENDENCIES_TOOL, arguments.as_object().cloned())
+                .unwrap()
+                .unwrap();
+            result.content[0].as_text().unwrap().text.clone()
+        };
+
+        // The default ecosystem applies when the call leaves it out
+        assert!(call(serde_json::json!({})).starts_with("0 dependencies"));
+        // The pinned query wins over the caller's
+        let text = call(serde_json::json!({"ecosystem": "go", "query": "viper"}));
+        assert!(text.starts_with("1 dependencies"));
+        assert!(text.contains("github.com/spf13/cobra"));
+
+        assert!(handler.call_local_tool("ask_question", None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_section_tool_follows_the_namespaced_contents_policy() {
+        let upstreams = || {
+            let upstream = |name: &str, url: &str| Upstream {
+                name: name.to_string(),
+                endpoint_url: url.to_string(),
+                client: None,
+            };
+            Upstreams::new(vec![
+                upstream("deepwiki", "https://mcp.deepwiki.com"),
+                upstream("devin", "https://mcp.devin.ai"),
+            ])
+        };
+        let handler = |settings: &str| {
+            ProxyHandler::new(
+                upstreams(),
+                None,
+                None,
+                ProjectContext::default(),
+                &BridgeSettings::from_json(settings).unwrap(),
+                Arc::default(),
+                Arc::default(),
+            )
+        };
+        assert_eq!(
+            handler("{}").section_source().1,
+            "deepwiki__read_wiki_contents"
+        );
+        // Only the upstream the contents are read from matters
+        let other = handler(r#"{"tools": {"deny": ["devin__read_wiki_contents"]}}"#);
+        assert!(other.section_tool_name().is_some());
+
+        let denied = handler(r#"{"tools": {"deny": ["deepwiki__read_wiki_contents"]}}"#);
+        assert_eq!(denied.section_tool_name(), None);
+        let arguments = serde_json::json!({"repoName": "tokio-rs/tokio", "section": "Scheduler"});
+        let error = denied
+            .read_wiki_section(arguments.as_object().unwrap(), &CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS);
+        assert!(error
+            .message
+            .contains("'deepwiki__read_wiki_contents' is disabled"));
+    }
+
+    #[test]
+    fn test_tool_settings_use_namespaced_names() {
+        let upstream = |name: &str, url: &str| Upstream {
+            name: name.to_string(),
+            endpoint_url: url.to_string(),
+            client: None,
+        };
+        let settings = r#"{"tools": {
+            "pinned": {"devin__ask_question": {"repoName": "our-org/platform"}},
+            "max_response_bytes": {"devin__ask_question": 40, "devin__read_wiki_contents": 0}
+        }}"#;
+        let handler = ProxyHandler::new(
+            Upstreams::new(vec![
+                upstream("deepwiki", "https://mcp.deepwiki.com"),
+                upstream("devin", "https://mcp.devin.ai"),
+            ]),
+            None,
+            None,
+            ProjectContext::default(),
+            &BridgeSettings::from_json(settings).unwrap(),
+            Arc::default(),
+            Arc::default(),
+        );
+
+        let pinned = |name: &str| {
+            let mut arguments = None;
+            handler.arguments.apply(name, &mut arguments);
+            arguments.and_then(|arguments| arguments.get(REPO_ARGUMENT).cloned())
+        };
+        assert_eq!(
+            pinned("devin__ask_question"),
+            Some("our-org/platform".into())
+        );
+        assert_eq!(pinned("deepwiki__ask_question"), None);
+        assert_eq!(pinned("ask_question"), None);
+
+        let answer = || CallToolResult::success(vec![Content::text("# Answer\n".repeat(10))]);
+        let text = |result: CallToolResult| result_text(&result);
+        assert!(
+            text(handler.limit_response("devin__ask_question", answer())).ends_with(
+                "raise tools.max_response_bytes.devin__ask_question in the bridge settings.]"
+            )
+        );
+        assert_eq!(
+            text(handler.limit_response("deepwiki__ask_question", answer())),
+            "# Answer\n".repeat(10)
+        );
+
+        // The built-in `read_wiki_contents` limit covers each upstream without its own
+        let wiki = || CallToolResult::success(vec![Content::text("# Page\nline\n".repeat(10_000))]);
+        assert!(
+            text(handler.limit_response("deepwiki__read_wiki_contents", wiki()))
+                .contains("call read_wiki_section with the same repoName")
+        );
+        assert_eq!(
+            text(handler.limit_response("devin__read_wiki_contents", wiki())).len(),
+            "# Page\nline\n".len() * 10_000
+        );
+    }
+
+    #[test]
+    fn test_filtered_items_are_rejected() {
+        let settings = BridgeSettings::from_json(r#"{"tools": {"deny": ["ask_*"]}}"#).unwrap();
+        let policy = AccessPolicy::from_settings(&settings);
+
+        assert!(check_allowed(&policy.tools, "Tool", "tools", "read_wiki_structure").is_ok());
+        let error = check_allowed(&policy.tools, "Tool", "tools", "ask_question").unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS);
+        assert!(error.message.contains("'ask_question' is disabled"));
+    }
+}
@@ -0,0 +1,355 @@
+//! Record raw JSON-RPC traffic to a JSONL file for debugging and bug reports
+//!
+//! `--record <file>` (or `record.file`) appends one line per message crossing the bridge:
+//! between Zed and the bridge, and between the bridge and each upstream. Every line carries a
+//! timestamp, the direction, the upstream name and, for responses, the latency since the
+//! matching request. Secrets are redacted before anything reaches the disk: credential-like
+//! fields (`authorization`, `token`, ...), the fields listed in `record.redact`, bearer tokens,
+//! and any occurrence of a sensitive header value configured for an upstream.
+
+use crate::settings::BridgeSettings;
+use crate::traffic::{Direction, TrafficObserver};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::warn;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Field names whose values are always redacted, compared case-insensitively
+const SECRET_FIELDS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "password",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "api_key",
+    "apikey",
+    "x-api-key",
+];
+
+/// Header names containing any of these are treated as credentials
+const SENSITIVE_HEADER_PARTS: &[&str] = &["auth", "token", "key", "secret", "cookie", "password"];
+
+/// Which hop a recorded message travelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Hop {
+    #[serde(rename = "zed->bridge")]
+    ZedToBridge,
+    #[serde(rename = "bridge->zed")]
+    BridgeToZed,
+    #[serde(rename = "bridge->upstream")]
+    BridgeToUpstream,
+    #[serde(rename = "upstream->bridge")]
+    UpstreamToBridge,
+}
+
+/// One line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// RFC 3339 with milliseconds
+    pub timestamp: String,
+    pub direction: Hop,
+    /// Upstream name for the upstream hops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// For responses, milliseconds since the request with the same id on the same hop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    pub message: Value,
+}
+
+/// Removes secrets from recorded messages
+#[derive(Debug, Clone, Default)]
+pub struct Redaction {
+    /// Lowercased field names
+    fields: HashSet<String>,
+    /// Secret values scrubbed wherever they appear inside strings
+    values: Vec<String>,
+}
+
+impl Redaction {
+    pub fn from_settings(settings: &BridgeSettings) -> Self {
+        let fields = SECRET_FIELDS
+            .iter()
+            .map(|field| (*field).to_string())
+            .chain(
+                settings
+                    .record
+                    .redact
+                    .iter()
+                    .map(|f| f.to_ascii_lowercase()),
+            )
+            .collect();
+        let values = settings
+            .upstreams
+            .iter()
+            .flat_map(|upstream| &upstream.headers)
+            .filter(|(name, _)| is_sensitive_header(name))
+            .flat_map(|(_, value)| {
+                // Also catch the bare token when the header carries a scheme
+                let token = value.split_once(' ').map(|(_, token)| token.trim());
+                std::iter::once(value.trim()).chain(token)
+            })
+            .filter(|value| value.len() >= 4)
+            .map(str::to_string)
+            .collect();
+        Self { fields, values }
+    }
+
+    pub fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    if self.fields.contains(&key.to_ascii_lowercase()) && !value.is_null() {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            Value::String(text) => {
+                if let Some(redacted) = self.redact_text(text) {
+                    *text = redacted;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The text with secrets removed, or `None` if it contained none
+    fn redact_text(&self, text: &str) -> Option<String> {
+        let mut redacted = None::<String>;
+        for secret in &self.values {
+            let current = redacted.as_deref().unwrap_or(text);
+            if current.contains(secret.as_str()) {
+                redacted = Some(current.replace(secret.as_str(), REDACTED));
+            }
+        }
+        let current = redacted.as_deref().unwrap_or(text);
+        if let Some(start) = current.find("Bearer ") {
+            let token_start = start + "Bearer ".len();
+            let token_len = current[token_start..]
+                .find(|c: char| c.is_whitespace() || c == '"' || c == ',')
+                .unwrap_or(current.len() - token_start);
+            if token_len > 0 && &current[token_start..token_start + token_len] != REDACTED {
+                redacted = Some(format!(
+                    "{}{REDACTED}{}",
+                    &current[..token_start],
+                    &current[token_start + token_len..]
+                ));
+            }
+        }
+        redacted
+    }
+}
+
+fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_HEADER_PARTS
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// Appends redacted traffic to a JSONL file
+pub struct TrafficRecorder {
+    writer: Mutex<LineWriter<File>>,
+    redaction: Redaction,
+    /// Start of each request awaiting its response, by upstream (`None` for Zed) and id
+    pending: Mutex<HashMap<(Option<String>, String), Instant>>,
+    /// Set after the first write failure so the log is not flooded
+    failed: AtomicBool,
+}
+
+impl TrafficRecorder {
+    pub fn create(path: &Path, redaction: Redaction) -> Result<Arc<Self>> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Cannot open recording {}: {}", path.display(), e))?;
+        Ok(Arc::new(Self {
+            writer: Mutex::new(LineWriter::new(file)),
+            redaction,
+            pending: Mutex::new(HashMap::new()),
+            failed: AtomicBool::new(false),
+        }))
+    }
+
+    /// Observer for the connection to Zed (`None`) or to the named upstream
+    pub fn observer(self: &Arc<Self>, upstream: Option<&str>) -> Arc<dyn TrafficObserver> {
+        Arc::new(HopObserver {
+            recorder: self.clone(),
+            upstream: upstream.map(str::to_string),
+        })
+    }
+
+    fn record(&self, direction: Hop, upstream: Option<&String>, message: &Value) {
+        let latency_ms = self.track_latency(upstream, message);
+        let mut message = message.clone();
+        self.redaction.redact(&mut message);
+        let record = RecordedMessage {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            direction,
+            upstream: upstream.cloned(),
+            latency_ms,
+            message,
+        };
+
+        let written = serde_json::to_string(&record)
+            .map_err(std::io::Error::from)
+            .and_then(|line| match self.writer.lock() {
+                Ok(mut writer) => writeln!(writer, "{line}"),
+                Err(_) => Err(std::io::Error::other("recording writer poisoned")),
+            });
+        if let Err(e) = written {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                warn!("Failed to write traffic recording: {}", e);
+            }
+        }
+    }
+
+    /// Remember when requests start and return the elapsed time for responses
+    fn track_latency(&self, upstream: Option<&String>, message: &Value) -> Option<u64> {
+        let id = message.get("id")?.to_string();
+        let key = (upstream.cloned(), id);
+        let mut pending = self.pending.lock().ok()?;
+        if message.get("method").is_some() {
+            pending.insert(key, Instant::now());
+            None
+        } else {
+            let started = pending.remove(&key)?;
+            u64::try_from(started.elapsed().as_millis()).ok()
+        }
+    }
+}
+
+struct HopObserver {
+    recorder: Arc<TrafficRecorder>,
+    upstream: Option<String>,
+}
+
+impl TrafficObserver for HopObserver {
+    fn observe(&self, direction: Direction, message: &Value) {
+        let hop = match (&self.upstream, direction) {
+            (None, Direction::Inbound) => Hop::ZedToBridge,
+            (None, Direction::Outbound) => Hop::BridgeToZed,
+            (Some(_), Direction::Outbound) => Hop::BridgeToUpstream,
+            (Some(_), Direction::Inbound) => Hop::UpstreamToBridge,
+        };
+        self.recorder.record(hop, self.upstream.as_ref(), message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redaction() -> Redaction {
+        let settings = BridgeSettings::from_json(
+            r#"{"upstreams": [{"name": "docs", "url": "https://docs.example.com/mcp",
+                "headers": {"Authorization": "Bearer s3cr3t-token", "X-Client": "zed"}}],
+                "record": {"redact": ["repoName"]}}"#,
+        )
+        .unwrap();
+        Redaction::from_settings(&settings)
+    }
+
+    #[test]
+    fn test_redacts_fields_header_values_and_bearer_tokens() {
+        let redaction = redaction();
+        let mut message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {
+                "name": "ask_question",
+                "arguments": {"repoName": "me/secret", "question": "Why?", "token": "abc"},
+                "_meta": {"Authorization": "Basic xyz"}
+            },
+            "error": {"message": "rejected s3cr3t-token; sent Bearer other-token, retrying"},
+            "note": "client zed"
+        });
+        redaction.redact(&mut message);
+
+        assert_eq!(message["params"]["arguments"]["repoName"], REDACTED);
+        assert_eq!(message["params"]["arguments"]["token"], REDACTED);
+        assert_eq!(message["params"]["arguments"]["question"], "Why?");
+        assert_eq!(message["params"]["_meta"]["Authorization"], REDACTED);
+        assert_eq!(
+            message["error"]["message"],
+            "rejected [REDACTED]; sent Bearer [REDACTED], retrying"
+        );
+        assert_eq!(message["note"], "client zed");
+        assert_eq!(message["method"], "tools/call");
+    }
+
+    #[test]
+    fn test_records_directions_and_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traffic.jsonl");
+        let recorder = TrafficRecorder::create(&path, redaction()).unwrap();
+        let zed = recorder.observer(None);
+        let upstream = recorder.observer(Some("deepwiki"));
+
+        zed.observe(
+            Direction::Inbound,
+            &json!({"id": 1, "method": "tools/list"}),
+        );
+        upstream.observe(
+            Direction::Outbound,
+            &json!({"id": 1, "method": "tools/list"}),
+        );
+        upstream.observe(
+            Direction::Inbound,
+            &json!({"id": 1, "result": {"tools": []}}),
+        );
+        zed.observe(
+            Direction::Outbound,
+            &json!({"id": 1, "result": {"tools": []}}),
+        );
+        zed.observe(Direction::Outbound, &json!({"id": 9, "result": {}}));
+
+        let records: Vec<RecordedMessage> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let hops: Vec<Hop> = records.iter().map(|r| r.direction).collect();
+        assert_eq!(
+            hops,
+            [
+                Hop::ZedToBridge,
+                Hop::BridgeToUpstream,
+                Hop::UpstreamToBridge,
+                Hop::BridgeToZed,
+                Hop::BridgeToZed
+            ]
+        );
+        assert_eq!(records[0].upstream, None);
+        assert_eq!(records[1].upstream.as_deref(), Some("deepwiki"));
+        assert!(records[0].latency_ms.is_none());
+        assert!(records[2].latency_ms.is_some());
+        assert!(records[3].latency_ms.is_some());
+        assert!(records[4].latency_ms.is_none());
+    }
+}
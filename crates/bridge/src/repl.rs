@@ -0,0 +1,381 @@
+//! Interactive REPL for exploring an MCP server
+//!
+//! Keeps a single session open and lets the user list tools, resources and prompts,
+//! call tools with JSON arguments, and optionally watch the raw JSON-RPC traffic.
+
+use crate::commands::{format_call_result_text, shutdown};
+use crate::traffic::{Direction, TrafficObserver};
+use crate::{connect_remote, RemoteClient};
+use anyhow::{anyhow, Result};
+use rmcp::model::CallToolRequestParam;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const PROMPT: &str = "deepwiki> ";
+const COMMANDS: &[&str] = &[
+    "help",
+    "tools",
+    "resources",
+    "prompts",
+    "call",
+    "raw",
+    "exit",
+    "quit",
+];
+
+/// A single line entered at the REPL prompt
+#[derive(Debug, PartialEq)]
+enum ReplCommand {
+    Help,
+    Tools,
+    Resources,
+    Prompts,
+    Call {
+        tool: String,
+        arguments: Map<String, Value>,
+    },
+    /// Toggle (`None`) or set raw traffic display
+    Raw(Option<bool>),
+    Exit,
+}
+
+impl ReplCommand {
+    fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command {
+            "help" | "?" => Ok(Self::Help),
+            "tools" => Ok(Self::Tools),
+            "resources" => Ok(Self::Resources),
+            "prompts" => Ok(Self::Prompts),
+            "exit" | "quit" => Ok(Self::Exit),
+            "raw" => match rest {
+                "" => Ok(Self::Raw(None)),
+                "on" => Ok(Self::Raw(Some(true))),
+                "off" => Ok(Self::Raw(Some(false))),
+                other => Err(anyhow!("Invalid raw mode: {other}. Expected 'on' or 'off'")),
+            },
+            "call" => {
+                let (tool, json) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if tool.is_empty() {
+                    return Err(anyhow!("Usage: call <tool> [JSON object]"));
+                }
+                let arguments = match json.trim() {
+                    "" => Map::new(),
+                    json => match serde_json::from_str(json)? {
+                        Value::Object(map) => map,
+                        _ => return Err(anyhow!("Tool arguments must be a JSON object")),
+                    },
+                };
+                Ok(Self::Call {
+                    tool: tool.to_string(),
+                    arguments,
+                })
+            }
+            other => Err(anyhow!(
+                "Unknown command: {other}. Type 'help' for commands"
+            )),
+        }
+    }
+}
+
+/// Prints raw JSON-RPC traffic to stderr while enabled
+#[derive(Default)]
+struct RawTrafficPrinter {
+    enabled: AtomicBool,
+}
+
+impl TrafficObserver for RawTrafficPrinter {
+    fn observe(&self, direction: Direction, message: &Value) {
+        if self.enabled.load(Ordering::Relaxed) {
+            let arrow = match direction {
+                Direction::Outbound => ">>",
+                Direction::Inbound => "<<",
+            };
+            eprintln!("{arrow} {message}");
+        }
+    }
+}
+
+/// Tab completion for command names and tool names
+struct ReplHelper {
+    tool_names: Arc<Mutex<Vec<String>>>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let tool_names = self.tool_names.lock().map(|names| names.clone());
+        Ok(complete_line(&line[..pos], &tool_names.unwrap_or_default()))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Compute the completion start offset and candidates for the text before the cursor
+fn complete_line(line: &str, tool_names: &[String]) -> (usize, Vec<String>) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let ends_with_space = line.ends_with(char::is_whitespace);
+    let start = if ends_with_space {
+        line.len()
+    } else {
+        line.rfind(char::is_whitespace).map_or(0, |i| i + 1)
+    };
+    let partial = &line[start..];
+    let word_index = if ends_with_space {
+        words.len()
+    } else {
+        words.len().saturating_sub(1)
+    };
+
+    let candidates: Vec<String> = match (word_index, words.first().copied()) {
+        (0, _) => COMMANDS.iter().map(|c| (*c).to_string()).collect(),
+        (1, Some("call")) => tool_names.to_vec(),
+        (1, Some("raw")) => vec!["on".to_string(), "off".to_string()],
+        _ => Vec::new(),
+    };
+
+    (
+        start,
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(partial))
+            .collect(),
+    )
+}
+
+fn history_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("deepwiki-mcp-bridge").join("repl_history"))
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  tools                     List tools exposed by the server");
+    println!("  resources                 List resources and resource templates");
+    println!("  prompts                   List prompts");
+    println!("  call <tool> [JSON]        Call a tool with a JSON object of arguments");
+    println!("  raw [on|off]              Toggle display of raw JSON-RPC traffic");
+    println!("  help                      Show this help");
+    println!("  exit | quit               Leave the REPL (Ctrl+D also works)");
+}
+
+/// Run the REPL against `endpoint_url` until the user exits
+pub async fn run(endpoint_url: &str) -> Result<()> {
+    let traffic = Arc::new(RawTrafficPrinter::default());
+    let client = connect_remote(endpoint_url, Some(traffic.clone())).await?;
+
+    let tool_names = Arc::new(Mutex::new(Vec::new()));
+    refresh_tool_names(&client, &tool_names).await;
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper {
+        tool_names: tool_names.clone(),
+    }));
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    println!("Connected to {endpoint_url}. Type 'help' for commands.");
+
+    loop {
+        let line = tokio::task::block_in_place(|| editor.readline(PROMPT));
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                shutdown(client).await;
+                return Err(e.into());
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        match ReplCommand::parse(&line) {
+            Ok(ReplCommand::Exit) => break,
+            Ok(command) => {
+                if let Err(e) = execute(&client, command, &traffic, &tool_names).await {
+                    eprintln!("error: {e}");
+                }
+            }
+            Err(e) => eprintln!("error: {e}"),
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = editor.save_history(path);
+    }
+    shutdown(client).await;
+    Ok(())
+}
+
+async fn refresh_tool_names(client: &RemoteClient, tool_names: &Mutex<Vec<String>>) {
+    if let Ok(tools) = client.list_all_tools().await {
+        if let Ok(mut names) = tool_names.lock() {
+            *names = tools
+                .into_iter()
+                .map(|tool| tool.name.into_owned())
+                .collect();
+        }
+    }
+}
+
+async fn execute(
+    client: &RemoteClient,
+    command: ReplCommand,
+    traffic: &RawTrafficPrinter,
+    tool_names: &Mutex<Vec<String>>,
+) -> Result<()> {
+    match command {
+        ReplCommand::Help => print_help(),
+        ReplCommand::Tools => {
+            let tools = client.list_all_tools().await?;
+            for tool in &tools {
+                let summary = tool
+                    .description
+                    .as_deref()
+                    .and_then(|d| d.lines().next())
+                    .unwrap_or_default();
+                println!("  {:<28} {}", tool.name, summary);
+            }
+            if let Ok(mut names) = tool_names.lock() {
+                *names = tools
+                    .into_iter()
+                    .map(|tool| tool.name.into_owned())
+                    .collect();
+            }
+        }
+        ReplCommand::Resources => {
+            for resource in client.list_all_resources().await? {
+                println!("  {:<40} {}", resource.uri, resource.name);
+            }
+            for template in client.list_all_resource_templates().await? {
+                println!("  {:<40} {}", template.uri_template, template.name);
+            }
+        }
+        ReplCommand::Prompts => {
+            for prompt in client.list_all_prompts().await? {
+                println!(
+                    "  {:<28} {}",
+                    prompt.name,
+                    prompt.description.unwrap_or_default()
+                );
+            }
+        }
+        ReplCommand::Call { tool, arguments } => {
+            let result = client
+                .call_tool(CallToolRequestParam {
+                    name: tool.into(),
+                    arguments: Some(arguments),
+                })
+                .await?;
+            if result.is_error == Some(true) {
+                eprintln!("(tool reported an error)");
+            }
+            println!("{}", format_call_result_text(&result));
+        }
+        ReplCommand::Raw(mode) => {
+            let enabled = mode.unwrap_or_else(|| !traffic.enabled.load(Ordering::Relaxed));
+            traffic.enabled.store(enabled, Ordering::Relaxed);
+            println!("Raw traffic display {}", if enabled { "on" } else { "off" });
+        }
+        ReplCommand::Exit => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_repl_commands() {
+        assert_eq!(ReplCommand::parse("tools").unwrap(), ReplCommand::Tools);
+        assert_eq!(ReplCommand::parse("  quit ").unwrap(), ReplCommand::Exit);
+        assert_eq!(ReplCommand::parse("raw").unwrap(), ReplCommand::Raw(None));
+        assert_eq!(
+            ReplCommand::parse("raw off").unwrap(),
+            ReplCommand::Raw(Some(false))
+        );
+        assert!(ReplCommand::parse("raw maybe").is_err());
+        assert!(ReplCommand::parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_parse_call_with_json_arguments() {
+        let command =
+            ReplCommand::parse(r#"call ask_question {"repoName": "a/b", "question": "why?"}"#)
+                .unwrap();
+        let ReplCommand::Call { tool, arguments } = command else {
+            panic!("expected call command");
+        };
+        assert_eq!(tool, "ask_question");
+        assert_eq!(
+            Value::Object(arguments),
+            json!({"repoName": "a/b", "question": "why?"})
+        );
+
+        assert!(ReplCommand::parse("call").is_err());
+        assert!(ReplCommand::parse("call tool [1]").is_err());
+        assert_eq!(
+            ReplCommand::parse("call tool").unwrap(),
+            ReplCommand::Call {
+                tool: "tool".to_string(),
+                arguments: Map::new()
+            }
+        );
+    }
+
+    #[test]
+    fn test_completion() {
+        let tools = vec![
+            "read_wiki_structure".to_string(),
+            "read_wiki_contents".to_string(),
+            "ask_question".to_string(),
+        ];
+
+        assert_eq!(complete_line("to", &tools), (0, vec!["tools".to_string()]));
+        assert_eq!(
+            complete_line("call read_wiki_s", &tools),
+            (5, vec!["read_wiki_structure".to_string()])
+        );
+        assert_eq!(complete_line("call ", &tools).1.len(), 3);
+        assert_eq!(
+            complete_line("raw o", &tools).1,
+            vec!["on".to_string(), "off".to_string()]
+        );
+        assert!(complete_line("call ask_question {", &tools).1.is_empty());
+    }
+}
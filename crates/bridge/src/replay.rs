@@ -0,0 +1,342 @@
+//! Serve a `--record` recording back as a fake MCP server
+//!
+//! Requests are answered by matching their method and params against the recorded requests of
+//! one hop: either Zed's side of the bridge (`replay <file>` then stands in for the whole
+//! bridge) or one upstream (`replay <file> --upstream NAME`, or an upstream whose URL is
+//! `file:///path/to/recording.jsonl`). Identical requests replay their recorded responses in
+//! order, repeating the last one once exhausted. `initialize`, `ping` and the list methods fall
+//! back to any recorded response for the method, since client details differ between runs.
+//! Nothing touches the network, so recordings double as deterministic test fixtures.
+
+use crate::recording::{Hop, RecordedMessage};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, warn};
+
+/// JSON-RPC error code for requests the recording has no answer to
+const NO_RECORDING_CODE: i64 = -32603;
+
+/// Which recorded conversation to replay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplaySide {
+    /// Zed's requests to the bridge and the bridge's responses
+    Bridge,
+    /// The bridge's requests to an upstream and its responses; any upstream when `None` or
+    /// when the recording has nothing for the named one
+    Upstream(Option<String>),
+}
+
+/// A recorded request and the `result` or `error` member of its response
+#[derive(Debug, Clone)]
+struct Exchange {
+    method: String,
+    params: Value,
+    response: (String, Value),
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    exchanges: Vec<Exchange>,
+    /// Times each exchange has been replayed
+    replayed: Mutex<Vec<usize>>,
+}
+
+impl Recording {
+    pub fn load(path: &Path, side: &ReplaySide) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Cannot read recording {}: {}", path.display(), e))?;
+        let mut records = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: RecordedMessage = serde_json::from_str(line).map_err(|e| {
+                anyhow!(
+                    "Invalid recording {} line {}: {}",
+                    path.display(),
+                    number + 1,
+                    e
+                )
+            })?;
+            records.push(record);
+        }
+        Ok(Self::from_records(&records, side))
+    }
+
+    fn from_records(records: &[RecordedMessage], side: &ReplaySide) -> Self {
+        let (request_hop, response_hop) = match side {
+            ReplaySide::Bridge => (Hop::ZedToBridge, Hop::BridgeToZed),
+            ReplaySide::Upstream(_) => (Hop::BridgeToUpstream, Hop::UpstreamToBridge),
+        };
+        let upstream = match side {
+            ReplaySide::Upstream(Some(name))
+                if records.iter().any(|r| r.upstream.as_ref() == Some(name)) =>
+            {
+                Some(name)
+            }
+            _ => None,
+        };
+        let on_side = |record: &&RecordedMessage| {
+            upstream.is_none_or(|name| record.upstream.as_ref() == Some(name))
+        };
+
+        let mut pending = HashMap::new();
+        let mut exchanges = Vec::new();
+        for record in records.iter().filter(on_side) {
+            let message = &record.message;
+            let Some(id) = message.get("id") else {
+                continue;
+            };
+            let key = (record.upstream.clone(), id.to_string());
+            if record.direction == request_hop {
+                if let Some(method) = message.get("method").and_then(Value::as_str) {
+                    pending.insert(key, (method.to_string(), strip_meta(message.get("params"))));
+                }
+            } else if record.direction == response_hop {
+                let Some((method, params)) = pending.remove(&key) else {
+                    continue;
+                };
+                let response = ["result", "error"].into_iter().find_map(|member| {
+                    message
+                        .get(member)
+                        .map(|value| (member.to_string(), value.clone()))
+                });
+                if let Some(response) = response {
+                    exchanges.push(Exchange {
+                        method,
+                        params,
+                        response,
+                    });
+                }
+            }
+        }
+
+        Self {
+            replayed: Mutex::new(vec![0; exchanges.len()]),
+            exchanges,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    /// The recorded response member (`result` or `error`) for a request
+    fn respond(&self, method: &str, params: Option<&Value>) -> Option<(String, Value)> {
+        let params = strip_meta(params);
+        let exact: Vec<usize> = self
+            .exchanges
+            .iter()
+            .enumerate()
+            .filter(|(_, exchange)| exchange.method == method && exchange.params == params)
+            .map(|(index, _)| index)
+            .collect();
+        let candidates = if exact.is_empty() && matches_by_method(method) {
+            self.exchanges
+                .iter()
+                .enumerate()
+                .filter(|(_, exchange)| exchange.method == method)
+                .map(|(index, _)| index)
+                .collect()
+        } else {
+            exact
+        };
+
+        let mut replayed = self.replayed.lock().ok()?;
+        let index = candidates
+            .iter()
+            .copied()
+            .find(|&index| replayed[index] == 0)
+            .or_else(|| candidates.last().copied())?;
+        replayed[index] += 1;
+        Some(self.exchanges[index].response.clone())
+    }
+
+    /// The JSON-RPC response to an incoming message; `None` for notifications
+    pub fn reply(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id")?.clone();
+        let method = message.get("method")?.as_str()?;
+        let (member, value) = match self.respond(method, message.get("params")) {
+            Some(response) => response,
+            None if method == "ping" => ("result".to_string(), json!({})),
+            None => {
+                warn!("No recorded response for {}", method);
+                (
+                    "error".to_string(),
+                    json!({
+                        "code": NO_RECORDING_CODE,
+                        "message": format!("No recorded response for {method} with these params"),
+                    }),
+                )
+            }
+        };
+        let mut response = json!({"jsonrpc": "2.0", "id": id});
+        response[member] = value;
+        Some(response)
+    }
+}
+
+/// Requests whose params vary between runs without changing the answer
+fn matches_by_method(method: &str) -> bool {
+    method == "initialize" || method == "ping" || method.ends_with("/list")
+}
+
+/// Params without `_meta`, which carries per-run progress tokens
+fn strip_meta(params: Option<&Value>) -> Value {
+    let mut params = params.cloned().unwrap_or(Value::Null);
+    if let Some(object) = params.as_object_mut() {
+        object.remove("_meta");
+        if object.is_empty() {
+            return Value::Null;
+        }
+    }
+    params
+}
+
+/// Answer newline-delimited JSON-RPC requests from `reader` until it closes
+pub async fn serve<R, W>(recording: &Recording, reader: R, mut writer: W) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Ignoring malformed message: {}", e);
+                continue;
+            }
+        };
+        let Some(response) = recording.reply(&message) else {
+            debug!("Ignoring notification {}", message["method"]);
+            continue;
+        };
+        let mut line = serde_json::to_string(&response)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Run `replay <file>` on STDIO
+pub async fn run(file: &Path, upstream: Option<String>) -> Result<()> {
+    let side = match upstream {
+        Some(name) => ReplaySide::Upstream(Some(name)),
+        None => ReplaySide::Bridge,
+    };
+    let recording = Recording::load(file, &side)?;
+    if recording.len() == 0 {
+        return Err(anyhow!(
+            "{} has no recorded exchanges for {:?}",
+            file.display(),
+            side
+        ));
+    }
+    serve(&recording, tokio::io::stdin(), tokio::io::stdout()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(direction: Hop, upstream: Option<&str>, message: Value) -> RecordedMessage {
+        RecordedMessage {
+            timestamp: "2026-01-01T00:00:00.000Z".to_string(),
+            direction,
+            upstream: upstream.map(str::to_string),
+            latency_ms: None,
+            message,
+        }
+    }
+
+    fn recording(side: &ReplaySide) -> Recording {
+        let call = |id, question| {
+            json!({"id": id, "method": "tools/call", "params": {"name": "ask_question",
+                "arguments": {"question": question}, "_meta": {"progressToken": id}}})
+        };
+        let records = [
+            record(Hop::ZedToBridge, None, call(1, "why")),
+            record(Hop::BridgeToUpstream, Some("deepwiki"), call(5, "why")),
+            record(
+                Hop::UpstreamToBridge,
+                Some("deepwiki"),
+                json!({"id": 5, "result": {"n": 1}}),
+            ),
+            record(Hop::BridgeToZed, None, json!({"id": 1, "result": {"n": 1}})),
+            record(Hop::ZedToBridge, None, call(2, "why")),
+            record(Hop::BridgeToZed, None, json!({"id": 2, "result": {"n": 2}})),
+            record(
+                Hop::ZedToBridge,
+                None,
+                json!({"id": 3, "method": "tools/list"}),
+            ),
+            record(
+                Hop::BridgeToZed,
+                None,
+                json!({"id": 3, "error": {"code": 1}}),
+            ),
+        ];
+        Recording::from_records(&records, side)
+    }
+
+    #[test]
+    fn test_replays_matching_responses_in_order() {
+        let recording = recording(&ReplaySide::Bridge);
+        assert_eq!(recording.len(), 3);
+
+        let ask = json!({"jsonrpc": "2.0", "id": 40, "method": "tools/call",
+            "params": {"name": "ask_question", "arguments": {"question": "why"}}});
+        let results: Vec<Value> = (0..3)
+            .map(|_| recording.reply(&ask).unwrap()["result"]["n"].clone())
+            .collect();
+        assert_eq!(results, [json!(1), json!(2), json!(2)]);
+        assert_eq!(recording.reply(&ask).unwrap()["id"], 40);
+
+        let list = json!({"id": 41, "method": "tools/list", "params": {"cursor": null}});
+        assert_eq!(recording.reply(&list).unwrap()["error"]["code"], 1);
+
+        let other = json!({"id": 42, "method": "tools/call",
+            "params": {"name": "ask_question", "arguments": {"question": "how"}}});
+        assert_eq!(
+            recording.reply(&other).unwrap()["error"]["code"],
+            NO_RECORDING_CODE
+        );
+        assert!(recording
+            .reply(&json!({"method": "notifications/initialized"}))
+            .is_none());
+    }
+
+    #[test]
+    fn test_upstream_side_falls_back_to_any_upstream() {
+        for name in ["deepwiki", "default"] {
+            let recording = recording(&ReplaySide::Upstream(Some(name.to_string())));
+            assert_eq!(recording.len(), 1);
+            let ask = json!({"id": 1, "method": "tools/call",
+                "params": {"name": "ask_question", "arguments": {"question": "why"}}});
+            assert_eq!(recording.reply(&ask).unwrap()["result"]["n"], 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_json_rpc_lines() {
+        let recording = recording(&ReplaySide::Bridge);
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":7,"method":"ping"}"#,
+            "\n"
+        );
+        let mut output = Vec::new();
+        serve(&recording, input.as_bytes(), &mut output)
+            .await
+            .unwrap();
+        let response: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 7, "result": {}}));
+    }
+}
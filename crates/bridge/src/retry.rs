@@ -0,0 +1,287 @@
+//! Retrying transient upstream failures
+//!
+//! A request that fails with a gateway error (502, 504), a 503 or 429, a timeout or a network
+//! error is sent again after an exponential backoff with jitter, up to `max_attempts` in all.
+//! A `Retry-After` header on a 429 or 503 response sets the delay instead, unless it asks for
+//! more than [`MAX_RETRY_AFTER`].
+//!
+//! Only idempotent requests are retried: listing, reading and completion requests, and the
+//! tools classified as idempotent in [`RetrySettings::tools`] (by default the DeepWiki tools
+//! that read a wiki). Other tool calls fail on the first error, since sending them twice could
+//! repeat their side effects or, for `ask_question`, pay for another generated answer.
+//!
+//! HTTP status codes and `Retry-After` are only visible to the transport's HTTP client, which
+//! notes them per JSON-RPC request in [`HttpFailures`] for the proxy to look up.
+
+use crate::metrics::method_name;
+use crate::settings::RetrySettings;
+use chrono::{DateTime, Utc};
+use rmcp::model::{ClientRequest, RequestId};
+use rmcp::service::ServiceError;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Longest `Retry-After` the bridge waits for; a server asking for more is not retried
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Methods that read state without changing it, retried regardless of tool classification
+const IDEMPOTENT_METHODS: [&str; 8] = [
+    "ping",
+    "tools/list",
+    "prompts/list",
+    "prompts/get",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "completion/complete",
+];
+
+/// A non-success HTTP response to a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpFailure {
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpFailure {
+    /// Overloaded, throttled or failing gateways, as opposed to rejected requests
+    pub fn is_transient(&self) -> bool {
+        matches!(self.status, 429 | 502 | 503 | 504)
+    }
+}
+
+/// HTTP failures of an upstream's requests, by JSON-RPC request ID
+#[derive(Debug, Default)]
+pub struct HttpFailures(Mutex<HashMap<RequestId, HttpFailure>>);
+
+impl HttpFailures {
+    pub fn record(&self, id: RequestId, status: u16, retry_after: Option<&str>) {
+        let retry_after = match status {
+            429 | 503 => retry_after.and_then(|value| parse_retry_after(value, SystemTime::now())),
+            _ => None,
+        };
+        if let Ok(mut failures) = self.0.lock() {
+            failures.insert(
+                id,
+                HttpFailure {
+                    status,
+                    retry_after,
+                },
+            );
+        }
+    }
+
+    /// Remove and return the failure noted for a request
+    pub fn take(&self, id: &RequestId) -> Option<HttpFailure> {
+        self.0.lock().ok()?.remove(id)
+    }
+}
+
+/// When to retry a failed request
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    tools: HashMap<String, bool>,
+}
+
+impl RetryPolicy {
+    pub fn new(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(settings.initial_backoff_ms),
+            max_backoff: Duration::from_millis(settings.max_backoff_ms),
+            tools: settings.tools.clone(),
+        }
+    }
+
+    /// Whether sending the request again is harmless
+    pub fn is_idempotent(&self, request: &ClientRequest) -> bool {
+        match request {
+            ClientRequest::CallToolRequest(call) => self
+                .tools
+                .get(call.params.name.as_ref())
+                .copied()
+                .unwrap_or(false),
+            request => IDEMPOTENT_METHODS.contains(&method_name(request)),
+        }
+    }
+
+    /// Delay before the next attempt after `attempt` (counting from 1) failed, or `None` to
+    /// give up
+    pub fn delay(
+        &self,
+        attempt: u32,
+        error: &ServiceError,
+        http: Option<HttpFailure>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_transient(error, http) {
+            return None;
+        }
+        match http.and_then(|http| http.retry_after) {
+            Some(retry_after) if retry_after > MAX_RETRY_AFTER => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// Exponential backoff with "equal jitter": half the capped delay, plus up to that again
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let capped = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        let half = capped / 2;
+        half + half.mul_f64(jitter())
+    }
+}
+
+/// Whether a failure may succeed when repeated
+fn is_transient(error: &ServiceError, http: Option<HttpFailure>) -> bool {
+    match (error, http) {
+        (ServiceError::TransportSend(_), Some(http)) => http.is_transient(),
+        (ServiceError::TransportSend(_) | ServiceError::Timeout { .. }, None) => true,
+        _ => false,
+    }
+}
+
+/// A uniformly distributed fraction in `[0, 1)`
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let bits = RandomState::new().hash_one(nanos) >> 11;
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = bits as f64 / (1u64 << 53) as f64;
+    fraction
+}
+
+/// A `Retry-After` value, either delay seconds or an HTTP date
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    let now = DateTime::<Utc>::from(now);
+    Some((date - now).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{
+        CallToolRequest, CallToolRequestParam, ListToolsRequest, NumberOrString, PingRequest,
+    };
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(&RetrySettings::default())
+    }
+
+    fn call(name: &str) -> ClientRequest {
+        ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: None,
+        }))
+    }
+
+    fn send_error() -> ServiceError {
+        ServiceError::TransportSend(Box::new(std::io::Error::other("connection reset")))
+    }
+
+    fn status(status: u16, retry_after: Option<Duration>) -> Option<HttpFailure> {
+        Some(HttpFailure {
+            status,
+            retry_after,
+        })
+    }
+
+    #[test]
+    fn test_classifies_idempotent_requests() {
+        let policy = policy();
+        assert!(policy.is_idempotent(&call("read_wiki_contents")));
+        // A generated answer costs as much again when repeated
+        assert!(!policy.is_idempotent(&call("ask_question")));
+        assert!(!policy.is_idempotent(&call("create_issue")));
+        assert!(policy.is_idempotent(&ClientRequest::ListToolsRequest(ListToolsRequest::default())));
+        assert!(policy.is_idempotent(&ClientRequest::PingRequest(PingRequest::default())));
+
+        let mut settings = RetrySettings::default();
+        settings.tools.insert("ask_question".to_string(), true);
+        settings
+            .tools
+            .insert("read_wiki_contents".to_string(), false);
+        let policy = RetryPolicy::new(&settings);
+        assert!(policy.is_idempotent(&call("ask_question")));
+        assert!(!policy.is_idempotent(&call("read_wiki_contents")));
+    }
+
+    #[test]
+    fn test_retries_transient_failures_only() {
+        let policy = policy();
+        assert!(policy.delay(1, &send_error(), None).is_some());
+        assert!(policy.delay(1, &send_error(), status(503, None)).is_some());
+        assert!(policy.delay(1, &send_error(), status(404, None)).is_none());
+        assert!(policy
+            .delay(1, &ServiceError::TransportClosed, None)
+            .is_none());
+        // The third attempt is the last by default
+        assert!(policy.delay(3, &send_error(), None).is_none());
+    }
+
+    #[test]
+    fn test_backoff_grows_within_bounds() {
+        let policy = policy();
+        for attempt in 1..10 {
+            let capped = policy
+                .initial_backoff
+                .saturating_mul(1 << (attempt - 1))
+                .min(policy.max_backoff);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= capped / 2 && delay <= capped, "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn test_honors_retry_after() {
+        let policy = policy();
+        let retry_after = Some(Duration::from_secs(2));
+        assert_eq!(
+            policy.delay(1, &send_error(), status(429, retry_after)),
+            retry_after
+        );
+        let too_long = Some(MAX_RETRY_AFTER + Duration::from_secs(1));
+        assert_eq!(policy.delay(1, &send_error(), status(503, too_long)), None);
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_470);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+
+        let failures = HttpFailures::default();
+        let id = NumberOrString::Number(7);
+        failures.record(id.clone(), 503, Some("3"));
+        assert_eq!(
+            failures.take(&id),
+            status(503, Some(Duration::from_secs(3)))
+        );
+        assert_eq!(failures.take(&id), None);
+        // Only throttling responses carry a meaningful Retry-After
+        failures.record(id.clone(), 502, Some("3"));
+        assert_eq!(failures.take(&id), status(502, None));
+    }
+}
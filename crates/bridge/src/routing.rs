@@ -0,0 +1,186 @@
+//! Route DeepWiki tool calls to an upstream by repository
+//!
+//! With several upstreams, the `repoName` tools are listed once under their plain names and
+//! each call picks its upstream from the repository: the `private` list first, then the
+//! `rules` in order, then the default. Patterns are case-insensitive globs over
+//! `owner/repo`, so `our-org/*` covers a whole organization. When the chosen upstream reports
+//! that it has not indexed the repository, the call is retried against the `fallback`
+//! upstream, typically Devin for repositories DeepWiki does not cover.
+
+use crate::filter::glob_match;
+use crate::project::REPO_TOOLS;
+use crate::settings::{RouteRule, RoutingSettings};
+use anyhow::{anyhow, Result};
+use rmcp::model::CallToolResult;
+
+/// Phrases DeepWiki uses when a repository has no wiki yet
+const NOT_INDEXED_MARKERS: &[&str] = &[
+    "not indexed",
+    "not been indexed",
+    "repository not found",
+    "to index it",
+];
+
+/// Longest successful result still treated as a notice rather than content
+const MAX_NOTICE_LEN: usize = 300;
+
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    rules: Vec<RouteRule>,
+    private: Vec<String>,
+    private_upstream: Option<String>,
+    default: Option<String>,
+    fallback: Option<String>,
+}
+
+impl Router {
+    pub fn new(settings: &RoutingSettings) -> Self {
+        Self {
+            rules: settings.rules.clone(),
+            private: settings.private.clone(),
+            private_upstream: settings.private_upstream.clone(),
+            default: settings.default.clone(),
+            fallback: settings.fallback.clone(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.rules.is_empty()
+            || self.private_upstream.is_some()
+            || self.default.is_some()
+            || self.fallback.is_some()
+    }
+
+    /// Whether calls to `tool` are routed by repository rather than by namespace
+    pub fn routes(&self, tool: &str) -> bool {
+        self.is_configured() && REPO_TOOLS.contains(&tool)
+    }
+
+    /// Upstream name for `repo`; `None` means the first upstream
+    pub fn route(&self, repo: &str) -> Option<&str> {
+        let repo = repo.to_ascii_lowercase();
+        let matches = |pattern: &str| glob_match(&pattern.to_ascii_lowercase(), &repo);
+
+        if let Some(upstream) = &self.private_upstream {
+            if self.private.iter().any(|pattern| matches(pattern)) {
+                return Some(upstream);
+            }
+        }
+        self.rules
+            .iter()
+            .find(|rule| matches(&rule.repo))
+            .map(|rule| rule.upstream.as_str())
+            .or(self.default.as_deref())
+    }
+
+    /// Upstream to retry on when `upstream` has not indexed the repository
+    pub fn fallback_for(&self, upstream: &str) -> Option<&str> {
+        self.fallback
+            .as_deref()
+            .filter(|fallback| *fallback != upstream)
+    }
+
+    /// Check every upstream the rules name exists
+    pub fn validate(&self, upstreams: &[&str]) -> Result<()> {
+        let named = self
+            .rules
+            .iter()
+            .map(|rule| &rule.upstream)
+            .chain(&self.private_upstream)
+            .chain(&self.default)
+            .chain(&self.fallback);
+        for name in named {
+            if !upstreams.contains(&name.as_str()) {
+                return Err(anyhow!(
+                    "Routing refers to unknown upstream '{}'; configured upstreams: {}",
+                    name,
+                    upstreams.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a tool result or error message says the repository is not indexed
+pub fn reports_not_indexed(text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    NOT_INDEXED_MARKERS
+        .iter()
+        .any(|marker| text.contains(marker))
+}
+
+/// Whether a tool result says the repository is not indexed
+///
+/// DeepWiki sometimes reports this as a successful call, so short results count too.
+pub fn result_not_indexed(result: &CallToolResult) -> bool {
+    let text = crate::mirror::result_text(result);
+    (result.is_error == Some(true) || text.len() <= MAX_NOTICE_LEN) && reports_not_indexed(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router {
+        let settings: RoutingSettings = serde_json::from_value(serde_json::json!({
+            "rules": [
+                {"repo": "our-org/*", "upstream": "devin"},
+                {"repo": "*/public-*", "upstream": "deepwiki"}
+            ],
+            "private": ["me/secret", "Partner/*"],
+            "private_upstream": "devin",
+            "default": "deepwiki",
+            "fallback": "devin"
+        }))
+        .unwrap();
+        Router::new(&settings)
+    }
+
+    #[test]
+    fn test_routes_by_private_list_rules_and_default() {
+        let router = router();
+        assert_eq!(router.route("me/secret"), Some("devin"));
+        assert_eq!(router.route("partner/tools"), Some("devin"));
+        assert_eq!(router.route("Our-Org/platform"), Some("devin"));
+        assert_eq!(router.route("me/public-site"), Some("deepwiki"));
+        assert_eq!(router.route("tokio-rs/tokio"), Some("deepwiki"));
+
+        assert!(router.routes("ask_question"));
+        assert!(!router.routes("search_cached_wiki"));
+        assert!(!Router::default().routes("ask_question"));
+        assert_eq!(Router::default().route("tokio-rs/tokio"), None);
+    }
+
+    #[test]
+    fn test_fallback_and_validation() {
+        let router = router();
+        assert_eq!(router.fallback_for("deepwiki"), Some("devin"));
+        assert_eq!(router.fallback_for("devin"), None);
+
+        assert!(router.validate(&["deepwiki", "devin"]).is_ok());
+        assert!(router.validate(&["deepwiki"]).is_err());
+    }
+
+    #[test]
+    fn test_not_indexed_detection() {
+        assert!(reports_not_indexed(
+            "Error: Repository not found. Visit https://deepwiki.com to index it."
+        ));
+        assert!(!reports_not_indexed("Tokio is an asynchronous runtime"));
+
+        let error = CallToolResult::error(vec![rmcp::model::Content::text(
+            "me/app has not been indexed",
+        )]);
+        assert!(result_not_indexed(&error));
+        let notice = CallToolResult::success(vec![rmcp::model::Content::text(
+            "Repository not found. Visit https://deepwiki.com to index it.",
+        )]);
+        assert!(result_not_indexed(&notice));
+        let page = CallToolResult::success(vec![rmcp::model::Content::text(format!(
+            "# Troubleshooting\nA repository not found error means...\n{}",
+            "More details. ".repeat(40)
+        ))]);
+        assert!(!result_not_indexed(&page));
+    }
+}
@@ -0,0 +1,479 @@
+//! Local full-text search over every wiki page the bridge has fetched
+//!
+//! Pages from `read_wiki_contents` responses (and `mirror` runs) are persisted as one JSON file
+//! per repository. On startup they are loaded into an in-memory inverted index ranked with
+//! BM25, which backs the bridge-provided [`SEARCH_TOOL`]. The corpus is small enough that the
+//! index is simply rebuilt whenever a repository's pages change.
+
+use crate::mirror::split_contents;
+use crate::settings::SearchSettings;
+use anyhow::{anyhow, Result};
+use rmcp::model::{JsonObject, Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Name of the synthetic tool listed next to the upstream tools
+pub const SEARCH_TOOL: &str = "search_cached_wiki";
+
+const DEFAULT_LIMIT: usize = 5;
+const MAX_LIMIT: usize = 50;
+const SNIPPET_CHARS: usize = 200;
+
+/// BM25 term-frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document-length normalization
+const B: f64 = 0.75;
+
+/// All stored pages of one repository's wiki
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredWiki {
+    repo: String,
+    fetched_at: u64,
+    pages: Vec<StoredPage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPage {
+    title: String,
+    body: String,
+}
+
+/// A ranked search result
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub repo: String,
+    pub title: String,
+    pub score: f64,
+    pub snippet: String,
+}
+
+struct Document {
+    repo: String,
+    page: StoredPage,
+    len: usize,
+}
+
+/// Inverted index from term to `(document, term frequency)` postings
+#[derive(Default)]
+struct SearchIndex {
+    documents: Vec<Document>,
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    average_len: f64,
+}
+
+impl SearchIndex {
+    fn build(wikis: &BTreeMap<String, Vec<StoredPage>>) -> Self {
+        let mut index = Self::default();
+        for (repo, pages) in wikis {
+            for page in pages {
+                let mut frequencies: HashMap<String, u32> = HashMap::new();
+                // Titles are indexed twice so a match there outweighs one in passing
+                let title_tokens = tokenize(&page.title);
+                let tokens = title_tokens
+                    .iter()
+                    .chain(&title_tokens)
+                    .cloned()
+                    .chain(tokenize(&page.body));
+                let mut len = 0;
+                for token in tokens {
+                    *frequencies.entry(token).or_default() += 1;
+                    len += 1;
+                }
+
+                let id = index.documents.len();
+                for (term, frequency) in frequencies {
+                    index
+                        .postings
+                        .entry(term)
+                        .or_default()
+                        .push((id, frequency));
+                }
+                index.documents.push(Document {
+                    repo: repo.clone(),
+                    page: page.clone(),
+                    len,
+                });
+            }
+        }
+
+        let total: usize = index.documents.iter().map(|doc| doc.len).sum();
+        index.average_len = total as f64 / index.documents.len().max(1) as f64;
+        index
+    }
+
+    fn search(&self, query: &str, repo: Option<&str>, limit: usize) -> Vec<SearchHit> {
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        let document_count = self.documents.len() as f64;
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f64;
+            let idf = ((document_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for &(id, frequency) in postings {
+                let document = &self.documents[id];
+                if repo.is_some_and(|repo| !document.repo.eq_ignore_ascii_case(repo)) {
+                    continue;
+                }
+                let tf = f64::from(frequency);
+                let norm = K1 * (1.0 - B + B * document.len as f64 / self.average_len);
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|(a_id, a), (b_id, b)| {
+            let (a_doc, b_doc) = (&self.documents[*a_id], &self.documents[*b_id]);
+            b.total_cmp(a)
+                .then_with(|| a_doc.repo.cmp(&b_doc.repo))
+                .then_with(|| a_doc.page.title.cmp(&b_doc.page.title))
+        });
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(id, score)| {
+                let document = &self.documents[id];
+                SearchHit {
+                    repo: document.repo.clone(),
+                    title: document.page.title.clone(),
+                    score,
+                    snippet: snippet(&document.page.body, &terms),
+                }
+            })
+            .collect()
+    }
+
+    fn repo_count(&self) -> usize {
+        self.documents
+            .iter()
+            .map(|doc| doc.repo.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Persistent page store plus the index built over it
+pub struct WikiSearch {
+    dir: PathBuf,
+    wikis: RwLock<BTreeMap<String, Vec<StoredPage>>>,
+    index: RwLock<SearchIndex>,
+}
+
+impl WikiSearch {
+    /// Load every stored wiki, or `None` when search is disabled
+    pub fn open(settings: &SearchSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        let dir = settings.dir.clone().or_else(default_pages_dir)?;
+
+        let mut wikis = BTreeMap::new();
+        for path in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = path.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match read_wiki_file(&path) {
+                Some(wiki) => {
+                    wikis.insert(wiki.repo, wiki.pages);
+                }
+                None => warn!("Skipping unreadable wiki page file {}", path.display()),
+            }
+        }
+
+        let index = SearchIndex::build(&wikis);
+        Some(Self {
+            dir,
+            wikis: RwLock::new(wikis),
+            index: RwLock::new(index),
+        })
+    }
+
+    /// Persist the pages of a `read_wiki_contents` response and reindex
+    pub fn add_wiki(&self, repo: &str, contents: &str) -> Result<()> {
+        let pages: Vec<StoredPage> = split_contents(contents)
+            .into_iter()
+            .map(|(title, body)| StoredPage { title, body })
+            .collect();
+        if pages.is_empty() {
+            return Ok(());
+        }
+
+        let wiki = StoredWiki {
+            repo: repo.to_string(),
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            pages,
+        };
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", self.dir.display(), e))?;
+        let path = self.dir.join(format!("{}.json", file_name(repo)));
+        fs::write(&path, serde_json::to_vec(&wiki)?)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+
+        let mut wikis = self
+            .wikis
+            .write()
+            .map_err(|_| anyhow!("Search index lock poisoned"))?;
+        wikis.insert(wiki.repo, wiki.pages);
+        let index = SearchIndex::build(&wikis);
+        *self
+            .index
+            .write()
+            .map_err(|_| anyhow!("Search index lock poisoned"))? = index;
+        Ok(())
+    }
+
+    pub fn search(&self, query: &str, repo: Option<&str>, limit: usize) -> Vec<SearchHit> {
+        self.index
+            .read()
+            .map(|index| index.search(query, repo, limit))
+            .unwrap_or_default()
+    }
+
+    /// Run the [`SEARCH_TOOL`] with its JSON arguments, returning the result text
+    pub fn call(&self, arguments: &JsonObject) -> Result<String> {
+        let query = arguments
+            .get("query")
+            .and_then(|value| value.as_str())
+            .filter(|query| !query.trim().is_empty())
+            .ok_or_else(|| anyhow!("'query' must be a non-empty string"))?;
+        let repo = match arguments.get("repoName") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("'repoName' must be a string"))?,
+            ),
+        };
+        let limit = match arguments.get("limit") {
+            None => DEFAULT_LIMIT,
+            Some(value) => value
+                .as_u64()
+                .and_then(|limit| usize::try_from(limit).ok())
+                .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                .ok_or_else(|| anyhow!("'limit' must be an integer from 1 to {MAX_LIMIT}"))?,
+        };
+
+        let (pages, repos) = self
+            .index
+            .read()
+            .map(|index| (index.documents.len(), index.repo_count()))
+            .unwrap_or_default();
+        if pages == 0 {
+            return Ok(
+                "No wiki pages have been cached yet. Read a repository's wiki with \
+                 read_wiki_contents (or run `deepwiki-mcp-bridge mirror`) to make it searchable."
+                    .to_string(),
+            );
+        }
+
+        let hits = self.search(query, repo, limit);
+        Ok(format_hits(&hits, pages, repos))
+    }
+}
+
+/// Definition of the [`SEARCH_TOOL`] advertised in `tools/list`
+pub fn search_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "query": {
+                "type": "string",
+                "description": "Keywords to search for"
+            },
+            "repoName": {
+                "type": "string",
+                "description": "Only search this repository (owner/repo)"
+            },
+            "limit": {
+                "type": "integer",
+                "minimum": 1,
+                "maximum": MAX_LIMIT,
+                "description": "Maximum number of results (default 5)"
+            }
+        },
+        "required": ["query"]
+    });
+    Tool::new(
+        SEARCH_TOOL,
+        "Keyword search across every DeepWiki page previously fetched by this bridge. \
+         Fast, offline and deterministic; use read_wiki_contents or ask_question for \
+         repositories that have not been read yet.",
+        schema.as_object().cloned().unwrap_or_default(),
+    )
+}
+
+fn format_hits(hits: &[SearchHit], pages: usize, repos: usize) -> String {
+    let mut text = format!(
+        "Found {} matching pages (searched {} cached pages across {} repositories)\n",
+        hits.len(),
+        pages,
+        repos
+    );
+    for (rank, hit) in hits.iter().enumerate() {
+        let _ = write!(
+            text,
+            "\n{}. {} - {} (score {:.2})\n   {}\n",
+            rank + 1,
+            hit.repo,
+            hit.title,
+            hit.score,
+            hit.snippet
+        );
+    }
+    text
+}
+
+/// Lowercased alphanumeric terms; underscores are kept so identifiers stay whole
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The body line mentioning the most query terms, shortened for display
+fn snippet(body: &str, terms: &HashSet<String>) -> String {
+    let line = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .max_by_key(|(position, line)| {
+            let tokens: HashSet<String> = tokenize(line).into_iter().collect();
+            // Prefer earlier lines on ties
+            (
+                tokens.intersection(terms).count(),
+                std::cmp::Reverse(*position),
+            )
+        })
+        .map(|(_, line)| line)
+        .unwrap_or_default();
+
+    if line.chars().count() > SNIPPET_CHARS {
+        let truncated: String = line.chars().take(SNIPPET_CHARS).collect();
+        format!("{truncated}...")
+    } else {
+        line.to_string()
+    }
+}
+
+fn read_wiki_file(path: &Path) -> Option<StoredWiki> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Filesystem-safe name for a repository, e.g. `tokio-rs__tokio`
+fn file_name(repo: &str) -> String {
+    repo.replace('/', "__")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Platform data directory used when no `search.dir` setting is given
+fn default_pages_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("deepwiki-mcp-bridge").join("pages"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKIO: &str = "# Page: Overview\nTokio is an asynchronous runtime for Rust.\n\
+        # Page: Scheduler\nThe multi-thread scheduler uses work stealing.\n\
+        Each worker keeps a local run queue.\n\
+        # Page: I/O Driver\nThe driver polls mio for readiness events.\n";
+    const SERDE: &str = "# Page: Overview\nSerde is a framework for serializing Rust data.\n";
+
+    fn open_in(dir: &Path) -> WikiSearch {
+        WikiSearch::open(&SearchSettings {
+            enabled: true,
+            dir: Some(dir.to_path_buf()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Work-stealing, read_wiki_contents & I/O!"),
+            vec!["work", "stealing", "read_wiki_contents", "i", "o"]
+        );
+    }
+
+    #[test]
+    fn test_bm25_ranking_and_repo_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let search = open_in(dir.path());
+        search.add_wiki("tokio-rs/tokio", TOKIO).unwrap();
+        search.add_wiki("serde-rs/serde", SERDE).unwrap();
+
+        let hits = search.search("work stealing scheduler", None, 5);
+        assert_eq!(hits[0].title, "Scheduler");
+        assert_eq!(
+            hits[0].snippet,
+            "The multi-thread scheduler uses work stealing."
+        );
+
+        let hits = search.search("rust", None, 5);
+        assert_eq!(hits.len(), 2);
+        let hits = search.search("rust", Some("serde-rs/serde"), 5);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].repo, "serde-rs/serde");
+
+        assert!(search.search("nonexistent", None, 5).is_empty());
+    }
+
+    #[test]
+    fn test_pages_persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        open_in(dir.path())
+            .add_wiki("tokio-rs/tokio", TOKIO)
+            .unwrap();
+
+        let reopened = open_in(dir.path());
+        let hits = reopened.search("mio readiness", None, 1);
+        assert_eq!(hits[0].title, "I/O Driver");
+        assert!(dir.path().join("tokio-rs__tokio.json").exists());
+    }
+
+    #[test]
+    fn test_tool_call_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let search = open_in(dir.path());
+        let args = |value: serde_json::Value| value.as_object().cloned().unwrap();
+
+        let empty = search.call(&args(json!({"query": "tokio"}))).unwrap();
+        assert!(empty.starts_with("No wiki pages have been cached yet"));
+
+        search.add_wiki("tokio-rs/tokio", TOKIO).unwrap();
+        let text = search
+            .call(&args(json!({"query": "scheduler", "limit": 1})))
+            .unwrap();
+        assert!(text.starts_with("Found 1 matching pages"));
+        assert!(text.contains("1. tokio-rs/tokio - Scheduler"));
+
+        assert!(search.call(&args(json!({}))).is_err());
+        assert!(search
+            .call(&args(json!({"query": "x", "limit": 0})))
+            .is_err());
+    }
+}
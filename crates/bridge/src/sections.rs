@@ -0,0 +1,437 @@
+//! Section-by-section reading of a repository's wiki
+//!
+//! The bridge-provided [`SECTION_TOOL`] splits `read_wiki_contents` output (served from the
+//! response cache when possible) by heading hierarchy. Without a `section` argument it lists the
+//! numbered sections with token estimates; with one it returns that section and its
+//! subsections. Either way, output longer than a page ends with a `nextCursor` to pass back as
+//! `cursor`, following MCP pagination: the cursor is opaque, and the other arguments must stay
+//! the same.
+
+use crate::project::REPO_ARGUMENT;
+use crate::truncate::{line_floor, sections};
+use anyhow::{anyhow, Result};
+use rmcp::model::{JsonObject, Tool};
+use serde_json::json;
+use std::fmt::Write as _;
+use std::ops::Range;
+
+/// Name of the synthetic tool listed next to the upstream tools
+pub const SECTION_TOOL: &str = "read_wiki_section";
+
+/// Page size when `tools.max_response_bytes` has no entry for [`SECTION_TOOL`]
+pub const DEFAULT_PAGE_BYTES: usize = 20_000;
+
+/// Sections listed per page of the outline
+const OUTLINE_PAGE_ENTRIES: usize = 100;
+
+/// Heading DeepWiki puts before each page of `read_wiki_contents`
+const PAGE_PREFIX: &str = "Page:";
+
+/// A call to the [`SECTION_TOOL`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct SectionRequest {
+    pub repo: String,
+    /// Section number or title; the outline when `None`
+    section: Option<String>,
+    /// Where the previous page ended
+    cursor: usize,
+}
+
+impl SectionRequest {
+    pub fn parse(arguments: &JsonObject) -> Result<Self> {
+        let string = |name: &str| match arguments.get(name) {
+            None => Ok(None),
+            Some(value) => value
+                .as_str()
+                .filter(|value| !value.trim().is_empty())
+                .map(|value| Some(value.trim().to_string()))
+                .ok_or_else(|| anyhow!("'{name}' must be a non-empty string")),
+        };
+        let repo = string(REPO_ARGUMENT)?
+            .ok_or_else(|| anyhow!("'{REPO_ARGUMENT}' is required (owner/repo)"))?;
+        let cursor = match string("cursor")? {
+            None => 0,
+            Some(cursor) => decode_cursor(&cursor)?,
+        };
+        Ok(Self {
+            repo,
+            section: string("section")?,
+            cursor,
+        })
+    }
+}
+
+/// A numbered entry of the outline
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry<'a> {
+    /// Dotted section number, e.g. `2.1`; `0` for text before the first heading
+    number: String,
+    depth: usize,
+    title: &'a str,
+    /// The section's heading and text, followed by its subsections
+    range: Range<usize>,
+}
+
+/// Number the sections of `contents` by heading hierarchy
+///
+/// DeepWiki's `# Page: <title>` headings are treated as one level above the pages' own
+/// headings, so the `# <title>` heading inside a page becomes its first subsection.
+fn outline(contents: &str) -> Vec<Entry<'_>> {
+    let mut entries: Vec<Entry<'_>> = Vec::new();
+    // Level and number of each enclosing section
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut roots = 0;
+    let mut offset = 0;
+    for section in sections(contents) {
+        let start = offset;
+        offset += section.text.len();
+        if section.level == 0 {
+            if !section.text.trim().is_empty() {
+                entries.push(Entry {
+                    number: "0".to_string(),
+                    depth: 1,
+                    title: "Introduction",
+                    range: start..offset,
+                });
+            }
+            continue;
+        }
+        let (level, title) = match section.title.strip_prefix(PAGE_PREFIX) {
+            Some(title) if section.level == 1 => (0, title.trim()),
+            _ => (section.level, section.title),
+        };
+        while stack
+            .last()
+            .is_some_and(|(enclosing, _)| *enclosing > level)
+        {
+            stack.pop();
+        }
+        match stack.last_mut() {
+            Some((sibling, number)) if *sibling == level => *number += 1,
+            Some(_) => stack.push((level, 1)),
+            None => stack.push((level, roots + 1)),
+        }
+        roots = stack[0].1;
+        entries.push(Entry {
+            number: stack
+                .iter()
+                .map(|(_, number)| number.to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+            depth: stack.len(),
+            title,
+            range: start..offset,
+        });
+    }
+
+    // Extend each section over the subsections that follow it
+    for i in 0..entries.len() {
+        let depth = entries[i].depth;
+        let end = entries[i + 1..]
+            .iter()
+            .find(|entry| entry.depth <= depth)
+            .map_or(contents.len(), |entry| entry.range.start);
+        entries[i].range.end = end;
+    }
+    entries
+}
+
+fn tokens(bytes: usize) -> usize {
+    bytes.div_ceil(4)
+}
+
+fn encode_cursor(position: usize) -> String {
+    format!("p{position:x}")
+}
+
+fn decode_cursor(cursor: &str) -> Result<usize> {
+    cursor
+        .strip_prefix('p')
+        .and_then(|position| usize::from_str_radix(position, 16).ok())
+        .ok_or_else(|| anyhow!("Invalid cursor '{cursor}'; pass back a nextCursor unchanged"))
+}
+
+fn push_next_cursor(text: &mut String, position: usize) {
+    let _ = write!(
+        text,
+        "\n\n---\nnextCursor: {}\n(Call {SECTION_TOOL} again with the same arguments and this \
+         cursor for the rest.)",
+        encode_cursor(position)
+    );
+}
+
+/// Answer a [`SectionRequest`] from the repository's `read_wiki_contents` output, in pages of
+/// about `page_bytes`
+pub fn read_section(contents: &str, request: &SectionRequest, page_bytes: usize) -> Result<String> {
+    let entries = outline(contents);
+    match &request.section {
+        None => format_outline(&entries, request, contents.len()),
+        Some(section) => {
+            let wanted = section.strip_prefix(PAGE_PREFIX).unwrap_or(section).trim();
+            let entry = entries
+                .iter()
+                .find(|entry| entry.number == wanted)
+                .or_else(|| {
+                    entries
+                        .iter()
+                        .find(|entry| entry.title.eq_ignore_ascii_case(wanted))
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No section '{section}' in the {} wiki; call {SECTION_TOOL} without \
+                         'section' to list them",
+                        request.repo
+                    )
+                })?;
+            format_page(contents, &entries, entry, request.cursor, page_bytes)
+        }
+    }
+}
+
+fn format_outline(entries: &[Entry<'_>], request: &SectionRequest, total: usize) -> Result<String> {
+    if entries.is_empty() {
+        return Ok(format!("The {} wiki has no contents.", request.repo));
+    }
+    if request.cursor >= entries.len() {
+        return Err(anyhow!("Cursor is past the end of the section list"));
+    }
+    let end = (request.cursor + OUTLINE_PAGE_ENTRIES).min(entries.len());
+    let mut text = format!(
+        "Sections of the {} wiki ({} sections, ~{} tokens in all). Pass a section's number or \
+         title as 'section' to read it with its subsections.\n",
+        request.repo,
+        entries.len(),
+        tokens(total)
+    );
+    for entry in &entries[request.cursor..end] {
+        let _ = write!(
+            text,
+            "\n{}{} {} (~{} tokens)",
+            "  ".repeat(entry.depth - 1),
+            entry.number,
+            entry.title,
+            tokens(entry.range.len())
+        );
+    }
+    if end < entries.len() {
+        push_next_cursor(&mut text, end);
+    }
+    Ok(text)
+}
+
+/// One page of a section, starting `cursor` bytes in and ending at a subsection boundary
+/// where possible
+fn format_page(
+    contents: &str,
+    entries: &[Entry<'_>],
+    entry: &Entry<'_>,
+    cursor: usize,
+    page_bytes: usize,
+) -> Result<String> {
+    let section = &contents[entry.range.clone()];
+    if cursor >= section.len() || !section.is_char_boundary(cursor) {
+        return Err(anyhow!(
+            "Cursor is past the end of section {}",
+            entry.number
+        ));
+    }
+    let rest = &section[cursor..];
+    let length = if rest.len() <= page_bytes.max(1) {
+        rest.len()
+    } else {
+        let limit = cursor + page_bytes.max(1);
+        // The last subsection starting within the page, or else the last line
+        entries
+            .iter()
+            .filter_map(|other| other.range.start.checked_sub(entry.range.start))
+            .filter(|start| *start > cursor && *start <= limit && *start < section.len())
+            .max()
+            .map_or_else(
+                || line_floor(rest, page_bytes.max(1)),
+                |start| start - cursor,
+            )
+    };
+    // A page smaller than the character at the cursor still has to move past it
+    let length = match length {
+        0 => rest.chars().next().map_or(rest.len(), char::len_utf8),
+        length => length,
+    };
+    let end = cursor + length;
+
+    let mut text = if cursor == 0 && end == section.len() {
+        String::new()
+    } else {
+        format!(
+            "[Section {} {}: bytes {}-{} of {}]\n\n",
+            entry.number,
+            entry.title,
+            cursor,
+            end,
+            section.len()
+        )
+    };
+    text.push_str(section[cursor..end].trim_end());
+    if end < section.len() {
+        push_next_cursor(&mut text, end);
+    }
+    Ok(text)
+}
+
+/// Definition of the [`SECTION_TOOL`] advertised in `tools/list`
+pub fn section_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "repoName": {
+                "type": "string",
+                "description": "GitHub repository: owner/repo (e.g. \"facebook/react\")"
+            },
+            "section": {
+                "type": "string",
+                "description": "Section number (e.g. \"2.1\") or title to read; omit to list \
+                                the sections"
+            },
+            "cursor": {
+                "type": "string",
+                "description": "The nextCursor of the previous call, to continue reading"
+            }
+        },
+        "required": ["repoName"]
+    });
+    Tool::new(
+        SECTION_TOOL,
+        "Read a repository's DeepWiki documentation one section at a time. Without 'section', \
+         lists the numbered sections with token estimates. Prefer this to read_wiki_contents \
+         for large wikis.",
+        schema.as_object().cloned().unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIKI: &str = "# Page: Overview\n\n# Overview\nTokio is a runtime.\n\
+        ## Features\nFast.\n\
+        # Page: Scheduler\nWork stealing.\n\
+        ## Queues\nLocal and global.\n\
+        ### Injection\nFrom outside.\n\
+        # Page: I/O Driver\nPolls mio.\n";
+
+    fn request(section: Option<&str>, cursor: Option<&str>) -> SectionRequest {
+        let mut arguments = json!({"repoName": "tokio-rs/tokio"});
+        if let Some(section) = section {
+            arguments["section"] = json!(section);
+        }
+        if let Some(cursor) = cursor {
+            arguments["cursor"] = json!(cursor);
+        }
+        SectionRequest::parse(arguments.as_object().unwrap()).unwrap()
+    }
+
+    /// The `nextCursor` at the end of a page
+    fn next_cursor(text: &str) -> Option<&str> {
+        let (_, rest) = text.split_once("nextCursor: ")?;
+        rest.lines().next()
+    }
+
+    #[test]
+    fn test_numbers_sections_by_heading_hierarchy() {
+        let entries = outline(WIKI);
+        let numbered: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.number.as_str(), entry.title))
+            .collect();
+        assert_eq!(
+            numbered,
+            [
+                ("1", "Overview"),
+                ("1.1", "Overview"),
+                ("1.1.1", "Features"),
+                ("2", "Scheduler"),
+                ("2.1", "Queues"),
+                ("2.1.1", "Injection"),
+                ("3", "I/O Driver")
+            ]
+        );
+        // A section spans its subsections
+        assert_eq!(
+            &WIKI[entries[3].range.clone()],
+            "# Page: Scheduler\nWork stealing.\n## Queues\nLocal and global.\n\
+             ### Injection\nFrom outside.\n"
+        );
+
+        let headings = outline("Preface\n## Deep\n# Top\n");
+        let numbers: Vec<_> = headings.iter().map(|entry| entry.number.as_str()).collect();
+        assert_eq!(numbers, ["0", "1", "2"]);
+    }
+
+    #[test]
+    fn test_lists_sections_with_token_estimates() {
+        let text = read_section(WIKI, &request(None, None), 1000).unwrap();
+        assert!(text.starts_with("Sections of the tokio-rs/tokio wiki (7 sections"));
+        assert!(text.contains("\n2 Scheduler (~"));
+        assert!(text.contains("\n    2.1.1 Injection (~7 tokens)"));
+        assert!(next_cursor(&text).is_none());
+    }
+
+    #[test]
+    fn test_reads_a_section_by_number_or_title() {
+        let text = read_section(WIKI, &request(Some("2.1"), None), 1000).unwrap();
+        assert_eq!(
+            text,
+            "## Queues\nLocal and global.\n### Injection\nFrom outside."
+        );
+        let text = read_section(WIKI, &request(Some("Page: I/O Driver"), None), 1000).unwrap();
+        assert_eq!(text, "# Page: I/O Driver\nPolls mio.");
+
+        let error = read_section(WIKI, &request(Some("9"), None), 1000).unwrap_err();
+        assert!(error.to_string().contains("No section '9'"));
+    }
+
+    #[test]
+    fn test_pages_through_a_section_with_cursors() {
+        let mut cursor = None;
+        let mut pages = Vec::new();
+        loop {
+            let text = read_section(WIKI, &request(Some("2"), cursor.as_deref()), 40).unwrap();
+            cursor = next_cursor(&text).map(str::to_string);
+            pages.push(text);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages.len(), 3);
+        assert!(
+            pages[0].starts_with("[Section 2 Scheduler: bytes 0-33 of 89]\n\n# Page: Scheduler")
+        );
+        // Pages end at subsection boundaries
+        assert!(pages[1].contains("\n\n## Queues\nLocal and global.\n\n---\nnextCursor"));
+        assert!(pages[2].ends_with("### Injection\nFrom outside."));
+
+        let invalid = json!({"repoName": "a/b", "cursor": "later"});
+        assert!(SectionRequest::parse(invalid.as_object().unwrap()).is_err());
+        assert!(read_section(WIKI, &request(Some("2"), Some("pfff")), 40).is_err());
+    }
+
+    #[test]
+    fn test_tiny_pages_make_progress_through_multibyte_text() {
+        let wiki = "# Page: Café\nnaïve ☕ 日本\n";
+        let mut cursor = None;
+        let mut covered = 0;
+        for _ in 0..wiki.len() {
+            let text = read_section(wiki, &request(Some("1"), cursor.as_deref()), 1).unwrap();
+            let (_, bytes) = text.split_once("bytes ").unwrap();
+            let (range, _) = bytes.split_once(" of").unwrap();
+            let (start, end) = range.split_once('-').unwrap();
+            assert_eq!(start.parse::<usize>().unwrap(), covered);
+            covered = end.parse().unwrap();
+            cursor = next_cursor(&text).map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(cursor, None);
+        assert_eq!(covered, wiki.len());
+    }
+}
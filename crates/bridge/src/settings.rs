@@ -0,0 +1,551 @@
+//! Bridge settings passed down from the Zed extension
+//!
+//! The extension forwards the user's `context_servers` settings verbatim as JSON in the
+//! [`SETTINGS_ENV_VAR`] environment variable. Fields the bridge does not know about (such as
+//! `endpoint`, which arrives as a command-line argument) are ignored.
+
+use crate::logging::{LogFormat, LogRotation};
+use anyhow::{anyhow, Result};
+use rmcp::model::JsonObject;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Environment variable carrying the JSON settings object
+pub const SETTINGS_ENV_VAR: &str = "DEEPWIKI_BRIDGE_SETTINGS";
+
+/// Default cache lifetime for the wiki-reading tools, in seconds
+const DEFAULT_WIKI_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Default response size limit for `read_wiki_contents`, in bytes (roughly 25k tokens)
+const DEFAULT_WIKI_CONTENTS_MAX_BYTES: usize = 100_000;
+
+/// The DeepWiki tools that only fetch a wiki and are safe to retry; `ask_question` generates
+/// a new answer on every call, so retrying it is opt-in
+const IDEMPOTENT_TOOLS: [&str; 2] = ["read_wiki_structure", "read_wiki_contents"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BridgeSettings {
+    /// Serve only from the response cache, as with `--offline`
+    pub offline: bool,
+    /// Repository (`owner/repo`) used when a DeepWiki tool call omits `repoName`;
+    /// detected from the project when unset
+    pub repository: Option<String>,
+    pub cache: CacheSettings,
+    pub search: SearchSettings,
+    pub tools: ToolSettings,
+    pub prompts: FilterSettings,
+    pub resources: FilterSettings,
+    /// Servers to aggregate in place of the single endpoint
+    pub upstreams: Vec<UpstreamSettings>,
+    pub routing: RoutingSettings,
+    pub record: RecordSettings,
+    /// Address to serve Prometheus metrics on, as with `--metrics-addr`
+    pub metrics_addr: Option<SocketAddr>,
+    pub log: LogSettings,
+    pub retry: RetrySettings,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub limits: LimitsSettings,
+}
+
+impl BridgeSettings {
+    /// Load settings from [`SETTINGS_ENV_VAR`], falling back to defaults when unset or invalid
+    pub fn from_env() -> Self {
+        match std::env::var(SETTINGS_ENV_VAR) {
+            Ok(raw) => Self::from_json(&raw).unwrap_or_else(|e| {
+                warn!("Ignoring invalid {}: {}", SETTINGS_ENV_VAR, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self> {
+        serde_json::from_str(raw).map_err(|e| anyhow!("Invalid bridge settings: {}", e))
+    }
+}
+
+/// How the response cache is consulted, modelled on HTTP `Cache-Control` directives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    /// Serve fresh entries from the cache and store new responses
+    #[default]
+    Default,
+    /// Always fetch from upstream, but refresh the cache with the response
+    NoCache,
+    /// Neither read from nor write to the cache
+    NoStore,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub mode: CacheMode,
+    /// Cache directory; defaults to the platform cache directory
+    pub dir: Option<PathBuf>,
+    /// Lifetime in seconds per tool name, merged over the defaults.
+    /// Tools without an entry (or with a lifetime of 0) are never cached.
+    #[serde(deserialize_with = "deserialize_ttls")]
+    pub ttl: HashMap<String, u64>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            mode: CacheMode::default(),
+            dir: None,
+            ttl: default_cache_ttls(),
+        }
+    }
+}
+
+/// Local full-text search over fetched wiki pages
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Persist fetched pages and offer the `search_cached_wiki` tool
+    pub enabled: bool,
+    /// Page store directory; defaults to the platform data directory
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+/// Glob lists selecting which tools, prompts or resources are exposed to Zed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    /// Expose only matching items; everything when empty
+    pub allow: Vec<String>,
+    /// Hide matching items, even if they are allowed
+    pub deny: Vec<String>,
+}
+
+/// Which tools are exposed, and under which names and descriptions
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ToolSettings {
+    #[serde(flatten)]
+    pub filter: FilterSettings,
+    /// Prepended to every tool name, e.g. `deepwiki_`
+    pub prefix: String,
+    /// New names by original tool name; the prefix still applies
+    pub rename: HashMap<String, String>,
+    /// Replacement descriptions by original tool name
+    pub descriptions: HashMap<String, String>,
+    /// Arguments filled in when a call leaves them out, by original tool name
+    pub defaults: HashMap<String, JsonObject>,
+    /// Arguments that always override the caller's, by original tool name
+    pub pinned: HashMap<String, JsonObject>,
+    /// Response size limit in bytes by original tool name, merged over the defaults.
+    /// Larger responses are truncated at a Markdown heading; 0 disables the limit.
+    #[serde(deserialize_with = "deserialize_max_response_bytes")]
+    pub max_response_bytes: HashMap<String, usize>,
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            filter: FilterSettings::default(),
+            prefix: String::new(),
+            rename: HashMap::new(),
+            descriptions: HashMap::new(),
+            defaults: HashMap::new(),
+            pinned: HashMap::new(),
+            max_response_bytes: default_max_response_bytes(),
+        }
+    }
+}
+
+/// One of several MCP servers fronted by the bridge
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpstreamSettings {
+    /// Namespace for the server's tools and prompts, e.g. `devin`
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub transport: TransportKind,
+    #[serde(default)]
+    pub auth: AuthMode,
+    /// Extra HTTP headers sent with every request, e.g. `Authorization`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+impl UpstreamSettings {
+    /// The upstream for a bare endpoint URL, with everything detected from the URL
+    pub fn from_url(url: &str) -> Self {
+        Self {
+            name: "default".to_string(),
+            url: url.to_string(),
+            transport: TransportKind::Auto,
+            auth: AuthMode::Auto,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportKind {
+    /// SSE for URLs containing `/sse`, streamable HTTP otherwise
+    #[default]
+    Auto,
+    Http,
+    Sse,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMode {
+    /// OAuth2 for Devin endpoints, none otherwise
+    #[default]
+    Auto,
+    #[serde(rename = "oauth")]
+    OAuth,
+    None,
+}
+
+/// Choice of upstream for DeepWiki tool calls, by their `repoName`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RoutingSettings {
+    /// Rules tried in order; the first whose glob matches the repository wins
+    pub rules: Vec<RouteRule>,
+    /// Private repositories (globs), checked before the rules
+    pub private: Vec<String>,
+    /// Upstream for private repositories
+    pub private_upstream: Option<String>,
+    /// Upstream for repositories nothing matches; the first upstream when unset
+    pub default: Option<String>,
+    /// Upstream to retry when the chosen one has not indexed the repository
+    pub fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteRule {
+    /// Glob over `owner/repo`, e.g. `our-org/*`
+    pub repo: String,
+    pub upstream: String,
+}
+
+/// Traffic recording for debugging, as with `--record`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RecordSettings {
+    /// JSONL file to append every JSON-RPC message to; `--record` takes precedence
+    pub file: Option<PathBuf>,
+    /// Extra field names whose values are redacted, in addition to the built-in secrets
+    pub redact: Vec<String>,
+}
+
+/// Log output; the command-line options take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    /// Log filter such as `debug` or `info,rmcp=warn`; `RUST_LOG` takes precedence
+    pub level: Option<String>,
+    /// `json`, `pretty`, `compact` or `full`, as with `--log-format`
+    pub format: Option<LogFormat>,
+    /// File to write logs to instead of stderr, as with `--log-file`
+    pub file: Option<PathBuf>,
+    /// `daily` (the default), `never`, or a size such as `10MB`
+    pub rotation: LogRotation,
+    /// Rotated files to keep
+    pub max_files: Option<usize>,
+}
+
+/// Retries of transient upstream failures
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// Attempts per request, including the first; 1 disables retries
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled for each further one
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the backoff
+    pub max_backoff_ms: u64,
+    /// Whether each tool is idempotent and may be retried, merged over the defaults.
+    /// Tools without an entry are not retried.
+    #[serde(
+        alias = "idempotent_tools",
+        deserialize_with = "deserialize_idempotent_tools"
+    )]
+    pub tools: HashMap<String, bool>,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 5_000,
+            tools: default_idempotent_tools(),
+        }
+    }
+}
+
+/// Failing fast while an upstream is down
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerSettings {
+    /// Consecutive failures that open an upstream's circuit; 0 disables the breaker
+    pub failure_threshold: u32,
+    /// Seconds an open circuit waits before probing the upstream again
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_secs: 30,
+        }
+    }
+}
+
+/// Client-side rate limits and concurrency caps
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsSettings {
+    /// Requests that may wait on each limit before further ones are refused
+    pub max_queued: usize,
+    /// Limits per upstream name; `*` applies to upstreams without their own entry
+    pub upstreams: HashMap<String, LimitSettings>,
+    /// Limits per tool name, applied on each upstream separately
+    pub tools: HashMap<String, LimitSettings>,
+}
+
+impl Default for LimitsSettings {
+    fn default() -> Self {
+        Self {
+            max_queued: 16,
+            upstreams: HashMap::new(),
+            tools: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LimitSettings {
+    /// Sustained request rate; unlimited when unset
+    pub requests_per_second: Option<f64>,
+    /// Requests allowed at once after a quiet period; defaults to one second's worth
+    pub burst: Option<u32>,
+    /// Requests awaiting a response at any time; unlimited when unset
+    pub max_in_flight: Option<usize>,
+}
+
+/// Only the idempotent wiki-reading tools are cached unless the user opts in
+fn default_cache_ttls() -> HashMap<String, u64> {
+    HashMap::from([
+        ("read_wiki_structure".to_string(), DEFAULT_WIKI_TTL_SECS),
+        ("read_wiki_contents".to_string(), DEFAULT_WIKI_TTL_SECS),
+    ])
+}
+
+fn deserialize_ttls<'de, D>(deserializer: D) -> Result<HashMap<String, u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut ttls = default_cache_ttls();
+    ttls.extend(HashMap::<String, u64>::deserialize(deserializer)?);
+    Ok(ttls)
+}
+
+fn default_max_response_bytes() -> HashMap<String, usize> {
+    HashMap::from([(
+        "read_wiki_contents".to_string(),
+        DEFAULT_WIKI_CONTENTS_MAX_BYTES,
+    )])
+}
+
+fn deserialize_max_response_bytes<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut limits = default_max_response_bytes();
+    limits.extend(HashMap::<String, usize>::deserialize(deserializer)?);
+    Ok(limits)
+}
+
+fn default_idempotent_tools() -> HashMap<String, bool> {
+    IDEMPOTENT_TOOLS
+        .iter()
+        .map(|tool| (tool.to_string(), true))
+        .collect()
+}
+
+fn deserialize_idempotent_tools<'de, D>(deserializer: D) -> Result<HashMap<String, bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut tools = default_idempotent_tools();
+    tools.extend(HashMap::<String, bool>::deserialize(deserializer)?);
+    Ok(tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_cache_wiki_tools_only() {
+        let settings = BridgeSettings::from_json("{}").unwrap();
+        assert_eq!(settings.cache.mode, CacheMode::Default);
+        assert!(!settings.offline);
+        assert!(settings.search.enabled);
+        assert!(settings.cache.ttl.contains_key("read_wiki_structure"));
+        assert!(settings.cache.ttl.contains_key("read_wiki_contents"));
+        assert!(!settings.cache.ttl.contains_key("ask_question"));
+    }
+
+    #[test]
+    fn test_extension_settings_are_accepted() {
+        let settings = BridgeSettings::from_json(
+            r#"{
+                "endpoint": "https://mcp.deepwiki.com",
+                "cache": {"mode": "no-cache", "ttl": {"ask_question": 600}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.cache.mode, CacheMode::NoCache);
+        assert_eq!(settings.cache.ttl.get("ask_question"), Some(&600));
+        // User lifetimes are merged over the defaults
+        assert!(settings.cache.ttl.contains_key("read_wiki_contents"));
+    }
+
+    #[test]
+    fn test_filter_lists() {
+        let settings = BridgeSettings::from_json(
+            r#"{"tools": {"deny": ["ask_question"]}, "resources": {"allow": ["deepwiki://*"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.tools.filter.deny, ["ask_question"]);
+        assert!(settings.tools.filter.allow.is_empty());
+        assert!(settings.tools.prefix.is_empty());
+        assert_eq!(settings.resources.allow, ["deepwiki://*"]);
+        assert!(settings.prompts.allow.is_empty() && settings.prompts.deny.is_empty());
+    }
+
+    #[test]
+    fn test_upstreams() {
+        let settings = BridgeSettings::from_json(
+            r#"{"upstreams": [
+                {"name": "deepwiki", "url": "https://mcp.deepwiki.com/mcp"},
+                {"name": "docs", "url": "https://docs.internal/sse", "transport": "sse",
+                 "auth": "none", "headers": {"Authorization": "Bearer t"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.upstreams.len(), 2);
+        assert_eq!(settings.upstreams[0].transport, TransportKind::Auto);
+        assert_eq!(settings.upstreams[0].auth, AuthMode::Auto);
+        assert_eq!(settings.upstreams[1].transport, TransportKind::Sse);
+        assert_eq!(settings.upstreams[1].auth, AuthMode::None);
+        assert_eq!(settings.upstreams[1].headers["Authorization"], "Bearer t");
+        assert!(BridgeSettings::from_json(r#"{"upstreams": [{"name": "x"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_log_settings() {
+        let settings = BridgeSettings::from_json(
+            r#"{"log": {"level": "debug", "format": "json", "file": "/tmp/bridge.log",
+                "rotation": "5MB", "max_files": 3}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.log.level.as_deref(), Some("debug"));
+        assert_eq!(settings.log.format, Some(LogFormat::Json));
+        assert_eq!(settings.log.rotation, LogRotation::Size(5 * 1024 * 1024));
+        assert_eq!(settings.log.max_files, Some(3));
+        assert_eq!(BridgeSettings::default().log.rotation, LogRotation::Daily);
+        assert!(BridgeSettings::from_json(r#"{"log": {"format": "xml"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_retry_settings() {
+        let settings = BridgeSettings::from_json(
+            r#"{"retry": {"max_attempts": 5, "tools": {"ask_question": false, "lookup": true}}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.retry.max_attempts, 5);
+        assert_eq!(settings.retry.initial_backoff_ms, 250);
+        assert_eq!(settings.retry.tools.get("ask_question"), Some(&false));
+        assert_eq!(settings.retry.tools.get("lookup"), Some(&true));
+        // Classifications are merged over the defaults
+        assert_eq!(settings.retry.tools.get("read_wiki_contents"), Some(&true));
+
+        // Generated answers are only retried when opted in
+        assert_eq!(
+            BridgeSettings::default().retry.tools.get("ask_question"),
+            None
+        );
+        let settings =
+            BridgeSettings::from_json(r#"{"retry": {"idempotent_tools": {"ask_question": true}}}"#)
+                .unwrap();
+        assert_eq!(settings.retry.tools.get("ask_question"), Some(&true));
+        assert_eq!(settings.retry.tools.get("read_wiki_structure"), Some(&true));
+    }
+
+    #[test]
+    fn test_circuit_breaker_settings() {
+        let settings =
+            BridgeSettings::from_json(r#"{"circuit_breaker": {"failure_threshold": 0}}"#).unwrap();
+        assert_eq!(settings.circuit_breaker.failure_threshold, 0);
+        assert_eq!(settings.circuit_breaker.open_secs, 30);
+    }
+
+    #[test]
+    fn test_limits_settings() {
+        let settings = BridgeSettings::from_json(
+            r#"{"limits": {"upstreams": {"*": {"requests_per_second": 2.5, "burst": 5}},
+                "tools": {"read_wiki_contents": {"max_in_flight": 2}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.limits.max_queued, 16);
+        let any = &settings.limits.upstreams["*"];
+        assert_eq!(any.requests_per_second, Some(2.5));
+        assert_eq!(any.burst, Some(5));
+        assert_eq!(any.max_in_flight, None);
+        assert_eq!(
+            settings.limits.tools["read_wiki_contents"].max_in_flight,
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_max_response_bytes() {
+        let defaults = BridgeSettings::from_json("{}").unwrap();
+        assert_eq!(
+            defaults.tools.max_response_bytes["read_wiki_contents"],
+            DEFAULT_WIKI_CONTENTS_MAX_BYTES
+        );
+        let settings = BridgeSettings::from_json(
+            r#"{"tools": {"max_response_bytes": {"ask_question": 20000, "read_wiki_contents": 0}}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.tools.max_response_bytes["ask_question"], 20_000);
+        assert_eq!(settings.tools.max_response_bytes["read_wiki_contents"], 0);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(BridgeSettings::from_json(r#"{"cache": {"mode": "sometimes"}}"#).is_err());
+        assert!(BridgeSettings::from_json("not json").is_err());
+        assert!(BridgeSettings::from_json(r#"{"tools": {"deny": "ask_question"}}"#).is_err());
+    }
+}
@@ -0,0 +1,503 @@
+//! OpenTelemetry trace export over OTLP with W3C trace context
+//!
+//! With the `otel` cargo feature, and an OTLP endpoint configured through the standard `OTEL_*`
+//! environment variables, the bridge's own `tracing` spans are exported through
+//! `tracing-opentelemetry` and `opentelemetry-otlp` (a local collector listens on
+//! `http://localhost:4318`, or `4317` for gRPC). Every forwarded request gets a client span
+//! whose `traceparent` is added to the upstream request's `_meta` and HTTP headers, and a
+//! `traceparent` in a client's `_meta` becomes the remote parent of the bridge's spans, so one
+//! assistant query can be followed end to end.
+//!
+//! Recognised variables: `OTEL_SDK_DISABLED`, `OTEL_TRACES_EXPORTER` (`otlp` or `none`),
+//! `OTEL_EXPORTER_OTLP[_TRACES]_ENDPOINT`, `OTEL_EXPORTER_OTLP[_TRACES]_HEADERS`,
+//! `OTEL_EXPORTER_OTLP[_TRACES]_TIMEOUT`, `OTEL_EXPORTER_OTLP[_TRACES]_PROTOCOL` (`grpc`,
+//! `http/protobuf` or `http/json`), `OTEL_SERVICE_NAME` and `OTEL_RESOURCE_ATTRIBUTES`.
+
+use crate::metrics;
+use anyhow::{bail, Result};
+use rmcp::model::{ClientNotification, ClientRequest, ErrorData, ServerInfo, ServerResult};
+use rmcp::service::{NotificationContext, RequestContext, Service};
+use rmcp::RoleServer;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{info_span, Instrument, Span};
+
+#[cfg(feature = "otel")]
+use opentelemetry::propagation::TextMapPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::SdkTracerProvider;
+#[cfg(feature = "otel")]
+use std::collections::HashMap;
+#[cfg(feature = "otel")]
+use tracing::Subscriber;
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+#[cfg(feature = "otel")]
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// Name of the `_meta` entry and HTTP header carrying the trace context
+pub const TRACEPARENT: &str = "traceparent";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `traceparent` of the current span, when traces are being exported
+#[cfg(feature = "otel")]
+pub fn current_traceparent() -> Option<String> {
+    let context = Span::current().context();
+    if !context.span().span_context().is_valid() {
+        return None;
+    }
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&context, &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// The `traceparent` of the current span, when traces are being exported
+#[cfg(not(feature = "otel"))]
+pub fn current_traceparent() -> Option<String> {
+    None
+}
+
+/// Make the span a `traceparent` names the parent of `span`
+#[cfg(feature = "otel")]
+fn set_remote_parent(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+    if context.span().span_context().is_valid() {
+        // Fails only when spans aren't exported
+        let _ = span.set_parent(context);
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn set_remote_parent(_span: &Span, _traceparent: &str) {}
+
+/// Mark a span as failed, both in the logs and in the exported trace
+///
+/// The span must declare `error` and `otel.status_description` fields.
+pub fn record_error(span: &Span, message: &str) {
+    span.record("error", message);
+    span.record("otel.status_description", message);
+}
+
+/// Server wrapper giving every request from the client a span, parented by the `traceparent`
+/// in the request's `_meta` when there is one
+pub struct TracedServer<S>(pub S);
+
+impl<S: Service<RoleServer>> Service<RoleServer> for TracedServer<S> {
+    async fn handle_request(
+        &self,
+        request: ClientRequest,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ServerResult, ErrorData> {
+        let (method, tool) = metrics::describe(&request);
+        let span = info_span!(
+            "mcp.request",
+            otel.name = format!("{method} {tool}").trim_end(),
+            otel.kind = "server",
+            rpc.method = method,
+            mcp.tool = %tool,
+            traceparent = tracing::field::Empty,
+            error = tracing::field::Empty,
+            otel.status_description = tracing::field::Empty,
+        );
+        // rmcp moves the request's `_meta` into the context
+        let traceparent = context.meta.0.get(TRACEPARENT);
+        if let Some(traceparent) = traceparent.and_then(Value::as_str) {
+            span.record(TRACEPARENT, traceparent);
+            set_remote_parent(&span, traceparent);
+        }
+        let response = self
+            .0
+            .handle_request(request, context)
+            .instrument(span.clone())
+            .await;
+        if let Err(e) = &response {
+            record_error(&span, e.message.as_ref());
+        }
+        response
+    }
+
+    async fn handle_notification(
+        &self,
+        notification: ClientNotification,
+        context: NotificationContext<RoleServer>,
+    ) -> Result<(), ErrorData> {
+        self.0.handle_notification(notification, context).await
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        self.0.get_info()
+    }
+}
+
+/// OTLP transport, from `OTEL_EXPORTER_OTLP[_TRACES]_PROTOCOL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+    HttpJson,
+}
+
+impl OtlpProtocol {
+    fn parse(protocol: &str) -> Option<Self> {
+        match protocol.trim() {
+            "grpc" => Some(Self::Grpc),
+            "http/protobuf" => Some(Self::HttpProtobuf),
+            "http/json" => Some(Self::HttpJson),
+            _ => None,
+        }
+    }
+
+    /// Traces endpoint for a base `OTEL_EXPORTER_OTLP_ENDPOINT`; only HTTP has a signal path
+    fn traces_endpoint(self, base: &str) -> String {
+        match self {
+            Self::Grpc => base.to_string(),
+            Self::HttpProtobuf | Self::HttpJson => {
+                format!("{}/v1/traces", base.trim_end_matches('/'))
+            }
+        }
+    }
+
+    /// Where a local collector listens for this protocol
+    fn default_base(self) -> &'static str {
+        match self {
+            Self::Grpc => "http://localhost:4317",
+            Self::HttpProtobuf | Self::HttpJson => "http://localhost:4318",
+        }
+    }
+}
+
+/// Exporter settings read from the `OTEL_*` environment variables
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(not(feature = "otel"), allow(dead_code))]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub resource: Vec<(String, String)>,
+}
+
+impl OtlpConfig {
+    /// `None` unless an OTLP traces exporter is configured, or an error when it can't be used
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        // Signal-specific variables take precedence over the general ones
+        let otlp = |suffix: &str| {
+            var(&format!("OTEL_EXPORTER_OTLP_TRACES_{suffix}"))
+                .or_else(|| var(&format!("OTEL_EXPORTER_OTLP_{suffix}")))
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        if var("OTEL_SDK_DISABLED").is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+            return Ok(None);
+        }
+        let exporter = var("OTEL_TRACES_EXPORTER");
+        if exporter.as_deref().is_some_and(|e| e.trim() == "none") {
+            return Ok(None);
+        }
+        let requested = exporter.as_deref().is_some_and(|e| e.trim() == "otlp");
+        let protocol = match otlp("PROTOCOL") {
+            None => OtlpProtocol::HttpProtobuf,
+            Some(protocol) => match OtlpProtocol::parse(&protocol) {
+                Some(protocol) => protocol,
+                None => bail!(
+                    "OTLP protocol {protocol} is not supported; use grpc, http/protobuf or \
+                     http/json"
+                ),
+            },
+        };
+        let endpoint = match var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").filter(|e| !e.is_empty()) {
+            Some(endpoint) => endpoint,
+            None => match var("OTEL_EXPORTER_OTLP_ENDPOINT").filter(|e| !e.is_empty()) {
+                Some(base) => protocol.traces_endpoint(&base),
+                None if requested => protocol.traces_endpoint(protocol.default_base()),
+                None => return Ok(None),
+            },
+        };
+
+        let timeout = otlp("TIMEOUT")
+            .and_then(|ms| ms.trim().parse().ok())
+            .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+
+        let mut resource = otlp_list(var("OTEL_RESOURCE_ATTRIBUTES").as_deref());
+        let service_name = var("OTEL_SERVICE_NAME")
+            .or_else(|| {
+                resource
+                    .iter()
+                    .find(|(key, _)| key == "service.name")
+                    .map(|(_, value)| value.clone())
+            })
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        resource.retain(|(key, _)| key != "service.name");
+        resource.insert(0, ("service.name".to_string(), service_name));
+
+        Ok(Some(Self {
+            endpoint,
+            protocol,
+            headers: otlp_list(otlp("HEADERS").as_deref()),
+            timeout,
+            resource,
+        }))
+    }
+}
+
+/// Parse a `key=value,key=value` list
+fn otlp_list(raw: Option<&str>) -> Vec<(String, String)> {
+    raw.unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// Flushes spans still queued for export
+#[cfg_attr(not(feature = "otel"), allow(dead_code))]
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: SdkTracerProvider,
+}
+
+impl TelemetryGuard {
+    pub async fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        {
+            // Flushing blocks on the export
+            let provider = self.provider;
+            let _ = tokio::task::spawn_blocking(move || provider.shutdown()).await;
+        }
+    }
+}
+
+/// Build the export layer; needs a Tokio runtime for gRPC
+#[cfg(feature = "otel")]
+pub fn otlp_layer<S>(config: OtlpConfig) -> Result<(impl Layer<S>, TelemetryGuard)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    use http::header::{HeaderMap, HeaderName, HeaderValue};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+    use opentelemetry_otlp::{
+        Protocol, SpanExporter, WithExportConfig, WithHttpConfig, WithTonicConfig,
+    };
+    use opentelemetry_sdk::Resource;
+
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &config.headers {
+                headers.insert(
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                );
+            }
+            SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(&config.endpoint)
+                .with_timeout(config.timeout)
+                .with_metadata(MetadataMap::from_headers(headers))
+                .build()?
+        }
+        OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => SpanExporter::builder()
+            .with_http()
+            .with_protocol(if config.protocol == OtlpProtocol::HttpJson {
+                Protocol::HttpJson
+            } else {
+                Protocol::HttpBinary
+            })
+            .with_endpoint(&config.endpoint)
+            .with_timeout(config.timeout)
+            .with_headers(config.headers.iter().cloned().collect())
+            .build()?,
+    };
+    let resource = Resource::builder_empty()
+        .with_attributes(
+            config
+                .resource
+                .iter()
+                .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+        )
+        .build();
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    Ok((layer, TelemetryGuard { provider }))
+}
+
+/// Build the export layer; this build can't export
+#[cfg(not(feature = "otel"))]
+pub fn otlp_layer(
+    _config: OtlpConfig,
+) -> Result<(tracing_subscriber::layer::Identity, TelemetryGuard)> {
+    bail!("the bridge was built without the otel feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Option<OtlpConfig>> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        OtlpConfig::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        assert!(config(&[]).unwrap().is_none());
+        assert!(config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("OTEL_SDK_DISABLED", "true")
+        ])
+        .unwrap()
+        .is_none());
+
+        let default = config(&[("OTEL_TRACES_EXPORTER", "otlp")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(default.endpoint, "http://localhost:4318/v1/traces");
+        assert_eq!(default.protocol, OtlpProtocol::HttpProtobuf);
+
+        let config = config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318/"),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "x-token=abc, x-team=docs"),
+            ("OTEL_EXPORTER_OTLP_TRACES_TIMEOUT", "2500"),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "http/json"),
+            (
+                "OTEL_RESOURCE_ATTRIBUTES",
+                "service.name=bridge,host.name=vm1",
+            ),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.endpoint, "http://collector:4318/v1/traces");
+        assert_eq!(config.protocol, OtlpProtocol::HttpJson);
+        assert_eq!(
+            config.headers[1],
+            ("x-team".to_string(), "docs".to_string())
+        );
+        assert_eq!(config.timeout, Duration::from_millis(2500));
+        assert_eq!(
+            config.resource,
+            [
+                ("service.name".to_string(), "bridge".to_string()),
+                ("host.name".to_string(), "vm1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_protocols() {
+        let grpc = config(&[
+            ("OTEL_TRACES_EXPORTER", "otlp"),
+            ("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL", "grpc"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(grpc.protocol, OtlpProtocol::Grpc);
+        assert_eq!(grpc.endpoint, "http://localhost:4317");
+
+        let grpc = config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "grpc"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(grpc.endpoint, "http://collector:4317");
+
+        // Refused rather than sending a format the collector didn't ask for
+        let error = config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "thrift"),
+        ])
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("protocol thrift is not supported"));
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_spans_link_to_remote_and_local_parents() {
+        use opentelemetry::trace::{SpanKind, Status};
+        use opentelemetry_sdk::error::OTelSdkResult;
+        use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        #[derive(Debug, Default, Clone)]
+        struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+        impl SpanExporter for Collected {
+            async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+                self.0.lock().unwrap().extend(batch);
+                Ok(())
+            }
+        }
+
+        let collected = Collected::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(collected.clone())
+            .build();
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("test"));
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let remote = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        let injected = tracing::subscriber::with_default(subscriber, || {
+            let call = info_span!("call");
+            set_remote_parent(&call, remote);
+            let _call = call.enter();
+            let forward = info_span!(
+                "forward",
+                otel.kind = "client",
+                error = tracing::field::Empty,
+                otel.status_description = tracing::field::Empty,
+            );
+            record_error(&forward, "timeout");
+            let _forward = forward.enter();
+            current_traceparent()
+        });
+
+        let spans = collected.0.lock().unwrap();
+        let [forward, call] = &spans[..] else {
+            panic!("expected two spans, got {}", spans.len());
+        };
+        assert_eq!(
+            call.span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(call.parent_span_id.to_string(), "00f067aa0ba902b7");
+        assert_eq!(
+            forward.span_context.trace_id(),
+            call.span_context.trace_id()
+        );
+        assert_eq!(forward.parent_span_id, call.span_context.span_id());
+        assert_eq!(forward.span_kind, SpanKind::Client);
+        assert_eq!(forward.status, Status::error("timeout"));
+
+        let injected = injected.unwrap();
+        assert_eq!(
+            injected,
+            format!(
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01",
+                forward.span_context.span_id()
+            )
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! HTTP client for the remote transports that propagates trace context
+//!
+//! Behaves like rmcp's `reqwest::Client` transports, except that a request whose `_meta`
+//! carries a `traceparent` (added by the proxy for every forwarded request) also sends it as
+//! the W3C `traceparent` HTTP header, so upstreams that only read headers join the trace.
+//!
+//! The status and `Retry-After` of a request's failed POST are noted in [`HttpFailures`], since
+//! rmcp reports it to the proxy as a bare transport error.
+
+use crate::retry::HttpFailures;
+use crate::telemetry::TRACEPARENT;
+use futures::{stream::BoxStream, StreamExt};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER};
+use rmcp::model::{ClientJsonRpcMessage, GetMeta, JsonRpcMessage, ServerJsonRpcMessage};
+use rmcp::transport::common::client_side_sse::BoxedSseResponse;
+use rmcp::transport::common::http_header::{
+    EVENT_STREAM_MIME_TYPE, HEADER_SESSION_ID, JSON_MIME_TYPE,
+};
+use rmcp::transport::sse_client::{SseClient, SseTransportError};
+use rmcp::transport::streamable_http_client::{
+    SseError, StreamableHttpClient, StreamableHttpError, StreamableHttpPostResponse,
+};
+use sse_stream::{Sse, SseStream};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+pub struct TracedClient {
+    client: reqwest::Client,
+    failures: Arc<HttpFailures>,
+}
+
+impl TracedClient {
+    pub fn new(client: reqwest::Client, failures: Arc<HttpFailures>) -> Self {
+        Self { client, failures }
+    }
+
+    /// Send a POST of `message`, noting the status of a failed request
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        message: &ClientJsonRpcMessage,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let response = request.json(message).send().await?;
+        if let (JsonRpcMessage::Request(request), false) = (message, response.status().is_success())
+        {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok());
+            self.failures
+                .record(request.id.clone(), response.status().as_u16(), retry_after);
+        }
+        response.error_for_status()
+    }
+}
+
+/// The `traceparent` a client message carries in its `_meta`
+fn traceparent(message: &ClientJsonRpcMessage) -> Option<&str> {
+    match message {
+        JsonRpcMessage::Request(request) => request.request.get_meta().0.get(TRACEPARENT)?.as_str(),
+        _ => None,
+    }
+}
+
+/// A POST of `message` with its trace context header
+fn post(
+    client: &reqwest::Client,
+    uri: &str,
+    message: &ClientJsonRpcMessage,
+) -> reqwest::RequestBuilder {
+    let request = client.post(uri);
+    match traceparent(message) {
+        Some(traceparent) => request.header(TRACEPARENT, traceparent),
+        None => request,
+    }
+}
+
+impl StreamableHttpClient for TracedClient {
+    type Error = reqwest::Error;
+
+    async fn get_stream(
+        &self,
+        uri: Arc<str>,
+        session_id: Arc<str>,
+        last_event_id: Option<String>,
+        auth_token: Option<String>,
+    ) -> Result<BoxStream<'static, Result<Sse, SseError>>, StreamableHttpError<Self::Error>> {
+        StreamableHttpClient::get_stream(&self.client, uri, session_id, last_event_id, auth_token)
+            .await
+    }
+
+    async fn delete_session(
+        &self,
+        uri: Arc<str>,
+        session: Arc<str>,
+        auth_token: Option<String>,
+    ) -> Result<(), StreamableHttpError<Self::Error>> {
+        self.client.delete_session(uri, session, auth_token).await
+    }
+
+    async fn post_message(
+        &self,
+        uri: Arc<str>,
+        message: ClientJsonRpcMessage,
+        session_id: Option<Arc<str>>,
+        auth_token: Option<String>,
+    ) -> Result<StreamableHttpPostResponse, StreamableHttpError<Self::Error>> {
+        let mut request = post(&self.client, &uri, &message)
+            .header(ACCEPT, [EVENT_STREAM_MIME_TYPE, JSON_MIME_TYPE].join(", "));
+        if let Some(auth_token) = auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+        if let Some(session_id) = session_id {
+            request = request.header(HEADER_SESSION_ID, session_id.as_ref());
+        }
+        let response = self.send(request, &message).await?;
+        if response.status() == reqwest::StatusCode::ACCEPTED {
+            return Ok(StreamableHttpPostResponse::Accepted);
+        }
+        let session_id = response
+            .headers()
+            .get(HEADER_SESSION_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let content_type = response.headers().get(CONTENT_TYPE).cloned();
+        match content_type {
+            Some(ct) if ct.as_bytes().starts_with(EVENT_STREAM_MIME_TYPE.as_bytes()) => {
+                let stream = SseStream::from_byte_stream(response.bytes_stream()).boxed();
+                Ok(StreamableHttpPostResponse::Sse(stream, session_id))
+            }
+            Some(ct) if ct.as_bytes().starts_with(JSON_MIME_TYPE.as_bytes()) => {
+                let message: ServerJsonRpcMessage = response.json().await?;
+                Ok(StreamableHttpPostResponse::Json(message, session_id))
+            }
+            content_type => Err(StreamableHttpError::UnexpectedContentType(
+                content_type.map(|ct| String::from_utf8_lossy(ct.as_bytes()).to_string()),
+            )),
+        }
+    }
+}
+
+impl SseClient for TracedClient {
+    type Error = reqwest::Error;
+
+    async fn post_message(
+        &self,
+        uri: http::Uri,
+        message: ClientJsonRpcMessage,
+        auth_token: Option<String>,
+    ) -> Result<(), SseTransportError<Self::Error>> {
+        let mut request = post(&self.client, &uri.to_string(), &message);
+        if let Some(auth_token) = auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+        self.send(request, &message)
+            .await
+            .map_err(SseTransportError::from)
+            .map(drop)
+    }
+
+    async fn get_stream(
+        &self,
+        uri: http::Uri,
+        last_event_id: Option<String>,
+        auth_token: Option<String>,
+    ) -> Result<BoxedSseResponse, SseTransportError<Self::Error>> {
+        SseClient::get_stream(&self.client, uri, last_event_id, auth_token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reads_traceparent_from_request_meta() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let request: ClientJsonRpcMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "ask_question", "_meta": {"traceparent": traceparent}}
+        }))
+        .unwrap();
+        assert_eq!(super::traceparent(&request), Some(traceparent));
+
+        let notification: ClientJsonRpcMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0", "method": "notifications/initialized"
+        }))
+        .unwrap();
+        assert_eq!(super::traceparent(&notification), None);
+    }
+}
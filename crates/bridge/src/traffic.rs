@@ -0,0 +1,140 @@
+//! Observation hooks for raw JSON-RPC traffic
+//!
+//! [`ObservedTransport`] wraps any rmcp transport and reports every message it sends or
+//! receives to a [`TrafficObserver`], without altering the messages themselves.
+
+use rmcp::{
+    service::{RxJsonRpcMessage, ServiceRole, TxJsonRpcMessage},
+    transport::Transport,
+};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Direction of a message relative to the wrapped transport's owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the bridge over this transport
+    Outbound,
+    /// Received by the bridge from this transport
+    Inbound,
+}
+
+/// Receives a copy of every JSON-RPC message passing through an [`ObservedTransport`]
+pub trait TrafficObserver: Send + Sync + 'static {
+    fn observe(&self, direction: Direction, message: &Value);
+}
+
+/// Transport wrapper that reports traffic to an optional observer
+pub struct ObservedTransport<T> {
+    inner: T,
+    observer: Option<Arc<dyn TrafficObserver>>,
+}
+
+impl<T> ObservedTransport<T> {
+    pub fn new(inner: T, observer: Option<Arc<dyn TrafficObserver>>) -> Self {
+        Self { inner, observer }
+    }
+}
+
+fn notify<M: serde::Serialize>(
+    observer: Option<&Arc<dyn TrafficObserver>>,
+    direction: Direction,
+    message: &M,
+) {
+    if let Some(observer) = observer {
+        if let Ok(value) = serde_json::to_value(message) {
+            observer.observe(direction, &value);
+        }
+    }
+}
+
+impl<R, T> Transport<R> for ObservedTransport<T>
+where
+    R: ServiceRole,
+    T: Transport<R>,
+{
+    type Error = T::Error;
+
+    fn send(
+        &mut self,
+        item: TxJsonRpcMessage<R>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        notify(self.observer.as_ref(), Direction::Outbound, &item);
+        self.inner.send(item)
+    }
+
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<R>> {
+        let message = self.inner.receive().await;
+        if let Some(message) = &message {
+            notify(self.observer.as_ref(), Direction::Inbound, message);
+        }
+        message
+    }
+
+    fn close(&mut self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{ClientJsonRpcMessage, ClientRequest, PingRequest, RequestId};
+    use rmcp::RoleClient;
+    use std::sync::Mutex;
+
+    /// Transport that swallows sends and never receives anything
+    struct NullTransport;
+
+    impl Transport<RoleClient> for NullTransport {
+        type Error = std::io::Error;
+
+        fn send(
+            &mut self,
+            _item: TxJsonRpcMessage<RoleClient>,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+            std::future::ready(Ok(()))
+        }
+
+        fn receive(&mut self) -> impl Future<Output = Option<RxJsonRpcMessage<RoleClient>>> {
+            std::future::ready(None)
+        }
+
+        fn close(&mut self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+            std::future::ready(Ok(()))
+        }
+    }
+
+    #[derive(Default)]
+    struct Collector(Mutex<Vec<(Direction, Value)>>);
+
+    impl TrafficObserver for Collector {
+        fn observe(&self, direction: Direction, message: &Value) {
+            self.0.lock().unwrap().push((direction, message.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observed_transport_reports_outbound_messages() {
+        let collector = Arc::new(Collector::default());
+        let mut transport = ObservedTransport::new(NullTransport, Some(collector.clone()));
+
+        let ping = ClientJsonRpcMessage::request(
+            ClientRequest::PingRequest(PingRequest::default()),
+            RequestId::Number(7),
+        );
+        Transport::<RoleClient>::send(&mut transport, ping)
+            .await
+            .unwrap();
+        assert!(Transport::<RoleClient>::receive(&mut transport)
+            .await
+            .is_none());
+
+        let seen = collector.0.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].0, Direction::Outbound);
+        assert_eq!(seen[0].1["method"], "ping");
+        assert_eq!(seen[0].1["id"], 7);
+    }
+}
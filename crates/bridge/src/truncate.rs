@@ -0,0 +1,288 @@
+//! Truncating oversized tool responses at Markdown headings
+//!
+//! `read_wiki_contents` returns a repository's whole wiki in one response, which can be hundreds
+//! of kilobytes. Responses over a tool's `tools.max_response_bytes` are cut after the last
+//! whole section that fits, and a note lists the sections left out. Only what is sent to Zed
+//! shrinks: the response cache and search index keep the full text, from which
+//! `read_wiki_section` serves the omitted sections.
+
+use crate::mirror::result_text;
+use rmcp::model::{CallToolResult, Content};
+use std::fmt::Write as _;
+
+/// Omitted section titles listed in the note; the rest are only counted
+const MAX_LISTED_TITLES: usize = 20;
+
+/// A heading and the text up to the next one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Section<'a> {
+    /// 1 to 6, or 0 for any text before the first heading
+    pub level: usize,
+    pub title: &'a str,
+    /// The section's Markdown, heading line included
+    pub text: &'a str,
+}
+
+/// Level and title of an ATX heading line, e.g. `## Scheduler`
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.bytes().take_while(|b| *b == b'#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Split Markdown at its headings, ignoring `#` lines inside fenced code blocks
+pub fn sections(markdown: &str) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut current = Section {
+        level: 0,
+        title: "",
+        text: "",
+    };
+    let mut start = 0;
+    let mut offset = 0;
+    let mut fence: Option<&str> = None;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None => {
+                if let Some((level, title)) = heading(line.trim_end()) {
+                    if offset > start {
+                        current.text = &markdown[start..offset];
+                        sections.push(current);
+                    }
+                    current = Section {
+                        level,
+                        title,
+                        text: "",
+                    };
+                    start = offset;
+                }
+            }
+        }
+        offset += line.len();
+    }
+    if offset > start {
+        current.text = &markdown[start..offset];
+        sections.push(current);
+    }
+    sections
+}
+
+/// Cut `text` to at most `max_bytes` at a section boundary and note what was left out, or
+/// `None` when it fits
+///
+/// When not even the first section fits it is cut at a line instead. The note points to
+/// `section_tool`, the name Zed sees `read_wiki_section` under, when that tool can serve the
+/// rest of this response, or else to the size limit of `tool`, the tool's settings key.
+pub fn truncate_text(
+    text: &str,
+    max_bytes: usize,
+    tool: &str,
+    section_tool: Option<&str>,
+) -> Option<String> {
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return None;
+    }
+    let sections = sections(text);
+    let mut kept = 0;
+    let mut whole = 0;
+    for section in &sections {
+        if kept + section.text.len() > max_bytes {
+            break;
+        }
+        kept += section.text.len();
+        whole += 1;
+    }
+    let (shown, omitted) = if whole == 0 {
+        (&text[..line_floor(text, max_bytes)], &sections[1..])
+    } else {
+        (&text[..kept], &sections[whole..])
+    };
+
+    let mut out = shown.trim_end().to_string();
+    let _ = write!(
+        out,
+        "\n\n---\n[Truncated by the bridge to {} of {} bytes: {} of {} sections omitted",
+        shown.len(),
+        text.len(),
+        omitted.len(),
+        sections.len()
+    );
+    if whole == 0 {
+        out.push_str(", and the first one cut short");
+    }
+    // List the outermost omitted sections; their subsections go with them
+    let top = omitted.iter().map(|s| s.level).min().unwrap_or_default();
+    let titles: Vec<&str> = omitted
+        .iter()
+        .filter(|s| s.level == top && !s.title.is_empty())
+        .map(|s| s.title)
+        .collect();
+    if !titles.is_empty() {
+        let listed = titles.len().min(MAX_LISTED_TITLES);
+        let _ = write!(out, ": {}", titles[..listed].join("; "));
+        if titles.len() > listed {
+            let _ = write!(out, "; and {} more", titles.len() - listed);
+        }
+    }
+    match section_tool {
+        Some(section_tool) => {
+            let _ = write!(
+                out,
+                ". The full response is cached: call {section_tool} with the same repoName and \
+                 a section title to read one, or without 'section' to list them.]"
+            );
+        }
+        None => {
+            let _ = write!(
+                out,
+                ". To see them, raise tools.max_response_bytes.{tool} in the bridge settings.]"
+            );
+        }
+    }
+    Some(out)
+}
+
+/// The largest prefix length within `max_bytes` ending at a line break, or at a character
+/// boundary when the first line is longer
+pub fn line_floor(text: &str, max_bytes: usize) -> usize {
+    if let Some(end) = text.as_bytes()[..max_bytes]
+        .iter()
+        .rposition(|b| *b == b'\n')
+    {
+        return end + 1;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    end
+}
+
+/// Truncate a tool result's text to `max_bytes`, keeping any non-text content
+pub fn truncate_result(
+    result: CallToolResult,
+    max_bytes: usize,
+    tool: &str,
+    section_tool: Option<&str>,
+) -> CallToolResult {
+    if result.is_error == Some(true) {
+        return result;
+    }
+    let Some(text) = truncate_text(&result_text(&result), max_bytes, tool, section_tool) else {
+        return result;
+    };
+    let mut content = vec![Content::text(text)];
+    content.extend(
+        result
+            .content
+            .into_iter()
+            .filter(|content| content.as_text().is_none()),
+    );
+    CallToolResult {
+        content,
+        is_error: result.is_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIKI: &str = "Intro line\n\
+        # Page: Overview\nTokio is a runtime.\n\
+        ## Features\n```sh\n# not a heading\n```\n\
+        # Page: Scheduler\nWork stealing.\n\
+        # Page: I/O Driver\nPolls mio.\n";
+
+    #[test]
+    fn test_splits_at_headings_outside_code() {
+        let sections = sections(WIKI);
+        let titles: Vec<_> = sections.iter().map(|s| (s.level, s.title)).collect();
+        assert_eq!(
+            titles,
+            [
+                (0, ""),
+                (1, "Page: Overview"),
+                (2, "Features"),
+                (1, "Page: Scheduler"),
+                (1, "Page: I/O Driver")
+            ]
+        );
+        assert_eq!(
+            sections[2].text,
+            "## Features\n```sh\n# not a heading\n```\n"
+        );
+        assert_eq!(sections.iter().map(|s| s.text).collect::<String>(), WIKI);
+        assert_eq!(heading("#hashtag"), None);
+        assert_eq!(heading("### Closed ###"), Some((3, "Closed")));
+    }
+
+    #[test]
+    fn test_truncates_at_section_boundaries() {
+        let section_tool = Some("read_wiki_section");
+        assert_eq!(
+            truncate_text(WIKI, WIKI.len(), "read_wiki_contents", section_tool),
+            None
+        );
+        assert_eq!(
+            truncate_text(WIKI, 0, "read_wiki_contents", section_tool),
+            None
+        );
+
+        let limit = WIKI.find("# Page: Scheduler").unwrap() + 5;
+        let text = truncate_text(WIKI, limit, "read_wiki_contents", section_tool).unwrap();
+        assert!(text.starts_with("Intro line\n# Page: Overview\n"));
+        assert!(text.contains("# not a heading\n```\n\n---\n[Truncated by the bridge"));
+        assert!(!text.contains("Work stealing"));
+        assert!(text.contains("2 of 5 sections omitted: Page: Scheduler; Page: I/O Driver."));
+        assert!(text.contains("call read_wiki_section with the same repoName"));
+
+        // The note names the section tool as Zed sees it, or the limit when it is hidden
+        let prefixed = Some("deepwiki_read_wiki_section");
+        let text = truncate_text(WIKI, limit, "read_wiki_contents", prefixed).unwrap();
+        assert!(text.contains("call deepwiki_read_wiki_section with the same repoName"));
+        let text = truncate_text(WIKI, limit, "read_wiki_contents", None).unwrap();
+        assert!(!text.contains("read_wiki_section"));
+        assert!(text.ends_with(
+            "raise tools.max_response_bytes.read_wiki_contents in the bridge settings.]"
+        ));
+    }
+
+    #[test]
+    fn test_cuts_an_oversized_first_section_at_a_line() {
+        let text = "# Big\nfirst line\nsecond line\n# Next\nmore\n";
+        let truncated = truncate_text(text, 20, "ask_question", None).unwrap();
+        assert!(truncated.starts_with("# Big\nfirst line\n\n---\n"));
+        assert!(truncated.contains("1 of 2 sections omitted, and the first one cut short: Next."));
+        assert!(truncated
+            .ends_with("raise tools.max_response_bytes.ask_question in the bridge settings.]"));
+        // Never split a character
+        assert_eq!(line_floor("héllo", 2), 1);
+    }
+
+    #[test]
+    fn test_truncates_results_but_not_errors() {
+        let result = CallToolResult::success(vec![Content::text(WIKI)]);
+        let truncated = truncate_result(result, 40, "read_wiki_contents", None);
+        assert_eq!(truncated.content.len(), 1);
+        assert!(result_text(&truncated).contains("[Truncated"));
+
+        let error = CallToolResult::error(vec![Content::text(WIKI)]);
+        assert_eq!(
+            result_text(&truncate_result(error, 40, "read_wiki_contents", None)),
+            WIKI
+        );
+    }
+}
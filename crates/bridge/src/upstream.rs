@@ -0,0 +1,200 @@
+//! The MCP servers behind the bridge
+//!
+//! By default the bridge fronts the single endpoint it was started with. When `upstreams` are
+//! configured it aggregates all of them: each server's tools and prompts are namespaced as
+//! `<upstream>__<name>` so identical names cannot collide, and calls are routed back to the
+//! owning server. Resources keep their URIs and are routed to the server that listed them.
+//! A server that cannot be reached only takes its own items offline.
+
+use crate::settings::UpstreamSettings;
+use crate::RemoteClient;
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+/// Separates the upstream name from a tool or prompt name when aggregating
+pub const NAMESPACE_SEPARATOR: &str = "__";
+
+pub struct Upstream {
+    pub name: String,
+    pub endpoint_url: String,
+    /// `None` when offline or when the server could not be reached at startup
+    pub client: Option<RemoteClient>,
+}
+
+pub struct Upstreams {
+    upstreams: Vec<Upstream>,
+    /// Index of the upstream that listed each resource URI
+    resource_owners: RwLock<HashMap<String, usize>>,
+}
+
+impl Upstreams {
+    pub fn new(upstreams: Vec<Upstream>) -> Self {
+        Self {
+            upstreams,
+            resource_owners: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The bridge's own endpoint, possibly offline
+    pub fn single(endpoint_url: &str, client: Option<RemoteClient>) -> Self {
+        Self::new(vec![Upstream {
+            name: UpstreamSettings::from_url(endpoint_url).name,
+            endpoint_url: endpoint_url.to_string(),
+            client,
+        }])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Upstream> {
+        self.upstreams.iter()
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Upstream> {
+        self.upstreams.iter().find(|upstream| upstream.name == name)
+    }
+
+    /// The only upstream when not aggregating
+    pub fn sole(&self) -> Option<&Upstream> {
+        match self.upstreams.as_slice() {
+            [upstream] => Some(upstream),
+            _ => None,
+        }
+    }
+
+    /// Name under which an upstream's tool or prompt is exposed
+    pub fn qualify(&self, upstream: &Upstream, name: &str) -> String {
+        if self.sole().is_some() {
+            name.to_string()
+        } else {
+            format!("{}{NAMESPACE_SEPARATOR}{}", upstream.name, name)
+        }
+    }
+
+    /// The upstream owning a qualified name, and the name it uses there
+    pub fn resolve<'a>(&self, name: &'a str) -> Option<(&Upstream, &'a str)> {
+        if let Some(upstream) = self.sole() {
+            return Some((upstream, name));
+        }
+        let (upstream, name) = name.split_once(NAMESPACE_SEPARATOR)?;
+        self.named(upstream).map(|upstream| (upstream, name))
+    }
+
+    /// Remember which upstream listed a resource, keeping the first on collisions
+    pub fn record_resource(&self, upstream: &Upstream, uri: &str) {
+        let Some(index) = self.index_of(upstream) else {
+            return;
+        };
+        if let Ok(mut owners) = self.resource_owners.write() {
+            owners.entry(uri.to_string()).or_insert(index);
+        }
+    }
+
+    /// Upstreams to try for a resource: its owner if known, otherwise all of them in order
+    pub fn resource_candidates(&self, uri: &str) -> Vec<&Upstream> {
+        let owner = self
+            .resource_owners
+            .read()
+            .ok()
+            .and_then(|owners| owners.get(uri).copied())
+            .and_then(|index| self.upstreams.get(index));
+        match owner {
+            Some(upstream) => vec![upstream],
+            None => self.upstreams.iter().collect(),
+        }
+    }
+
+    fn index_of(&self, upstream: &Upstream) -> Option<usize> {
+        self.upstreams
+            .iter()
+            .position(|candidate| std::ptr::eq(candidate, upstream))
+    }
+}
+
+/// Check upstream names are usable as namespaces and unique
+pub fn validate_upstreams(upstreams: &[UpstreamSettings]) -> Result<()> {
+    let mut names = HashSet::new();
+    for upstream in upstreams {
+        let valid = !upstream.name.is_empty()
+            && !upstream.name.contains(NAMESPACE_SEPARATOR)
+            && !upstream.name.ends_with('_')
+            && upstream
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(anyhow!(
+                "Invalid upstream name '{}': use letters, digits, '-' and single '_'",
+                upstream.name
+            ));
+        }
+        if !names.insert(upstream.name.as_str()) {
+            return Err(anyhow!("Duplicate upstream name '{}'", upstream.name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offline(name: &str) -> Upstream {
+        Upstream {
+            name: name.to_string(),
+            endpoint_url: format!("https://{name}.example.com/mcp"),
+            client: None,
+        }
+    }
+
+    #[test]
+    fn test_single_upstream_is_not_namespaced() {
+        let upstreams = Upstreams::single("https://mcp.deepwiki.com/mcp", None);
+        let upstream = upstreams.sole().unwrap();
+        assert_eq!(upstreams.qualify(upstream, "ask_question"), "ask_question");
+        let (resolved, name) = upstreams.resolve("ask_question").unwrap();
+        assert_eq!(resolved.endpoint_url, "https://mcp.deepwiki.com/mcp");
+        assert_eq!(name, "ask_question");
+    }
+
+    #[test]
+    fn test_aggregated_names_route_to_their_upstream() {
+        let upstreams = Upstreams::new(vec![offline("deepwiki"), offline("devin")]);
+        let devin = upstreams.iter().nth(1).unwrap();
+        assert_eq!(
+            upstreams.qualify(devin, "ask_question"),
+            "devin__ask_question"
+        );
+
+        let (resolved, name) = upstreams.resolve("devin__ask_question").unwrap();
+        assert_eq!(resolved.name, "devin");
+        assert_eq!(name, "ask_question");
+        assert!(upstreams.resolve("ask_question").is_none());
+        assert!(upstreams.resolve("docs__ask_question").is_none());
+    }
+
+    #[test]
+    fn test_resources_route_to_the_first_owner() {
+        let upstreams = Upstreams::new(vec![offline("a"), offline("b")]);
+        assert_eq!(upstreams.resource_candidates("docs://x").len(), 2);
+
+        upstreams.record_resource(upstreams.iter().nth(1).unwrap(), "docs://x");
+        upstreams.record_resource(upstreams.iter().next().unwrap(), "docs://x");
+        let candidates = upstreams.resource_candidates("docs://x");
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].name, "b");
+    }
+
+    #[test]
+    fn test_validate_upstream_names() {
+        let upstream = |name: &str| UpstreamSettings {
+            name: name.to_string(),
+            ..UpstreamSettings::from_url("https://a.example.com")
+        };
+        assert!(validate_upstreams(&[upstream("deepwiki"), upstream("devin-private")]).is_ok());
+        assert!(validate_upstreams(&[upstream("a"), upstream("a")]).is_err());
+        assert!(validate_upstreams(&[upstream("a__b")]).is_err());
+        assert!(validate_upstreams(&[upstream("a_")]).is_err());
+        assert!(validate_upstreams(&[upstream("")]).is_err());
+        assert!(validate_upstreams(&[upstream("a.b")]).is_err());
+    }
+}
@@ -23,6 +23,21 @@ fn default_endpoint() -> String {
     "https://mcp.deepwiki.com".to_string()
 }
 
+/// Environment variable through which the bridge receives the full settings object
+const BRIDGE_SETTINGS_ENV_VAR: &str = "DEEPWIKI_BRIDGE_SETTINGS";
+
+/// Forward the raw user settings to the bridge, which reads the options it understands
+fn bridge_env(settings: Option<&serde_json::Value>) -> Vec<(String, String)> {
+    settings
+        .map(|value| {
+            vec![(
+                BRIDGE_SETTINGS_ENV_VAR.to_string(),
+                serde_json::to_string(value).unwrap_or_default(),
+            )]
+        })
+        .unwrap_or_default()
+}
+
 impl zed::Extension for DeepWikiMcpExtension {
     fn new() -> Self {
         Self
@@ -38,6 +53,7 @@ impl zed::Extension for DeepWikiMcpExtension {
 
         // Get user settings or use defaults
         let settings = ContextServerSettings::for_project("deepwiki-mcp-server", project)?;
+        let env = bridge_env(settings.settings.as_ref());
 
         let config = settings.settings.map_or_else(
             || DeepWikiContextServerSettings {
@@ -57,7 +73,7 @@ impl zed::Extension for DeepWikiMcpExtension {
         Ok(Command {
             command: bridge_path,
             args: vec![config.endpoint],
-            env: vec![],
+            env,
         })
     }
 
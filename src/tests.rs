@@ -12,7 +12,9 @@ const MOCK_ENDPOINT: &str = "https://test.example.com";
 #[cfg(test)]
 mod unit_tests {
     use super::*;
-    use crate::{default_endpoint, DeepWikiContextServerSettings, DeepWikiMcpExtension};
+    use crate::{
+        bridge_env, default_endpoint, DeepWikiContextServerSettings, DeepWikiMcpExtension,
+    };
     use serde_json::json;
     use zed_extension_api::Extension;
 
@@ -330,6 +332,21 @@ mod unit_tests {
         assert_eq!(env_vars.len(), 0); // No environment variables needed
     }
 
+    #[test]
+    fn test_bridge_env_forwards_settings() {
+        assert!(bridge_env(None).is_empty());
+
+        let settings = json!({
+            "endpoint": MOCK_ENDPOINT,
+            "cache": {"mode": "no-store"}
+        });
+        let env = bridge_env(Some(&settings));
+        assert_eq!(env.len(), 1);
+        assert_eq!(env[0].0, "DEEPWIKI_BRIDGE_SETTINGS");
+        let forwarded: serde_json::Value = serde_json::from_str(&env[0].1).unwrap();
+        assert_eq!(forwarded, settings);
+    }
+
     #[test]
     fn test_devin_endpoint_detection() {
         // Test that we can identify Devin endpoints